@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS messages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    node_id TEXT NOT NULL, -- Sender of the message the node_id
+    topic_id TEXT NOT NULL, -- Topic in which the message is sent
+    content TEXT NOT NULL,
+    sent_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_messages_topic_sent_at ON messages (topic_id, sent_at);
@@ -0,0 +1,3 @@
+-- Add migration script here
+-- Synced chat history can arrive more than once, a message is identified by its sender, topic, time and content
+CREATE UNIQUE INDEX IF NOT EXISTS idx_messages_identity ON messages (topic_id, node_id, sent_at, content);
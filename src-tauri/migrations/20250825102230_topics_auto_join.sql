@@ -0,0 +1,3 @@
+-- Add migration script here
+-- Topics flagged with auto_join are subscribed again when the app starts
+ALTER TABLE topics ADD COLUMN auto_join INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,6 @@
+-- Add migration script here
+-- Node wide settings, values are stored as JSON
+CREATE TABLE IF NOT EXISTS settings (
+    key TEXT PRIMARY KEY NOT NULL,
+    value TEXT NOT NULL
+);
@@ -0,0 +1,26 @@
+-- Invitation tickets this node issued
+CREATE TABLE IF NOT EXISTS issued_tickets (
+    nonce TEXT PRIMARY KEY NOT NULL,
+    topic_id TEXT NOT NULL,
+    expires_at INTEGER,
+    max_uses INTEGER,
+    created_at INTEGER NOT NULL
+);
+
+-- Newcomers this node admitted with a ticket, counted against its max uses
+CREATE TABLE IF NOT EXISTS ticket_redemptions (
+    nonce TEXT NOT NULL,
+    node_id TEXT NOT NULL,
+    redeemed_at INTEGER NOT NULL,
+    PRIMARY KEY (nonce, node_id)
+);
+
+-- Tickets revoked by the member that issued them
+CREATE TABLE IF NOT EXISTS revoked_tickets (
+    nonce TEXT NOT NULL,
+    issuer TEXT NOT NULL,
+    PRIMARY KEY (nonce, issuer)
+);
+
+-- The grant of the ticket this node joined the topic with, as JSON
+ALTER TABLE topics ADD COLUMN join_grant TEXT;
@@ -0,0 +1,30 @@
+-- Nodes the topic owner banned, their gossip is ignored
+CREATE TABLE IF NOT EXISTS topic_bans (
+    topic_id TEXT NOT NULL,
+    node_id TEXT NOT NULL,
+    banned_at INTEGER NOT NULL,
+    PRIMARY KEY (topic_id, node_id)
+);
+
+-- Newcomers with a valid ticket waiting for the owner, status is 'pending' or 'denied'
+CREATE TABLE IF NOT EXISTS join_requests (
+    topic_id TEXT NOT NULL,
+    node_id TEXT NOT NULL,
+    email TEXT NOT NULL,
+    first_name TEXT NOT NULL,
+    last_name TEXT,
+    status TEXT NOT NULL DEFAULT 'pending',
+    requested_at INTEGER NOT NULL,
+    PRIMARY KEY (topic_id, node_id)
+);
+
+-- Tickets removed members joined with, they need a new one to come back
+CREATE TABLE IF NOT EXISTS spent_tickets (
+    topic_id TEXT NOT NULL,
+    node_id TEXT NOT NULL,
+    nonce TEXT NOT NULL,
+    PRIMARY KEY (topic_id, node_id, nonce)
+);
+
+-- Newcomers need the owner's approval on top of a valid ticket
+ALTER TABLE topics ADD COLUMN require_approval INTEGER NOT NULL DEFAULT 0;
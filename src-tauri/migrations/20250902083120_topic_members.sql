@@ -0,0 +1,7 @@
+-- Roles of topic members, the owner is the one in topics.owner and members without a row are plain members
+CREATE TABLE IF NOT EXISTS topic_members (
+    topic_id TEXT NOT NULL,
+    node_id TEXT NOT NULL,
+    role TEXT NOT NULL,
+    PRIMARY KEY (topic_id, node_id)
+);
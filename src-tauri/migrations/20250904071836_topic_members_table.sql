@@ -0,0 +1,46 @@
+-- One row per topic member, the owner included, replacing the comma-joined topics.members column.
+-- Roles already stored are kept, also for nodes missing from topics.members, and everyone else in topics.members
+-- becomes a plain member
+CREATE TABLE topic_members_new (
+    topic_id TEXT NOT NULL,
+    node_id TEXT NOT NULL,
+    joined_at INTEGER NOT NULL,
+    last_seen INTEGER,
+    role TEXT NOT NULL DEFAULT 'member',
+    PRIMARY KEY (topic_id, node_id)
+);
+
+INSERT INTO topic_members_new (topic_id, node_id, joined_at, role)
+SELECT topic_id, owner, CAST(strftime('%s', 'now') AS INTEGER), 'owner'
+FROM topics;
+
+INSERT OR IGNORE INTO topic_members_new (topic_id, node_id, joined_at, role)
+WITH RECURSIVE split (topic_id, node_id, rest) AS (
+    SELECT topic_id, '', members || ','
+    FROM topics
+    WHERE members IS NOT NULL
+    UNION ALL
+    SELECT topic_id, substr(rest, 1, instr(rest, ',') - 1), substr(rest, instr(rest, ',') + 1)
+    FROM split
+    WHERE rest != ''
+)
+SELECT
+    s.topic_id,
+    s.node_id,
+    CAST(strftime('%s', 'now') AS INTEGER),
+    COALESCE(
+        (SELECT m.role FROM topic_members m WHERE m.topic_id = s.topic_id AND m.node_id = s.node_id),
+        'member'
+    )
+FROM split s
+WHERE s.node_id != '';
+
+INSERT OR IGNORE INTO topic_members_new (topic_id, node_id, joined_at, role)
+SELECT m.topic_id, m.node_id, CAST(strftime('%s', 'now') AS INTEGER), m.role
+FROM topic_members m
+JOIN topics t ON t.topic_id = m.topic_id;
+
+DROP TABLE topic_members;
+ALTER TABLE topic_members_new RENAME TO topic_members;
+
+ALTER TABLE topics DROP COLUMN members;
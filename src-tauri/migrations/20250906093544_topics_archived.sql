@@ -0,0 +1,2 @@
+-- Archived topics are hidden from the topic list, their members, files and chat history are kept
+ALTER TABLE topics ADD COLUMN archived INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,9 @@
+-- Downloads that are queued, running, paused or failed, in the order they were asked for.
+-- Finished and cancelled downloads are removed, the progress is in files.status
+CREATE TABLE IF NOT EXISTS downloads (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    file_id INTEGER NOT NULL UNIQUE, -- References files.id
+    target_path TEXT NOT NULL, -- Where the file is exported to, a free name next to it is used if it is taken
+    error TEXT, -- Why the last attempt failed
+    queued_at INTEGER NOT NULL
+);
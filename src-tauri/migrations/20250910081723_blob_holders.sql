@@ -0,0 +1,9 @@
+-- Members that announced in their check-ins that they downloaded a file of the topic.
+-- Downloads fetch the file from them as well as from the member that shared it
+CREATE TABLE IF NOT EXISTS blob_holders (
+    topic_id TEXT NOT NULL,
+    hash TEXT NOT NULL,
+    node_id TEXT NOT NULL,
+    announced_at INTEGER NOT NULL,
+    PRIMARY KEY (topic_id, hash, node_id)
+);
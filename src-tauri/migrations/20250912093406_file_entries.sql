@@ -0,0 +1,12 @@
+-- The files of a shared directory, which is shared as one collection in files.
+-- The node that shared it knows them right away, the others when they fetch the collection's names
+CREATE TABLE IF NOT EXISTS file_entries (
+    file_id INTEGER NOT NULL, -- References files.id of the collection
+    position INTEGER NOT NULL, -- Index of the entry in the collection
+    path TEXT NOT NULL, -- Relative to the shared directory, with / between the components
+    hash TEXT NOT NULL,
+    size INTEGER, -- Unknown until the entry is downloaded, except on the node that shared it
+    absolute_path TEXT, -- Where the entry is on this node
+    selected INTEGER NOT NULL DEFAULT 1, -- Whether downloading the collection fetches the entry
+    PRIMARY KEY (file_id, position)
+);
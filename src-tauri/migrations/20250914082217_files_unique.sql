@@ -0,0 +1,11 @@
+-- A file is known once per topic and sharer, the same content can be in several topics.
+-- Earlier versions kept one entry per hash, and could keep a file shared twice in a topic twice
+DELETE FROM downloads WHERE file_id IN (
+    SELECT id FROM files WHERE id NOT IN (SELECT MIN(id) FROM files GROUP BY topic_id, hash, node_id)
+);
+DELETE FROM file_entries WHERE file_id IN (
+    SELECT id FROM files WHERE id NOT IN (SELECT MIN(id) FROM files GROUP BY topic_id, hash, node_id)
+);
+DELETE FROM files WHERE id NOT IN (SELECT MIN(id) FROM files GROUP BY topic_id, hash, node_id);
+
+CREATE UNIQUE INDEX IF NOT EXISTS files_topic_hash_node ON files (topic_id, hash, node_id);
@@ -0,0 +1,2 @@
+-- The grant a queued newcomer presented, as JSON. Its ticket is only redeemed once the request is approved
+ALTER TABLE join_requests ADD COLUMN join_grant TEXT;
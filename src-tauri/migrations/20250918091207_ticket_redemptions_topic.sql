@@ -0,0 +1,5 @@
+-- The topic a redemption was for. Other members than the issuer have no issued ticket to tell it from
+ALTER TABLE ticket_redemptions ADD COLUMN topic_id TEXT;
+
+UPDATE ticket_redemptions
+SET topic_id = (SELECT topic_id FROM issued_tickets WHERE issued_tickets.nonce = ticket_redemptions.nonce);
@@ -0,0 +1,2 @@
+-- The sequence number of the last moderation applied to the topic, older ones that come in again are ignored
+ALTER TABLE topics ADD COLUMN moderation_seq INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+-- What the file was before it was queued, a cancelled download goes back to it
+ALTER TABLE downloads ADD COLUMN previous_status TEXT NOT NULL DEFAULT 'Shared';
@@ -0,0 +1,629 @@
+// Headless CrewCast peer. Uses the same database schema and blob store as the desktop app,
+// so it can keep topics joined and files seeded on a machine without a webview.
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use anyhow::anyhow;
+use clap::{Parser, Subcommand};
+use crewcast_lib::{
+	comm::{
+		endpoint::NetworkConfig,
+		events::{DownloadProgress, Event, MemorySink, TopicStatus},
+	},
+	database::{file::File, member::Role},
+	error::Result,
+	AppState,
+};
+use tokio::sync::broadcast;
+
+#[derive(Parser)]
+#[command(name = "crewcast-cli", about = "Headless CrewCast peer")]
+struct Cli {
+	/// Directory holding data.db and the blob store, defaults to the desktop app's data directory
+	#[arg(long, env = "CREWCAST_DATA_DIR")]
+	data_dir: Option<PathBuf>,
+
+	/// Passphrase the database is encrypted with, set on first use
+	#[arg(long, env = "DATABASE_PASSWORD", hide_env_values = true)]
+	password: String,
+
+	#[command(subcommand)]
+	command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+	/// Create the user profile other members see for this node
+	Init {
+		#[arg(long)]
+		email: String,
+		#[arg(long)]
+		first_name: String,
+		#[arg(long)]
+		last_name: Option<String>,
+	},
+	/// Manage topics
+	#[command(subcommand)]
+	Topic(TopicCommand),
+	/// Issue, list and revoke invitation tickets
+	#[command(subcommand)]
+	Ticket(TicketCommand),
+	/// Manage the members of a topic, mostly open to its owner and admins
+	#[command(subcommand)]
+	Member(MemberCommand),
+	/// Share a file, or a directory with everything in it, in a topic
+	Share {
+		topic_id: String,
+		path: PathBuf,
+		/// Seconds to wait for other members before broadcasting
+		#[arg(long, default_value_t = 15)]
+		wait: u64,
+	},
+	/// Download a file shared in a topic into the download directory
+	Download {
+		topic_id: String,
+		hash: String,
+		/// The member that shared it, needed when several members shared the same content
+		#[arg(long)]
+		sharer: Option<String>,
+		/// Save it to this file or directory instead
+		#[arg(long)]
+		to: Option<PathBuf>,
+		/// Only download this file of a shared directory, by its path in the directory. Can be repeated
+		#[arg(long = "entry")]
+		entries: Vec<String>,
+	},
+	/// List, pause, resume or cancel queued downloads
+	#[command(subcommand)]
+	Downloads(DownloadCommand),
+	/// Print the download directory, or change it
+	DownloadDir { dir: Option<String> },
+	/// Send a chat message to a topic
+	Chat {
+		topic_id: String,
+		message: String,
+		/// Seconds to wait for other members before broadcasting
+		#[arg(long, default_value_t = 15)]
+		wait: u64,
+	},
+	/// Join every auto-join topic and serve until interrupted, printing events
+	Listen,
+	/// Show or change how this node reaches other nodes
+	#[command(subcommand)]
+	Network(NetworkCommand),
+	/// Move this node's identity to or from another device
+	#[command(subcommand)]
+	Identity(IdentityCommand),
+	/// Re-encrypt the database with a new passphrase
+	ChangePassphrase {
+		#[arg(long, env = "NEW_DATABASE_PASSWORD", hide_env_values = true)]
+		new_password: String,
+	},
+}
+
+#[derive(Subcommand)]
+enum DownloadCommand {
+	/// List queued, running, paused and failed downloads
+	List,
+	/// Stop a download until it is resumed
+	Pause { file_id: i64 },
+	/// Queue a paused or failed download again and wait for it to finish
+	Resume { file_id: i64 },
+	/// Take a download out of the queue
+	Cancel { file_id: i64 },
+}
+
+#[derive(Subcommand)]
+enum NetworkCommand {
+	/// Print the stored network configuration
+	Show,
+	/// Use n0 discovery and relays
+	Default,
+	/// Only reach nodes on the local network, without relays
+	LanOnly,
+	/// Use self-hosted relays
+	Relays {
+		#[arg(required = true)]
+		urls: Vec<String>,
+	},
+}
+
+#[derive(Subcommand)]
+enum IdentityCommand {
+	/// Write the node key, profile, topics and file catalog to an encrypted bundle
+	Export {
+		path: PathBuf,
+		#[arg(long, env = "BUNDLE_PASSWORD", hide_env_values = true)]
+		bundle_password: String,
+	},
+	/// Take over the identity stored in a bundle
+	Import {
+		path: PathBuf,
+		#[arg(long, env = "BUNDLE_PASSWORD", hide_env_values = true)]
+		bundle_password: String,
+	},
+}
+
+#[derive(Subcommand)]
+enum TicketCommand {
+	/// Print a new `name:ticket` invitation for a topic
+	Create {
+		topic_id: String,
+		/// Seconds until the ticket expires
+		#[arg(long)]
+		expires_in: Option<i64>,
+		/// Newcomers that can join with the ticket
+		#[arg(long)]
+		max_uses: Option<u32>,
+	},
+	/// List the tickets this node issued
+	List { topic_id: Option<String> },
+	/// Stop members from admitting newcomers with a ticket
+	Revoke { nonce: String },
+}
+
+#[derive(Subcommand)]
+enum MemberCommand {
+	/// Remove a member, it needs a new ticket to come back
+	Remove {
+		topic_id: String,
+		node_id: String,
+		/// Seconds to wait for other members before broadcasting
+		#[arg(long, default_value_t = 15)]
+		wait: u64,
+	},
+	/// Remove a member and ignore its gossip
+	Ban {
+		topic_id: String,
+		node_id: String,
+		#[arg(long, default_value_t = 15)]
+		wait: u64,
+	},
+	/// Lift a ban
+	Unban {
+		topic_id: String,
+		node_id: String,
+		#[arg(long, default_value_t = 15)]
+		wait: u64,
+	},
+	/// List banned nodes
+	Banned { topic_id: String },
+	/// Require approval for newcomers, or stop requiring it with --off
+	RequireApproval {
+		topic_id: String,
+		#[arg(long)]
+		off: bool,
+		#[arg(long, default_value_t = 15)]
+		wait: u64,
+	},
+	/// List newcomers waiting for approval
+	Requests { topic_id: String },
+	/// Admit a newcomer waiting for approval
+	Approve {
+		topic_id: String,
+		node_id: String,
+		#[arg(long, default_value_t = 15)]
+		wait: u64,
+	},
+	/// Turn away a newcomer waiting for approval
+	Deny { topic_id: String, node_id: String },
+	/// List members with their roles and when they were last seen
+	List { topic_id: String },
+	/// Make a member an admin, a member or read-only
+	Role {
+		topic_id: String,
+		node_id: String,
+		/// admin, member or read_only
+		role: Role,
+		#[arg(long, default_value_t = 15)]
+		wait: u64,
+	},
+	/// Hand the topic over to another member
+	Transfer {
+		topic_id: String,
+		node_id: String,
+		#[arg(long, default_value_t = 15)]
+		wait: u64,
+	},
+}
+
+#[derive(Subcommand)]
+enum TopicCommand {
+	/// Create a topic and print its invitation ticket
+	Create { name: String },
+	/// Join a topic with a `name:ticket` invitation
+	Join { ticket: String },
+	/// Leave a topic and tell its members, it is not joined when listening anymore. With --permanent it is left for good
+	Leave {
+		topic_id: String,
+		#[arg(long)]
+		permanent: bool,
+		#[arg(long, default_value_t = 15)]
+		wait: u64,
+	},
+	/// List known topics, or the archived ones with --archived
+	List {
+		#[arg(long)]
+		archived: bool,
+	},
+	/// Hide a topic from the list, keeping its files and chat history
+	Archive { topic_id: String },
+	/// Bring an archived topic back to the list
+	Unarchive { topic_id: String },
+	/// Delete a topic with its files and chat history from this node
+	Delete { topic_id: String },
+	/// Join a topic and print its direct gossip neighbors
+	Connectivity {
+		topic_id: String,
+		#[arg(long, default_value_t = 15)]
+		wait: u64,
+	},
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+	let cli = Cli::parse();
+	let data_dir = cli.data_dir.unwrap_or_else(default_data_dir);
+	if AppState::is_unencrypted(&data_dir)? {
+		eprintln!(
+			"Encrypting the database in {} with the given passphrase",
+			data_dir.display()
+		);
+	}
+
+	// The commands decide which events to wait for or print
+	let sink = MemorySink::default();
+	let mut events = sink.subscribe();
+	let mut state = AppState::init(data_dir, cli.password.clone(), Arc::new(sink)).await?;
+
+	let result = match cli.command {
+		Command::Init {
+			email,
+			first_name,
+			last_name,
+		} => {
+			let node_id = state.node_id();
+			state
+				.create_user(email, first_name, last_name, node_id.clone())
+				.await
+				.map(|_| println!("Node {} is ready", node_id))
+		},
+		Command::Topic(TopicCommand::Create { name }) => {
+			state.start_new_topic(name).await.map(|ticket| println!("{}", ticket))
+		},
+		Command::Topic(TopicCommand::Join { ticket }) => state
+			.join_topic_with_ticket(&ticket)
+			.await
+			.map(|topic| println!("Joined {} ({})", topic.name, topic.topic_id)),
+		Command::Topic(TopicCommand::Leave {
+			topic_id,
+			permanent,
+			wait,
+		}) => {
+			join_and_wait(&mut state, &mut events, &topic_id, wait).await?;
+			let result = state.leave_topic(topic_id, permanent).await;
+			tokio::time::sleep(Duration::from_secs(1)).await;
+			result
+		},
+		Command::Topic(TopicCommand::List { archived }) => {
+			let topics = if archived {
+				state.list_archived_topics().await
+			} else {
+				state.list_topics().await
+			};
+			topics.map(|topics| {
+				for topic in topics {
+					let auto_join = if topic.auto_join { "auto-join" } else { "" };
+					println!("{}\t{}\t{}\t{}", topic.id, topic.topic_id, topic.name, auto_join);
+				}
+			})
+		},
+		Command::Topic(TopicCommand::Archive { topic_id }) => state.archive_topic(topic_id).await.map(|_| ()),
+		Command::Topic(TopicCommand::Unarchive { topic_id }) => state.unarchive_topic(topic_id).await.map(|_| ()),
+		Command::Topic(TopicCommand::Delete { topic_id }) => state.delete_topic(topic_id).await,
+		Command::Topic(TopicCommand::Connectivity { topic_id, wait }) => {
+			join_and_wait(&mut state, &mut events, &topic_id, wait).await?;
+			state.topic_connectivity(topic_id).await.map(|connectivity| {
+				for neighbor in connectivity.neighbors {
+					println!("{}\t{}", neighbor.node_id, neighbor.since);
+				}
+				if connectivity.partitioned {
+					eprintln!("Lost every neighbor, waiting for one to come back");
+				}
+				if connectivity.lagged > 0 {
+					eprintln!("Fell behind {} times", connectivity.lagged);
+				}
+			})
+		},
+		Command::Ticket(TicketCommand::Create {
+			topic_id,
+			expires_in,
+			max_uses,
+		}) => state
+			.get_ticket_for_topic(topic_id, expires_in, max_uses)
+			.await
+			.map(|ticket| println!("{}", ticket)),
+		Command::Ticket(TicketCommand::List { topic_id }) => state.list_issued_tickets(topic_id).await.map(|tickets| {
+			for ticket in tickets {
+				let expires_at = ticket.expires_at.map(|ts| ts.to_string()).unwrap_or_default();
+				let uses = match ticket.max_uses {
+					Some(max_uses) => format!("{}/{}", ticket.uses, max_uses),
+					None => ticket.uses.to_string(),
+				};
+				let revoked = if ticket.revoked { "revoked" } else { "" };
+				println!(
+					"{}\t{}\t{}\t{}\t{}",
+					ticket.nonce, ticket.topic_id, expires_at, uses, revoked
+				);
+			}
+		}),
+		Command::Ticket(TicketCommand::Revoke { nonce }) => state.revoke_ticket(nonce).await,
+		Command::Member(MemberCommand::Remove {
+			topic_id,
+			node_id,
+			wait,
+		}) => {
+			join_and_wait(&mut state, &mut events, &topic_id, wait).await?;
+			let result = state.remove_member(topic_id, node_id).await;
+			tokio::time::sleep(Duration::from_secs(1)).await;
+			result
+		},
+		Command::Member(MemberCommand::Ban {
+			topic_id,
+			node_id,
+			wait,
+		}) => {
+			join_and_wait(&mut state, &mut events, &topic_id, wait).await?;
+			let result = state.ban_member(topic_id, node_id).await;
+			tokio::time::sleep(Duration::from_secs(1)).await;
+			result
+		},
+		Command::Member(MemberCommand::Unban {
+			topic_id,
+			node_id,
+			wait,
+		}) => {
+			join_and_wait(&mut state, &mut events, &topic_id, wait).await?;
+			let result = state.unban_member(topic_id, node_id).await;
+			tokio::time::sleep(Duration::from_secs(1)).await;
+			result
+		},
+		Command::Member(MemberCommand::Banned { topic_id }) => {
+			state.list_banned_members(topic_id).await.map(|banned| {
+				for node_id in banned {
+					println!("{}", node_id);
+				}
+			})
+		},
+		Command::Member(MemberCommand::RequireApproval { topic_id, off, wait }) => {
+			join_and_wait(&mut state, &mut events, &topic_id, wait).await?;
+			let result = state.set_require_approval(topic_id, !off).await;
+			tokio::time::sleep(Duration::from_secs(1)).await;
+			result
+		},
+		Command::Member(MemberCommand::Requests { topic_id }) => {
+			state.list_join_requests(topic_id).await.map(|requests| {
+				for request in requests {
+					let name = match request.last_name {
+						Some(last_name) => format!("{} {}", request.first_name, last_name),
+						None => request.first_name,
+					};
+					println!("{}\t{}\t{}", request.node_id, name, request.email);
+				}
+			})
+		},
+		Command::Member(MemberCommand::Approve {
+			topic_id,
+			node_id,
+			wait,
+		}) => {
+			join_and_wait(&mut state, &mut events, &topic_id, wait).await?;
+			let result = state.approve_join(topic_id, node_id).await;
+			tokio::time::sleep(Duration::from_secs(1)).await;
+			result
+		},
+		Command::Member(MemberCommand::Deny { topic_id, node_id }) => state.deny_join(topic_id, node_id).await,
+		Command::Member(MemberCommand::List { topic_id }) => state.list_members(topic_id).await.map(|members| {
+			for member in members {
+				let name = [member.first_name, member.last_name]
+					.into_iter()
+					.flatten()
+					.collect::<Vec<_>>();
+				let presence = match member.last_seen {
+					_ if member.is_active => "online".to_string(),
+					Some(last_seen) => format!("last seen {}", last_seen),
+					None => "never seen".to_string(),
+				};
+				println!("{}\t{}\t{}\t{}", member.node_id, member.role, name.join(" "), presence);
+			}
+		}),
+		Command::Member(MemberCommand::Role {
+			topic_id,
+			node_id,
+			role,
+			wait,
+		}) => {
+			join_and_wait(&mut state, &mut events, &topic_id, wait).await?;
+			let result = state.set_member_role(topic_id, node_id, role).await;
+			tokio::time::sleep(Duration::from_secs(1)).await;
+			result
+		},
+		Command::Member(MemberCommand::Transfer {
+			topic_id,
+			node_id,
+			wait,
+		}) => {
+			join_and_wait(&mut state, &mut events, &topic_id, wait).await?;
+			let result = state.transfer_ownership(topic_id, node_id).await;
+			tokio::time::sleep(Duration::from_secs(1)).await;
+			result
+		},
+		Command::Share { topic_id, path, wait } => {
+			join_and_wait(&mut state, &mut events, &topic_id, wait).await?;
+			let result = state.share_file(topic_id, path.to_string_lossy().to_string()).await;
+			// Give gossip a moment to flush before the endpoint closes
+			tokio::time::sleep(Duration::from_secs(1)).await;
+			result
+		},
+		Command::Download {
+			topic_id,
+			hash,
+			sharer,
+			to,
+			entries,
+		} => {
+			let file = find_shared_file(&state, topic_id, &hash, sharer.as_deref()).await?;
+			let download = if entries.is_empty() {
+				state.download_file(file, to).await?
+			} else {
+				state.download_file_entries(file, entries, to).await?
+			};
+			wait_for_download(&mut events, download.file_id).await
+		},
+		Command::Downloads(DownloadCommand::List) => state.list_downloads().await.map(|downloads| {
+			for download in downloads {
+				let error = download.error.unwrap_or_default();
+				println!(
+					"{}\t{:?}\t{}\t{}\t{}",
+					download.file_id, download.status, download.name, download.target_path, error
+				);
+			}
+		}),
+		Command::Downloads(DownloadCommand::Pause { file_id }) => state.pause_download(file_id).await,
+		Command::Downloads(DownloadCommand::Resume { file_id }) => {
+			state.resume_download(file_id).await?;
+			wait_for_download(&mut events, file_id).await
+		},
+		Command::Downloads(DownloadCommand::Cancel { file_id }) => state.cancel_download(file_id).await,
+		Command::DownloadDir { dir: None } => state.get_download_dir().await.map(|dir| println!("{}", dir.display())),
+		Command::DownloadDir { dir: Some(dir) } => state
+			.set_download_dir(dir)
+			.await
+			.map(|dir| println!("{}", dir.display())),
+		Command::Chat {
+			topic_id,
+			message,
+			wait,
+		} => {
+			join_and_wait(&mut state, &mut events, &topic_id, wait).await?;
+			let result = state.send_message(topic_id, message).await;
+			tokio::time::sleep(Duration::from_secs(1)).await;
+			result
+		},
+		Command::Network(NetworkCommand::Show) => match state.get_network_config().await {
+			Ok(config) => serde_json::to_string(&config)
+				.map(|config| println!("{}", config))
+				.map_err(Into::into),
+			Err(e) => Err(e),
+		},
+		Command::Network(NetworkCommand::Default) => set_network(&mut state, NetworkConfig::Default).await,
+		Command::Network(NetworkCommand::LanOnly) => set_network(&mut state, NetworkConfig::LanOnly).await,
+		Command::Network(NetworkCommand::Relays { urls }) => {
+			set_network(&mut state, NetworkConfig::CustomRelays { relay_urls: urls }).await
+		},
+		Command::Identity(IdentityCommand::Export { path, bundle_password }) => {
+			state.export_identity(path, bundle_password).await
+		},
+		Command::Identity(IdentityCommand::Import { path, bundle_password }) => state
+			.import_identity(path, bundle_password)
+			.await
+			.map(|_| println!("Node {} is ready", state.node_id())),
+		Command::ChangePassphrase { new_password } => state.change_passphrase(cli.password, new_password).await,
+		Command::Listen => {
+			state.rejoin_auto_join_topics().await;
+			println!("Listening as {}, press Ctrl-C to stop", state.node_id());
+			loop {
+				tokio::select! {
+					_ = tokio::signal::ctrl_c() => break,
+					event = events.recv() => match event {
+						Ok(event) => println!("{}: {}", event.name(), serde_json::to_string(&event)?),
+						Err(broadcast::error::RecvError::Lagged(skipped)) => eprintln!("Skipped {} events", skipped),
+						Err(broadcast::error::RecvError::Closed) => break,
+					},
+				}
+			}
+			Ok(())
+		},
+	};
+
+	state.close().await?;
+	result
+}
+
+// Joins a topic and waits until at least one other member is reachable.
+// Messages are stored locally either way, and reach the others through check-in sync later.
+async fn join_and_wait(
+	state: &mut AppState,
+	events: &mut broadcast::Receiver<Event>,
+	topic_id: &str,
+	wait: u64,
+) -> Result<()> {
+	// A one-off command does not change whether the topic is joined when listening
+	state.open_topic(topic_id).await?;
+
+	let online = async {
+		loop {
+			match events.recv().await {
+				Ok(Event::TopicStatus(TopicStatus::Online { topic_id: online })) if online == topic_id => return true,
+				Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => (),
+				Err(broadcast::error::RecvError::Closed) => return false,
+			}
+		}
+	};
+	if !matches!(tokio::time::timeout(Duration::from_secs(wait), online).await, Ok(true)) {
+		eprintln!("No other member reachable yet, it will be synced when they check in");
+	}
+	Ok(())
+}
+
+// The same content can be shared by several members of a topic, each of them has an entry of their own
+async fn find_shared_file(state: &AppState, topic_id: String, hash: &str, sharer: Option<&str>) -> Result<File> {
+	let mut files: Vec<File> = state
+		.list_files(topic_id)
+		.await?
+		.into_iter()
+		.filter(|file| file.hash == hash && sharer.is_none_or(|sharer| file.node_id == sharer))
+		.collect();
+	if files.len() > 1 {
+		let sharers: Vec<_> = files.iter().map(|file| file.node_id.as_str()).collect();
+		return Err(anyhow!("Shared by {}, pick one with --sharer", sharers.join(", ")).into());
+	}
+	files
+		.pop()
+		.ok_or_else(|| anyhow!("No file with hash {} in this topic", hash).into())
+}
+
+// Downloads run in the background, the command waits for the one it queued
+async fn wait_for_download(events: &mut broadcast::Receiver<Event>, file_id: i64) -> Result<()> {
+	loop {
+		match events.recv().await {
+			Ok(Event::DownloadProgress(DownloadProgress::Complete {
+				file_id: done, path, ..
+			})) if done == file_id => {
+				println!("Saved to {}", path);
+				return Ok(());
+			},
+			Ok(Event::DownloadProgress(DownloadProgress::Failed {
+				file_id: failed, error, ..
+			})) if failed == file_id => {
+				return Err(anyhow!(error).into());
+			},
+			Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => (),
+			Err(broadcast::error::RecvError::Closed) => return Err(anyhow!("The node stopped").into()),
+		}
+	}
+}
+
+async fn set_network(state: &mut AppState, config: NetworkConfig) -> Result<()> {
+	let config = state.set_network_config(config).await?;
+	println!("{}", serde_json::to_string(&config)?);
+	Ok(())
+}
+
+// Same location the desktop app uses on Linux, `$XDG_DATA_HOME/<bundle identifier>`
+fn default_data_dir() -> PathBuf {
+	std::env::var_os("XDG_DATA_HOME")
+		.map(PathBuf::from)
+		.or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+		.unwrap_or_else(|| PathBuf::from("."))
+		.join("com.app.crewcast")
+}
@@ -0,0 +1,131 @@
+use std::sync::Arc;
+
+use iroh::NodeId;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::comm::events::SwarmEvent;
+
+/// A direct neighbor in the gossip swarm of a topic, messages reach this node through its neighbors.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Neighbor {
+	pub node_id: String,
+	pub since: i64,
+}
+
+/// How this node is connected to the gossip swarm of a topic.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Connectivity {
+	pub topic_id: String,
+	pub neighbors: Vec<Neighbor>,
+	/// Every neighbor was lost after the swarm was joined, nothing is sent or received until one comes back
+	pub partitioned: bool,
+	/// Times this node fell behind and dropped gossip, the next check-ins sync what was missed
+	pub lagged: u64,
+	pub last_lagged_at: Option<i64>,
+}
+
+// Shared by the subscription handler, which keeps it up to date, and the `topic_connectivity` command
+pub(crate) type SharedConnectivity = Arc<Mutex<Connectivity>>;
+
+impl Connectivity {
+	pub(crate) fn shared(topic_id: String) -> SharedConnectivity {
+		Arc::new(Mutex::new(Self {
+			topic_id,
+			..Default::default()
+		}))
+	}
+
+	/// Records the neighbors the swarm was joined with.
+	pub(crate) fn joined(&mut self, neighbors: impl Iterator<Item = NodeId>) {
+		let since = chrono::Utc::now().timestamp();
+		self.neighbors = neighbors
+			.map(|node_id| Neighbor {
+				node_id: node_id.to_string(),
+				since,
+			})
+			.collect();
+	}
+
+	pub(crate) fn neighbor_up(&mut self, node_id: NodeId) -> SwarmEvent {
+		let node_id = node_id.to_string();
+		if !self.neighbors.iter().any(|neighbor| neighbor.node_id == node_id) {
+			self.neighbors.push(Neighbor {
+				node_id: node_id.clone(),
+				since: chrono::Utc::now().timestamp(),
+			});
+		}
+		self.partitioned = false;
+		SwarmEvent::NeighborUp {
+			topic_id: self.topic_id.clone(),
+			node_id,
+			neighbors: self.neighbors.len(),
+		}
+	}
+
+	/// Returns the events for a lost neighbor, [`SwarmEvent::Partitioned`] follows when it was the last one.
+	pub(crate) fn neighbor_down(&mut self, node_id: NodeId) -> Vec<SwarmEvent> {
+		let node_id = node_id.to_string();
+		self.neighbors.retain(|neighbor| neighbor.node_id != node_id);
+		let mut events = vec![SwarmEvent::NeighborDown {
+			topic_id: self.topic_id.clone(),
+			node_id,
+			neighbors: self.neighbors.len(),
+		}];
+		if self.neighbors.is_empty() && !self.partitioned {
+			self.partitioned = true;
+			events.push(SwarmEvent::Partitioned {
+				topic_id: self.topic_id.clone(),
+			});
+		}
+		events
+	}
+
+	pub(crate) fn lagged(&mut self) -> SwarmEvent {
+		self.lagged += 1;
+		self.last_lagged_at = Some(chrono::Utc::now().timestamp());
+		SwarmEvent::Lagged {
+			topic_id: self.topic_id.clone(),
+			times: self.lagged,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use iroh::SecretKey;
+
+	use super::*;
+
+	fn node_id() -> NodeId {
+		SecretKey::generate(rand::rngs::OsRng).public()
+	}
+
+	#[test]
+	fn losing_the_last_neighbor_partitions() {
+		let (first, second) = (node_id(), node_id());
+		let mut connectivity = Connectivity {
+			topic_id: "topic".to_string(),
+			..Default::default()
+		};
+		connectivity.joined([first].into_iter());
+		connectivity.neighbor_up(second);
+		connectivity.neighbor_up(second);
+		assert_eq!(connectivity.neighbors.len(), 2);
+
+		assert_eq!(connectivity.neighbor_down(first).len(), 1);
+		let events = connectivity.neighbor_down(second);
+		assert!(connectivity.partitioned);
+		assert_eq!(
+			events.last(),
+			Some(&SwarmEvent::Partitioned {
+				topic_id: "topic".to_string()
+			})
+		);
+
+		connectivity.neighbor_up(first);
+		assert!(!connectivity.partitioned);
+	}
+}
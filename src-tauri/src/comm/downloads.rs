@@ -0,0 +1,438 @@
+use std::{
+	collections::HashMap,
+	path::{Component, Path, PathBuf},
+	str::FromStr,
+	sync::Arc,
+};
+
+use anyhow::anyhow;
+use futures_lite::StreamExt;
+use iroh::{Endpoint, NodeId};
+use iroh_blobs::{
+	api::downloader::{DownloadProgessItem, Downloader, Shuffled},
+	format::collection::Collection,
+	protocol::{ChunkRanges, GetRequest},
+	store::fs::FsStore,
+	BlobFormat, HashAndFormat,
+};
+use tokio::sync::{Mutex, Notify};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+	comm::events::{DownloadProgress, EventSink},
+	database::{
+		download::{Download, DownloadOperations},
+		file::{FileEntry, FileOperations, FileStatus},
+		Db,
+	},
+	error::{Error, Result},
+};
+
+// Transfers running at once, the rest of the queue waits for one of them to end
+const MAX_CONCURRENT_DOWNLOADS: usize = 3;
+
+// What the transfers run on, taken from the node when the manager starts
+#[derive(Clone)]
+pub(crate) struct DownloadContext {
+	pub db: Db,
+	pub store: FsStore,
+	pub endpoint: Endpoint,
+	pub events: Arc<dyn EventSink>,
+}
+
+/// Works through the `downloads` queue in the background, so a transfer holds neither the caller nor the node.
+/// Blobs are fetched into the store first, a transfer that is paused or interrupted continues from what the store has.
+pub(crate) struct DownloadManager {
+	wake: Arc<Notify>,
+	running: Arc<Mutex<HashMap<i64, CancellationToken>>>, // Keyed by file id
+	cancel_token: CancellationToken,
+}
+
+impl DownloadManager {
+	/// Queues the downloads a previous run left unfinished and starts working through the queue.
+	pub async fn start(ctx: DownloadContext) -> Result<Self> {
+		ctx.db.requeue_interrupted_downloads().await?;
+
+		let manager = Self {
+			wake: Arc::new(Notify::new()),
+			running: Arc::default(),
+			cancel_token: CancellationToken::new(),
+		};
+		let scheduler = Scheduler {
+			downloader: ctx.store.downloader(&ctx.endpoint),
+			ctx,
+			wake: manager.wake.clone(),
+			running: manager.running.clone(),
+			cancel_token: manager.cancel_token.clone(),
+		};
+		tokio::spawn(scheduler.run());
+		manager.wake();
+		Ok(manager)
+	}
+
+	/// Looks at the queue again, after a download was queued or resumed.
+	pub fn wake(&self) {
+		self.wake.notify_one();
+	}
+
+	/// Stops the transfer of a file, returns false if it was not running.
+	/// The status is left to the caller, the data fetched so far stays in the store.
+	pub async fn stop_transfer(&self, file_id: i64) -> bool {
+		let stopped = match self.running.lock().await.remove(&file_id) {
+			Some(token) => {
+				token.cancel();
+				true
+			},
+			None => false,
+		};
+		self.wake();
+		stopped
+	}
+
+	/// Stops the queue and every transfer, they stay queued for the next start.
+	pub fn stop(&self) {
+		self.cancel_token.cancel();
+	}
+}
+
+struct Scheduler {
+	ctx: DownloadContext,
+	downloader: Downloader,
+	wake: Arc<Notify>,
+	running: Arc<Mutex<HashMap<i64, CancellationToken>>>,
+	cancel_token: CancellationToken,
+}
+
+impl Scheduler {
+	async fn run(self) {
+		loop {
+			tokio::select! {
+				_ = self.cancel_token.cancelled() => break,
+				_ = self.wake.notified() => {
+					if let Err(e) = self.start_queued().await {
+						eprintln!("Failed to start queued downloads: {}", e);
+					}
+				},
+			}
+		}
+	}
+
+	// Starts queued downloads in order until the free slots are taken
+	async fn start_queued(&self) -> Result<()> {
+		let mut running = self.running.lock().await;
+		// Transfers that ended on their own free their slot
+		running.retain(|_, token| !token.is_cancelled());
+		let queued = self.ctx.db.list_downloads().await?;
+		for download in queued
+			.into_iter()
+			.filter(|download| download.status == FileStatus::Queued)
+		{
+			if running.len() >= MAX_CONCURRENT_DOWNLOADS {
+				break;
+			}
+			if !self
+				.ctx
+				.db
+				.set_download_status(download.file_id, FileStatus::Downloading)
+				.await?
+			{
+				continue;
+			}
+			let token = self.cancel_token.child_token();
+			running.insert(download.file_id, token.clone());
+
+			let ctx = self.ctx.clone();
+			let downloader = self.downloader.clone();
+			let wake = self.wake.clone();
+			tokio::spawn(async move {
+				tokio::select! {
+					// Whoever stopped it already gave it its status
+					_ = token.cancelled() => (),
+					result = transfer(&ctx, &downloader, &download) => {
+						if let Err(e) = finish(&ctx, &download, result).await {
+							eprintln!("Failed to record the download of {}: {}", download.name, e);
+						}
+					},
+				}
+				token.cancel();
+				wake.notify_one();
+			});
+		}
+		Ok(())
+	}
+}
+
+// Fetches the blob from the node that shared it and the members that downloaded it, and exports it.
+// Returns where it was saved
+async fn transfer(ctx: &DownloadContext, downloader: &Downloader, download: &Download) -> Result<PathBuf> {
+	let hash = iroh_blobs::Hash::from_str(&download.hash)
+		.map_err(|e| Error::EncodeDecode(format!("Failed to parse file hash: {}", e)))?;
+	let providers = providers(ctx, &download.topic_id, &download.node_id, &download.hash).await?;
+	let is_collection = download.format == BlobFormat::HashSeq.to_string();
+	let (request, entries) = if is_collection {
+		let entries = fetch_entries(ctx, downloader, download.file_id, hash, providers.clone()).await?;
+		let selected: Vec<FileEntry> = entries.iter().filter(|entry| entry.selected).cloned().collect();
+		let request = if selected.len() == entries.len() {
+			GetRequest::all(hash)
+		} else {
+			// The names are the first child, the entries follow it
+			let mut builder = GetRequest::builder()
+				.root(ChunkRanges::all())
+				.child(0, ChunkRanges::all());
+			for entry in &selected {
+				builder = builder.child(entry.position as u64 + 1, ChunkRanges::all());
+			}
+			builder.build(hash)
+		};
+		(request, selected)
+	} else {
+		(GetRequest::blob(hash), Vec::new())
+	};
+
+	// Providers are tried in random order so the load spreads, the next one takes over when one fails.
+	// Whatever a provider sends is verified against the hash, a holder can not slip in other content
+	let mut stream = downloader
+		.download(request, Shuffled::new(providers))
+		.stream()
+		.await
+		.map_err(|e| Error::Generic(anyhow!("Failed to create download stream: {}", e)))?;
+	let file_id = download.file_id;
+	let file_name = download.name.clone();
+	while let Some(item) = stream.next().await {
+		let file_name = file_name.clone();
+		let progress = match item {
+			DownloadProgessItem::Progress(downloaded) => {
+				// Includes what the store already had from an earlier attempt
+				let percentage = if download.size > 0 {
+					(downloaded as f64 / download.size as f64) * 100.0
+				} else {
+					0.0
+				};
+				DownloadProgress::Progress {
+					file_id,
+					file_name,
+					percentage,
+					downloaded,
+					total: download.size,
+				}
+			},
+			DownloadProgessItem::Error(err) => return Err(anyhow!("Download error: {:?}", err).into()),
+			DownloadProgessItem::DownloadError => return Err(anyhow!("Download error occurred").into()),
+			DownloadProgessItem::PartComplete { .. } => DownloadProgress::PartComplete { file_id, file_name },
+			DownloadProgessItem::ProviderFailed { .. } => DownloadProgress::ProviderFailed { file_id, file_name },
+			_ => continue,
+		};
+		ctx.events.emit(progress.into());
+	}
+
+	// Tagged like a shared file, the store keeps it until no topic refers to it any more
+	let tagged = if is_collection {
+		HashAndFormat::hash_seq(hash)
+	} else {
+		HashAndFormat::raw(hash)
+	};
+	ctx.store
+		.tags()
+		.create(tagged)
+		.await
+		.map_err(|e| Error::Generic(anyhow!("Failed to tag the download: {}", e)))?;
+
+	let target = PathBuf::from(&download.target_path);
+	if let Some(parent) = target.parent() {
+		std::fs::create_dir_all(parent)?;
+	}
+	let save_path = available_path(target);
+	if !is_collection {
+		export(ctx, hash, &save_path).await?;
+		return Ok(save_path);
+	}
+
+	// A directory keeps its layout, the entries are exported below the target
+	std::fs::create_dir_all(&save_path)?;
+	for entry in entries {
+		let path = entry_path(&save_path, &entry.path)
+			.ok_or_else(|| Error::EncodeDecode(format!("{} is not a relative path", entry.path)))?;
+		if let Some(parent) = path.parent() {
+			std::fs::create_dir_all(parent)?;
+		}
+		let entry_hash = iroh_blobs::Hash::from_str(&entry.hash)
+			.map_err(|e| Error::EncodeDecode(format!("Failed to parse file hash: {}", e)))?;
+		export(ctx, entry_hash, &path).await?;
+		let size = std::fs::metadata(&path)?.len() as i64;
+		ctx.db
+			.update_file_entry(file_id, entry.position, size, &path.to_string_lossy())
+			.await?;
+	}
+	Ok(save_path)
+}
+
+/// The entries of the shared directory `file_id`, see [`fetch_entries`].
+/// Takes the context rather than the node, so the fetch does not hold the app state.
+pub(crate) async fn list_entries(ctx: &DownloadContext, file_id: i64) -> Result<Vec<FileEntry>> {
+	let file = ctx.db.get_file_by_id(file_id).await?;
+	if file.format != BlobFormat::HashSeq.to_string() {
+		return Err(anyhow!("{} is not a directory", file.name).into());
+	}
+	let hash = iroh_blobs::Hash::from_str(&file.hash)
+		.map_err(|e| Error::EncodeDecode(format!("Failed to parse file hash: {}", e)))?;
+	let providers = providers(ctx, &file.topic_id, &file.node_id, &file.hash).await?;
+	let downloader = ctx.store.downloader(&ctx.endpoint);
+	fetch_entries(ctx, &downloader, file.id, hash, providers).await
+}
+
+/// The entries of a shared directory. The first time they are asked for on a node other than the one that shared it,
+/// the collection's list and names are fetched, which leaves the entries themselves for the download.
+pub(crate) async fn fetch_entries(
+	ctx: &DownloadContext,
+	downloader: &Downloader,
+	file_id: i64,
+	hash: iroh_blobs::Hash,
+	providers: Vec<NodeId>,
+) -> Result<Vec<FileEntry>> {
+	let entries = ctx.db.list_file_entries(file_id).await?;
+	if !entries.is_empty() {
+		return Ok(entries);
+	}
+	let request = GetRequest::builder()
+		.root(ChunkRanges::all())
+		.child(0, ChunkRanges::all())
+		.build(hash);
+	downloader
+		.download(request, Shuffled::new(providers))
+		.await
+		.map_err(|e| Error::Generic(anyhow!("Failed to fetch the collection: {}", e)))?;
+	let collection = Collection::load(hash, &*ctx.store)
+		.await
+		.map_err(|e| Error::EncodeDecode(format!("Failed to read the collection: {}", e)))?;
+	let entries: Vec<FileEntry> = collection
+		.into_iter()
+		.enumerate()
+		.map(|(position, (path, hash))| FileEntry::new(file_id, position as i64, path, hash.to_string(), None))
+		.collect();
+	ctx.db.create_file_entries(&entries).await?;
+	ctx.db.list_file_entries(file_id).await
+}
+
+/// The node that shared a file followed by the members that announced holding it, without this node.
+pub(crate) async fn providers(ctx: &DownloadContext, topic_id: &str, sharer: &str, hash: &str) -> Result<Vec<NodeId>> {
+	let sharer = NodeId::from_str(sharer).map_err(|e| Error::EncodeDecode(format!("Failed to parse nodeId: {}", e)))?;
+	let mut providers = vec![sharer];
+	for holder in ctx.db.list_blob_holders(topic_id, hash).await? {
+		if let Ok(holder) = NodeId::from_str(&holder) {
+			if holder != ctx.endpoint.node_id() && !providers.contains(&holder) {
+				providers.push(holder);
+			}
+		}
+	}
+	Ok(providers)
+}
+
+async fn export(ctx: &DownloadContext, hash: iroh_blobs::Hash, path: &Path) -> Result<()> {
+	ctx.store
+		.blobs()
+		.export(hash, path)
+		.await
+		.map_err(|e| Error::Generic(anyhow!("Failed to export blob: {}", e)))?;
+	Ok(())
+}
+
+// The names of a collection are chosen by the sharing node, a name that leaves the directory is refused
+fn entry_path(dir: &Path, name: &str) -> Option<PathBuf> {
+	let relative = Path::new(name);
+	let inside = relative
+		.components()
+		.all(|component| matches!(component, Component::Normal(_)));
+	(inside && !name.is_empty()).then(|| dir.join(relative))
+}
+
+async fn finish(ctx: &DownloadContext, download: &Download, result: Result<PathBuf>) -> Result<()> {
+	let file_id = download.file_id;
+	let file_name = download.name.clone();
+	match result {
+		Ok(path) => {
+			let path = path.to_string_lossy().to_string();
+			ctx.db
+				.finish_download(file_id, FileStatus::Downloaded, Some(path.clone()))
+				.await?;
+			ctx.events.emit(
+				DownloadProgress::Complete {
+					file_id,
+					file_name,
+					path,
+					percentage: 100.0,
+					downloaded: download.size,
+					total: download.size,
+				}
+				.into(),
+			);
+		},
+		Err(e) => {
+			let error = e.to_string();
+			ctx.db.fail_download(file_id, &error).await?;
+			ctx.events.emit(
+				DownloadProgress::Failed {
+					file_id,
+					file_name,
+					error,
+				}
+				.into(),
+			);
+		},
+	}
+	Ok(())
+}
+
+// The path itself when it is free, otherwise the first free `name (n).ext` next to it
+fn available_path(path: PathBuf) -> PathBuf {
+	if !path.exists() {
+		return path;
+	}
+	let stem = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+	let extension = path
+		.extension()
+		.map(|extension| extension.to_string_lossy().to_string());
+	(1..)
+		.map(|n| {
+			let name = match &extension {
+				Some(extension) => format!("{} ({}).{}", stem, n, extension),
+				None => format!("{} ({})", stem, n),
+			};
+			path.with_file_name(name)
+		})
+		.find(|candidate| !candidate.exists())
+		.expect("some name is free")
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+
+	use super::*;
+
+	#[test]
+	fn taken_names_are_numbered() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("notes.txt");
+		assert_eq!(available_path(path.clone()), path);
+
+		fs::write(&path, "notes").unwrap();
+		fs::write(dir.path().join("notes (1).txt"), "notes").unwrap();
+		assert_eq!(available_path(path), dir.path().join("notes (2).txt"));
+
+		let path = dir.path().join("README");
+		fs::write(&path, "readme").unwrap();
+		assert_eq!(available_path(path), dir.path().join("README (1)"));
+	}
+
+	#[test]
+	fn entries_stay_in_the_directory() {
+		let dir = Path::new("/downloads/photos");
+		assert_eq!(
+			entry_path(dir, "2024/beach.jpg"),
+			Some(dir.join("2024").join("beach.jpg"))
+		);
+		assert_eq!(entry_path(dir, "../.bashrc"), None);
+		assert_eq!(entry_path(dir, "/etc/passwd"), None);
+		assert_eq!(entry_path(dir, ""), None);
+	}
+}
@@ -1,10 +1,21 @@
-use iroh::{Endpoint, SecretKey};
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use iroh::{endpoint::Builder, Endpoint, RelayMap, RelayMode, RelayUrl, SecretKey};
 use iroh_gossip::{net::Gossip, proto::TopicId};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-	comm::model::UserInfo,
+	comm::{
+		events::GossipEvent,
+		model::{Moderation, Policy, UserInfo},
+		ticket::Grant,
+	},
 	database::{
-		node::{Node, NodeOperations},
+		member::{MemberOperations, Role},
+		moderation::ModerationOperations,
+		node::NodeOperations,
+		ticket::TicketOperations,
 		topic::{Topic, TopicOperations},
 		user::{User, UserOperations},
 		Db,
@@ -12,75 +23,294 @@ use crate::{
 	error::{Error, Result},
 };
 
-pub async fn create_endpoint(encoded_secret: String) -> Result<Endpoint> {
-	let mut key_bytes = [0u8; 32];
+/// How the node finds and reaches other nodes, persisted in the settings table.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum NetworkConfig {
+	/// n0 DNS discovery and relays, plus mDNS on the local network
+	#[default]
+	Default,
+	/// mDNS only and no relays, nothing leaves the local network
+	LanOnly,
+	/// Self-hosted relays instead of the n0 ones, nodes are still published through n0 DNS so tickets resolve
+	CustomRelays { relay_urls: Vec<String> },
+}
 
-	data_encoding::BASE32_NOPAD
-		.decode_mut(encoded_secret.as_bytes(), &mut key_bytes)
-		.map_err(|_| Error::EncodeDecode("Error Decoding Secret".to_string()))?;
+impl NetworkConfig {
+	pub(crate) const SETTINGS_KEY: &'static str = "network";
 
-	let secret_key = SecretKey::from_bytes(&key_bytes);
+	/// Endpoint builder for this configuration, fails on relay URLs that do not parse.
+	pub fn endpoint_builder(&self) -> Result<Builder> {
+		let builder = match self {
+			NetworkConfig::Default => Endpoint::builder().discovery_n0().discovery_local_network(),
+			NetworkConfig::LanOnly => Endpoint::builder()
+				.relay_mode(RelayMode::Disabled)
+				.discovery_local_network(),
+			NetworkConfig::CustomRelays { relay_urls } => {
+				if relay_urls.is_empty() {
+					return Err(anyhow!("At least one relay URL is required").into());
+				}
+				let relays = relay_urls
+					.iter()
+					.map(|url| {
+						RelayUrl::from_str(url).map_err(|e| Error::Generic(anyhow!("Invalid relay URL {}: {}", url, e)))
+					})
+					.collect::<Result<RelayMap>>()?;
+				Endpoint::builder()
+					.relay_mode(RelayMode::Custom(relays))
+					.discovery_n0()
+					.discovery_local_network()
+			},
+		};
+		Ok(builder)
+	}
+}
 
-	let endpoint = Endpoint::builder()
+pub async fn create_endpoint(secret_key: SecretKey, builder: Builder) -> Result<Endpoint> {
+	let endpoint = builder
 		.secret_key(secret_key)
-		.discovery_n0()
-		.discovery_local_network()
 		.bind()
 		.await
 		.map_err(|e| Error::Endpoint(format!("Failed to bind endpoint: {}", e)))?;
 	Ok(endpoint)
 }
 
+// Check-ins carry the sender's ticket grant and member list, which outgrows gossip's 4 KiB default
+const MAX_GOSSIP_MESSAGE_SIZE: usize = 64 * 1024;
+
 pub async fn new_gossip(endpoint: Endpoint) -> Result<Gossip> {
-	let gossip = Gossip::builder().spawn(endpoint);
+	let gossip = Gossip::builder()
+		.max_message_size(MAX_GOSSIP_MESSAGE_SIZE)
+		.spawn(endpoint);
 	Ok(gossip)
 }
 
-pub fn create_secret() -> String {
-	let secret_key = SecretKey::generate(rand::rngs::OsRng);
-	data_encoding::BASE32_NOPAD.encode(&secret_key.to_bytes())
-}
-
 pub fn new_topic() -> TopicId {
 	TopicId::from_bytes(rand::random())
 }
 
-pub async fn update_topic(db: &Db, topic_id: String, member: String, user_info: UserInfo) -> Result<Topic> {
-	let topic = db.get_topic_by_topic_id(topic_id).await?;
+pub async fn update_topic(db: &Db, topic_id: String, member: String, user_info: UserInfo) -> Result<()> {
+	// Check-ins of a new member can reach several topics at once, so its node and profile are added in one step each
+	let node = db.get_or_create_node(&member).await?;
+	let user = User::new(
+		user_info.email,
+		user_info.first_name,
+		user_info.last_name,
+		Some(node.id),
+	);
+	// A profile that can not be stored does not keep the member out
+	if let Err(e) = db.create_user_if_missing(user).await {
+		eprintln!("Failed to store the profile of {}: {}", member, e);
+	}
+
+	db.add_member(&topic_id, &member).await?;
+	Ok(())
+}
+
+// Members listed in the owner's check-in, their profile follows with their own check-in
+pub async fn add_vouched_member(db: &Db, topic_id: String, member: String) -> Result<()> {
+	if db.is_banned(&topic_id, &member).await? {
+		return Ok(());
+	}
+	db.get_or_create_node(&member).await?;
+	db.add_member(&topic_id, &member).await?;
+	Ok(())
+}
+
+/// Decides whether `member`, not known to be in the topic yet, may be admitted with the grant from its check-in.
+/// The grant has to be issued for this topic by one of its admins, and not be expired, revoked or used up.
+/// Only checks, the use is taken with [`redeem_grant`] once the member is admitted.
+pub async fn check_grant<'a>(db: &Db, topic: &Topic, member: &str, grant: Option<&'a Grant>) -> Result<&'a Grant> {
+	let grant = grant.ok_or_else(|| Error::Ticket("No ticket presented".to_string()))?;
+	grant.verify().map_err(|e| Error::Ticket(e.to_string()))?;
+	if grant.topic_id != topic.topic_id {
+		return Err(Error::Ticket("Ticket was issued for another topic".to_string()));
+	}
+	if !db.get_role(&topic.topic_id, &grant.issuer).await?.can_moderate() {
+		return Err(Error::Ticket("Ticket was not issued by an admin".to_string()));
+	}
+	if grant.is_expired(chrono::Utc::now().timestamp()) {
+		return Err(Error::Ticket("Ticket expired".to_string()));
+	}
+	if db.is_ticket_revoked(&grant.nonce, &grant.issuer).await? {
+		return Err(Error::Ticket("Ticket was revoked".to_string()));
+	}
+	if db.is_ticket_spent(&topic.topic_id, member, &grant.nonce).await? {
+		return Err(Error::Ticket(
+			"Ticket was used before being removed from the topic".to_string(),
+		));
+	}
+	if !db.can_redeem_ticket(&grant.nonce, member, grant.max_uses).await? {
+		return Err(Error::Ticket("Ticket was used up".to_string()));
+	}
+	Ok(grant)
+}
+
+/// Takes one use of the grant for `member`, fails if another newcomer took the last one since it was checked.
+pub async fn redeem_grant(db: &Db, member: &str, grant: &Grant) -> Result<()> {
+	if !db
+		.redeem_ticket(&grant.topic_id, &grant.nonce, member, grant.max_uses)
+		.await?
+	{
+		return Err(Error::Ticket("Ticket was used up".to_string()));
+	}
+	Ok(())
+}
+
+/// Fails with [`Error::Forbidden`] unless `sender` is allowed to send `moderation` to `topic`.
+pub async fn check_moderation(db: &Db, topic: &Topic, sender: &str, moderation: &Moderation) -> Result<()> {
+	let role = db.get_role(&topic.topic_id, sender).await?;
+	if !role.can_moderate() {
+		return Err(Error::Forbidden("Only admins can manage members".to_string()));
+	}
 
-	let node = match db.get_node_by_node_id(member.clone()).await {
-		Ok(node) => node,
-		Err(_) => {
-			let node = Node::new(member.clone(), None);
+	match moderation {
+		Moderation::SetRole {
+			node_id,
+			role: new_role,
+		} => {
+			if role != Role::Owner {
+				return Err(Error::Forbidden("Only the owner can change roles".to_string()));
+			}
+			if *new_role == Role::Owner || *node_id == topic.owner {
+				return Err(Error::Forbidden("Ownership can only be transferred".to_string()));
+			}
+		},
+		Moderation::TransferOwnership { .. } if role != Role::Owner => {
+			return Err(Error::Forbidden("Only the owner can transfer the topic".to_string()));
+		},
+		Moderation::RemoveMember { node_id, .. } | Moderation::Ban { node_id } => {
+			match db.get_role(&topic.topic_id, node_id).await? {
+				Role::Owner => return Err(Error::Forbidden("The topic owner can not be removed".to_string())),
+				Role::Admin if role != Role::Owner => {
+					return Err(Error::Forbidden("Only the owner can remove admins".to_string()))
+				},
+				_ => (),
+			}
+		},
+		_ => (),
+	}
+	Ok(())
+}
 
-			db.create_node(node).await?
+/// Applies a moderation action that passed [`check_moderation`], returns the event to emit for it.
+/// The owner can not be removed or banned.
+pub async fn apply_moderation(db: &Db, topic_id: &str, moderation: Moderation) -> Result<Option<GossipEvent>> {
+	let topic = db.get_topic_by_topic_id(topic_id.to_string()).await?;
+	let event = match moderation {
+		Moderation::RemoveMember { node_id, nonces } if node_id != topic.owner => {
+			db.spend_tickets(topic_id, &node_id, &nonces).await?;
+			db.remove_member(topic_id, &node_id).await?;
+			Some(GossipEvent::MemberRemoved {
+				topic_id: topic_id.to_string(),
+				node_id,
+				banned: false,
+			})
+		},
+		Moderation::Ban { node_id } if node_id != topic.owner => {
+			db.ban_node(topic_id, &node_id).await?;
+			db.spend_tickets(topic_id, &node_id, &[]).await?;
+			db.delete_join_request(topic_id, &node_id).await?;
+			db.remove_member(topic_id, &node_id).await?;
+			Some(GossipEvent::MemberRemoved {
+				topic_id: topic_id.to_string(),
+				node_id,
+				banned: true,
+			})
+		},
+		Moderation::Unban { node_id } => {
+			db.unban_node(topic_id, &node_id).await?;
+			None
+		},
+		Moderation::RequireApproval { enabled } => {
+			db.set_require_approval(topic_id, enabled).await?;
+			None
 		},
+		Moderation::ApproveJoin { node_id } => {
+			// Admins that queued the request count the use as well, the approving admin already checked it was left
+			if let Some(grant) = db.get_join_request_grant(topic_id, &node_id).await? {
+				db.redeem_ticket(topic_id, &grant.nonce, &node_id, None).await?;
+			}
+			db.delete_join_request(topic_id, &node_id).await?;
+			add_vouched_member(db, topic_id.to_string(), node_id).await?;
+			None
+		},
+		Moderation::DenyJoin { node_id, nonces } => {
+			db.deny_join_request(topic_id, &node_id).await?;
+			db.spend_tickets(topic_id, &node_id, &nonces).await?;
+			None
+		},
+		Moderation::SetRole { node_id, role } if role != Role::Owner && node_id != topic.owner => {
+			db.set_role(topic_id, &node_id, role).await?;
+			Some(GossipEvent::RoleChanged {
+				topic_id: topic_id.to_string(),
+				node_id,
+				role,
+			})
+		},
+		Moderation::TransferOwnership { node_id } if node_id != topic.owner => {
+			db.get_or_create_node(&node_id).await?;
+			db.set_topic_owner(topic.id, node_id.clone()).await?;
+			Some(GossipEvent::RoleChanged {
+				topic_id: topic_id.to_string(),
+				node_id,
+				role: Role::Owner,
+			})
+		},
+		Moderation::RemoveMember { .. }
+		| Moderation::Ban { .. }
+		| Moderation::SetRole { .. }
+		| Moderation::TransferOwnership { .. } => None,
 	};
-	if db.get_user_by_node_id(node.id).await.is_err() {
-		let user = User::new(
-			user_info.email,
-			user_info.first_name,
-			user_info.last_name,
-			Some(node.id),
-		);
-		let _ = db.create_user(user).await?;
+	Ok(event)
+}
+
+/// Brings bans, roles, removed members, the owner and the approval setting in line with the policy `sender` sent,
+/// for members that missed the owner's messages. `sender` is who this node takes for the owner.
+pub async fn apply_policy(db: &Db, topic_id: &str, sender: &str, policy: Policy) -> Result<Vec<GossipEvent>> {
+	// A former owner only hands the topic over, the rest comes with the new owner's policy
+	if !policy.owner.is_empty() && policy.owner != sender {
+		let transfer = Moderation::TransferOwnership { node_id: policy.owner };
+		return Ok(apply_moderation(db, topic_id, transfer).await?.into_iter().collect());
 	}
 
-	// Ensure that the new member is not already present in the database
-	if topic.get_peers().contains(&member) {
-		return Ok(topic);
+	if db.requires_approval(topic_id).await? != policy.require_approval {
+		db.set_require_approval(topic_id, policy.require_approval).await?;
 	}
 
-	// Push new members to the existing members vec
-	let updated_members = if let Some(mut existing_members) = topic.members {
-		existing_members.push(member.clone());
-		existing_members
-	} else {
-		// If this member is not present in the topic, try to add it to the nodes table as well
+	let banned = db.list_banned(topic_id).await?;
+	let mut events = Vec::new();
+	for node_id in banned.iter().filter(|node_id| !policy.banned.contains(node_id)) {
+		db.unban_node(topic_id, node_id).await?;
+	}
+	for node_id in policy.banned.into_iter().filter(|node_id| !banned.contains(node_id)) {
+		events.extend(apply_moderation(db, topic_id, Moderation::Ban { node_id }).await?);
+	}
 
-		vec![member.clone()]
-	};
+	let pending = db.list_join_requests(topic_id).await?;
+	for (node_id, nonces) in policy.removed {
+		let moderation = if pending.iter().any(|request| request.node_id == node_id) {
+			Moderation::DenyJoin { node_id, nonces }
+		} else if db.is_member(topic_id, &node_id).await? {
+			Moderation::RemoveMember { node_id, nonces }
+		} else {
+			continue;
+		};
+		events.extend(apply_moderation(db, topic_id, moderation).await?);
+	}
 
-	let topic = db.update_topic(topic.id, updated_members).await?;
-	Ok(topic)
+	let roles = db.list_roles(topic_id).await?;
+	let demoted = roles
+		.keys()
+		.filter(|node_id| !policy.roles.contains_key(*node_id))
+		.map(|node_id| (node_id.clone(), Role::Member));
+	let changed = policy
+		.roles
+		.iter()
+		.filter(|(node_id, role)| roles.get(*node_id) != Some(role))
+		.map(|(node_id, role)| (node_id.clone(), *role));
+	for (node_id, role) in demoted.chain(changed).collect::<Vec<_>>() {
+		events.extend(apply_moderation(db, topic_id, Moderation::SetRole { node_id, role }).await?);
+	}
+	Ok(events)
 }
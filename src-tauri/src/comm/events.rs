@@ -0,0 +1,339 @@
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::{
+	comm::model::UserInfo,
+	database::{file::File, member::Role, message::Message},
+};
+
+/// Something that happened in a topic, delivered to the frontend as "gossip-message".
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case", rename_all_fields = "camelCase")]
+pub enum GossipEvent {
+	CheckIn {
+		topic_id: String,
+		sender: String,
+		meta: UserInfo,
+	},
+	Chat {
+		topic_id: String,
+		sender: String,
+		content: String,
+		sent_at: i64,
+	},
+	File {
+		topic_id: String,
+		file: File,
+	},
+	FileBatch {
+		topic_id: String,
+		files: Vec<File>,
+		sender: String,
+	},
+	ChatBatch {
+		topic_id: String,
+		messages: Vec<Message>,
+		sender: String,
+	},
+	JoinRequested {
+		topic_id: String,
+		sender: String,
+		meta: UserInfo,
+	},
+	MemberRemoved {
+		topic_id: String,
+		node_id: String,
+		banned: bool,
+	},
+	RoleChanged {
+		topic_id: String,
+		node_id: String,
+		role: Role,
+	},
+	MemberLeft {
+		topic_id: String,
+		node_id: String,
+		permanent: bool,
+	},
+}
+
+/// Lifecycle of a topic subscription, delivered as "topic-status".
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case", rename_all_fields = "camelCase")]
+pub enum TopicStatus {
+	Joining { topic_id: String },
+	Online { topic_id: String },
+	Failed { topic_id: String, error: String },
+}
+
+/// A member started or stopped checking in, delivered as "member-online" or "member-offline".
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case", rename_all_fields = "camelCase")]
+pub enum Presence {
+	Online {
+		topic_id: String,
+		node_id: String,
+		last_seen: i64,
+	},
+	Offline {
+		topic_id: String,
+		node_id: String,
+		last_seen: i64,
+	},
+}
+
+/// Changes in how this node is connected to the gossip swarm of a topic, delivered as "topic-connectivity".
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case", rename_all_fields = "camelCase")]
+pub enum SwarmEvent {
+	NeighborUp {
+		topic_id: String,
+		node_id: String,
+		neighbors: usize,
+	},
+	NeighborDown {
+		topic_id: String,
+		node_id: String,
+		neighbors: usize,
+	},
+	/// The last neighbor was lost, messages neither arrive nor go out until one comes back
+	Partitioned { topic_id: String },
+	/// Gossip came in faster than it was handled and some of it was dropped
+	Lagged { topic_id: String, times: u64 },
+}
+
+/// Progress of a single download, delivered as "download-progress".
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case", rename_all_fields = "camelCase")]
+pub enum DownloadProgress {
+	Queued {
+		file_id: i64,
+		file_name: String,
+	},
+	Progress {
+		file_id: i64,
+		file_name: String,
+		percentage: f64,
+		downloaded: u64,
+		total: i64,
+	},
+	PartComplete {
+		file_id: i64,
+		file_name: String,
+	},
+	ProviderFailed {
+		file_id: i64,
+		file_name: String,
+	},
+	Paused {
+		file_id: i64,
+		file_name: String,
+	},
+	Cancelled {
+		file_id: i64,
+		file_name: String,
+	},
+	Failed {
+		file_id: i64,
+		file_name: String,
+		error: String,
+	},
+	Complete {
+		file_id: i64,
+		file_name: String,
+		path: String,
+		percentage: f64,
+		downloaded: i64,
+		total: i64,
+	},
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum Event {
+	Gossip(GossipEvent),
+	TopicStatus(TopicStatus),
+	Presence(Presence),
+	Swarm(SwarmEvent),
+	DownloadProgress(DownloadProgress),
+}
+
+impl Event {
+	/// The event name the webview listens on.
+	pub fn name(&self) -> &'static str {
+		match self {
+			Event::Gossip(_) => "gossip-message",
+			Event::TopicStatus(_) => "topic-status",
+			Event::Presence(Presence::Online { .. }) => "member-online",
+			Event::Presence(Presence::Offline { .. }) => "member-offline",
+			Event::Swarm(_) => "topic-connectivity",
+			Event::DownloadProgress(_) => "download-progress",
+		}
+	}
+}
+
+impl From<GossipEvent> for Event {
+	fn from(event: GossipEvent) -> Self {
+		Event::Gossip(event)
+	}
+}
+
+impl From<TopicStatus> for Event {
+	fn from(status: TopicStatus) -> Self {
+		Event::TopicStatus(status)
+	}
+}
+
+impl From<Presence> for Event {
+	fn from(presence: Presence) -> Self {
+		Event::Presence(presence)
+	}
+}
+
+impl From<SwarmEvent> for Event {
+	fn from(event: SwarmEvent) -> Self {
+		Event::Swarm(event)
+	}
+}
+
+impl From<DownloadProgress> for Event {
+	fn from(progress: DownloadProgress) -> Self {
+		Event::DownloadProgress(progress)
+	}
+}
+
+// Where gossip and transfer events are delivered, the webview in the app or an in-process listener otherwise
+pub trait EventSink: Send + Sync {
+	fn emit(&self, event: Event);
+}
+
+#[cfg(feature = "gui")]
+impl EventSink for tauri::AppHandle {
+	fn emit(&self, event: Event) {
+		use tauri::Emitter;
+
+		// The webview parses the payload itself, so it is sent as a JSON string
+		let payload = match serde_json::to_string(&event) {
+			Ok(payload) => payload,
+			Err(e) => {
+				eprintln!("Failed to serialize {}: {}", event.name(), e);
+				return;
+			},
+		};
+		if let Err(e) = Emitter::emit(self, event.name(), payload) {
+			eprintln!("Failed to emit {}: {}", event.name(), e);
+		}
+	}
+}
+
+/// Keeps events in process for tests and non-GUI frontends, every subscriber sees every event emitted after it subscribed.
+#[derive(Clone)]
+pub struct MemorySink {
+	sender: broadcast::Sender<Event>,
+}
+
+impl MemorySink {
+	pub fn new(capacity: usize) -> Self {
+		let (sender, _) = broadcast::channel(capacity);
+		Self { sender }
+	}
+
+	pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+		self.sender.subscribe()
+	}
+}
+
+impl Default for MemorySink {
+	fn default() -> Self {
+		Self::new(1024)
+	}
+}
+
+impl EventSink for MemorySink {
+	fn emit(&self, event: Event) {
+		// Nobody listening is not an error
+		let _ = self.sender.send(event);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// The webview matches on these exact field names
+	#[test]
+	fn gossip_event_payload() {
+		let event = Event::from(GossipEvent::Chat {
+			topic_id: "topic".to_string(),
+			sender: "node".to_string(),
+			content: "hello".to_string(),
+			sent_at: 42,
+		});
+
+		assert_eq!(event.name(), "gossip-message");
+		assert_eq!(
+			serde_json::to_value(&event).unwrap(),
+			serde_json::json!({
+				"type": "chat",
+				"topicId": "topic",
+				"sender": "node",
+				"content": "hello",
+				"sentAt": 42,
+			})
+		);
+	}
+
+	#[test]
+	fn topic_status_payload() {
+		let event = Event::from(TopicStatus::Failed {
+			topic_id: "topic".to_string(),
+			error: "boom".to_string(),
+		});
+
+		assert_eq!(event.name(), "topic-status");
+		assert_eq!(
+			serde_json::to_value(&event).unwrap(),
+			serde_json::json!({
+				"status": "failed",
+				"topicId": "topic",
+				"error": "boom",
+			})
+		);
+	}
+
+	#[test]
+	fn presence_payload() {
+		let event = Event::from(Presence::Offline {
+			topic_id: "topic".to_string(),
+			node_id: "node".to_string(),
+			last_seen: 42,
+		});
+
+		assert_eq!(event.name(), "member-offline");
+		assert_eq!(
+			serde_json::to_value(&event).unwrap(),
+			serde_json::json!({
+				"status": "offline",
+				"topicId": "topic",
+				"nodeId": "node",
+				"lastSeen": 42,
+			})
+		);
+	}
+
+	#[tokio::test]
+	async fn memory_sink_delivers_to_subscribers() {
+		let sink = MemorySink::default();
+		let mut receiver = sink.subscribe();
+
+		sink.emit(
+			TopicStatus::Online {
+				topic_id: "topic".to_string(),
+			}
+			.into(),
+		);
+
+		let event = receiver.recv().await.unwrap();
+		assert!(matches!(event, Event::TopicStatus(TopicStatus::Online { topic_id }) if topic_id == "topic"));
+	}
+}
@@ -1,40 +1,74 @@
-use std::{collections::HashMap, str::FromStr};
+use std::{collections::HashMap, str::FromStr, sync::Arc};
 
 use futures_lite::StreamExt;
-use iroh::Watcher;
-use iroh_blobs::ticket::BlobTicket;
+use iroh::{Endpoint, NodeAddr, Watcher};
+use iroh_blobs::{ticket::BlobTicket, BlobFormat};
 use iroh_gossip::api::{Event, GossipReceiver, GossipSender};
-use tauri::{Emitter, Manager};
 use tokio::sync::Mutex;
 use tokio_util::sync::CancellationToken;
 
 use crate::{
 	comm::{
-		endpoint::update_topic,
-		model::{CheckIn, FileBatch, MessageType, UserInfo},
+		connectivity::SharedConnectivity,
+		endpoint::{
+			add_vouched_member, apply_moderation, apply_policy, check_grant, check_moderation, redeem_grant,
+			update_topic,
+		},
+		events::{EventSink, GossipEvent, Presence, TopicStatus},
+		model::{
+			ChatBatch, ChatRecord, CheckIn, FileBatch, Leave, MessageType, Moderation, ModerationUpdate, Policy,
+			SignedMessage, UserInfo,
+		},
 	},
 	database::{
 		file::{File, FileOperations, FileStatus, TsDirection, TsFilter},
+		member::{MemberOperations, Role},
+		message::{Message, MessageOperations},
+		moderation::ModerationOperations,
+		ticket::TicketOperations,
 		topic::TopicOperations,
 		Db,
 	},
 	error::{Error, Result},
-	AppState,
 };
 
+pub mod connectivity;
+pub mod downloads;
 pub mod endpoint;
+pub mod events;
 pub mod model;
 pub mod state;
 pub mod ticket;
 
 const MAX_FILES_PER_BATCH: usize = 50; // Limit batch size to avoid huge messages
+const MAX_MESSAGES_PER_BATCH: usize = 100;
+const MAX_MESSAGES_PER_SYNC: i64 = 500; // The rest is picked up on the next check-in
+const MAX_REMOVED_IN_POLICY: i64 = 50; // Most recently removed nodes, older removals have long reached everyone
+const MAX_HOLDS_PER_CHECK_IN: i64 = 200; // Newest files first, keeps check-ins well below the gossip message size limit
+const HEARTBEAT_INTERVAL: i64 = 10; // Seconds between check-ins
+const MISSED_HEARTBEATS: i64 = 3;
+/// Seconds without a check-in after which a member counts as offline.
+pub const OFFLINE_AFTER: i64 = HEARTBEAT_INTERVAL * MISSED_HEARTBEATS;
+
+// Members heard from since the topic was joined, with the time of their last check-in
+pub(crate) type OnlineMembers = Arc<Mutex<HashMap<String, i64>>>;
+
+// Everything a topic subscription needs from the node, captured when the topic is joined
+#[derive(Clone)]
+pub struct SubscriptionContext {
+	pub db: Db,
+	pub endpoint: Endpoint,
+	pub user_info: UserInfo,
+	pub events: Arc<dyn EventSink>,
+}
 
 pub async fn subscribe(
 	mut receiver: GossipReceiver,
 	sender: GossipSender,
-	app_handle: tauri::AppHandle,
-	node_id: String,
+	ctx: SubscriptionContext,
 	topic_id: String,
+	connectivity: SharedConnectivity,
+	online: OnlineMembers,
 	cancel_token: CancellationToken,
 ) -> Result<()> {
 	let topic_id_copy = topic_id.clone();
@@ -42,25 +76,29 @@ pub async fn subscribe(
 		.joined()
 		.await
 		.map_err(|e| Error::GossipSubscription(format!("Failed to join gossip: {}", e)))?;
+	connectivity.lock().await.joined(receiver.neighbors());
 
-	let user_info = app_handle.state::<Mutex<UserInfo>>().lock().await.clone();
-
-	let state = app_handle.state::<Mutex<AppState>>();
-	let db = state.lock().await.db.clone();
-	_ = state;
+	ctx.events.emit(
+		TopicStatus::Online {
+			topic_id: topic_id.clone(),
+		}
+		.into(),
+	);
 
-	let mut check_in_task = Some(tauri::async_runtime::spawn(check_in_task(
-		user_info,
-		node_id,
+	let mut check_in_task = Some(tokio::spawn(check_in_task(
+		ctx.clone(),
 		topic_id.clone(),
 		sender.clone(),
-		db,
+		online.clone(),
 	)));
 
-	let mut subscription_handler_task = Some(tauri::async_runtime::spawn(subscription_handler(
+	let mut subscription_handler_task = Some(tokio::spawn(subscription_handler(
 		receiver,
-		app_handle,
+		sender,
+		ctx,
 		topic_id_copy,
+		online,
+		connectivity,
 	)));
 
 	tokio::select! {
@@ -107,235 +145,576 @@ pub async fn subscribe(
 
 async fn subscription_handler(
 	mut receiver: GossipReceiver,
-	app_handle: tauri::AppHandle,
+	topic_sender: GossipSender,
+	ctx: SubscriptionContext,
 	topic_id: String,
+	online: OnlineMembers,
+	connectivity: SharedConnectivity,
 ) -> Result<()> {
-	// Extract all needed state at the start to minimize locking
-	let (me, my_endpoint, my_node_id, db, topic_sender) = {
-		let user_info_state = app_handle.state::<Mutex<UserInfo>>();
-		let me = user_info_state.lock().await.clone();
-
-		let state = app_handle.state::<Mutex<AppState>>();
-		let state_guard = state.lock().await;
-		let my_endpoint = state_guard.comm.endpoint.clone();
-		let my_node_id = my_endpoint.node_id().to_string();
-		let db = state_guard.db.clone();
-		let topic_sender = state_guard.comm.topic_sender.clone().unwrap();
-
-		(me, my_endpoint, my_node_id, db, topic_sender)
+	let SubscriptionContext {
+		db,
+		endpoint: my_endpoint,
+		user_info: me,
+		events,
+	} = ctx;
+	let handler = MessageHandler {
+		db,
+		my_node_id: my_endpoint.node_id().to_string(),
+		my_endpoint,
+		me,
+		events,
+		topic_id,
+		topic_sender,
+		online,
 	};
 
 	while let Ok(event) = receiver.try_next().await {
+		track_connectivity(&connectivity, handler.events.as_ref(), &event).await;
 		if let Some(Event::Received(message)) = event {
-			// Improved error handling - don't crash on deserialization errors
-			let message_type = match serde_json::from_slice::<MessageType>(&message.content) {
-				Ok(msg_type) => msg_type,
+			// Drop anything that is malformed, not signed by the node it claims to come from, or replayed
+			let message_type = match SignedMessage::verify_and_decode(
+				&message.content,
+				&handler.topic_id,
+				chrono::Utc::now().timestamp(),
+			) {
+				Ok((_, msg_type)) => msg_type,
 				Err(e) => {
-					eprintln!("Failed to deserialize gossip message: {}", e);
+					eprintln!("Dropping gossip message: {}", e);
 					continue;
 				},
 			};
 
-			let to_be_emitted = match message_type {
-				MessageType::CheckIn(msg) => {
-					let target_node = &msg.metadata.sender;
-
-					// Update topic with new member
-					update_topic(
-						&db,
-						msg.data.topic_id.clone(),
-						target_node.clone(),
-						msg.metadata.user.clone(),
-					)
-					.await?;
-
-					// Check if we need to send files to the target node
-					if let Some(&latest) = msg.data.sync.get(&my_node_id) {
-						let files = db
-							.list_files(
-								topic_id.clone(),
-								Some(my_node_id.clone()),
-								Some(TsFilter {
-									timestamp: latest,
-									direction: TsDirection::Newer,
-								}),
-							)
-							.await?;
-
-						// Send files as a batch if there are any
-						if !files.is_empty() {
-							let my_node_addr = my_endpoint.node_addr().get().unwrap();
-
-							// Convert database files to model files
-							let batch_files: Vec<model::File> = files
-								.iter()
-								.rev() // Send newest first
-								.map(|file| {
-									let hash = iroh_blobs::Hash::from_str(&file.hash).unwrap();
-									let hash_and_format = iroh_blobs::HashAndFormat::from(hash);
-									let ticket = BlobTicket::new(
-										my_node_addr.clone(),
-										hash_and_format.hash,
-										hash_and_format.format,
-									);
-
-									model::File::new(file.name.clone(), ticket.to_string(), file.size, file.shared_at)
-								})
-								.collect();
-
-							// Send files in chunks if too many
-							for chunk in batch_files.chunks(MAX_FILES_PER_BATCH) {
-								let metadata = model::Metadata::new(me.clone(), my_node_id.clone(), None);
-								let batch_message =
-									model::Message::new(FileBatch::new(chunk.to_vec(), target_node.clone()), metadata);
-								let message = MessageType::FileBatch(batch_message);
-
-								// Send batch message
-								if let Ok(serialized) = serde_json::to_vec(&message) {
-									topic_sender.broadcast(serialized.into()).await.ok();
-								}
-							}
-						}
-					}
-
-					Some(
-						serde_json::json!({
-							"type": "check_in",
-							"sender": target_node,
-							"meta": msg.metadata.user
-						})
-						.to_string(),
-					)
-				},
-				MessageType::Chat(msg) => Some(
-					serde_json::json!({
-						"type": "chat",
-						"sender": msg.metadata.sender,
-						"content": msg.data.content,
-					})
-					.to_string(),
+			// A message that can not be handled is dropped, the topic goes on with the next one
+			let sender = message_type.metadata().sender.clone();
+			match handler.handle(message_type).await {
+				Ok(Some(event)) => handler.events.emit(event.into()),
+				Ok(None) => (),
+				Err(e) => eprintln!(
+					"Failed to handle message from {} in {}: {}",
+					sender, handler.topic_id, e
 				),
-				MessageType::File(msg) => {
-					let file = msg.data;
-					let metadata = msg.metadata;
+			}
+		}
+	}
+	Ok(())
+}
+
+// What handling a topic's messages needs, taken apart from the subscription so one message can fail on its own
+struct MessageHandler {
+	db: Db,
+	my_endpoint: Endpoint,
+	my_node_id: String,
+	me: UserInfo,
+	events: Arc<dyn EventSink>,
+	topic_id: String,
+	topic_sender: GossipSender,
+	online: OnlineMembers,
+}
+
+impl MessageHandler {
+	async fn handle(&self, message_type: MessageType) -> Result<Option<GossipEvent>> {
+		let Self {
+			db,
+			my_endpoint,
+			my_node_id,
+			me,
+			events,
+			topic_id,
+			topic_sender,
+			online,
+		} = self;
+
+		// Banned nodes are ignored altogether, check-ins included
+		if db.is_banned(topic_id, &message_type.metadata().sender).await? {
+			return Ok(None);
+		}
 
-					// Parse blob ticket
-					let ticket = match file.blob_ticket.parse::<BlobTicket>() {
-						Ok(ticket) => ticket,
+		// Only members take part, a newcomer becomes one with its first check-in
+		if !matches!(message_type, MessageType::CheckIn(_))
+			&& !db.is_member(topic_id, &message_type.metadata().sender).await?
+		{
+			return Ok(None);
+		}
+
+		// Read-only members follow the topic without posting to it
+		if matches!(
+			message_type,
+			MessageType::Chat(_) | MessageType::File(_) | MessageType::FileBatch(_) | MessageType::ChatBatch(_)
+		) && !db.get_role(topic_id, &message_type.metadata().sender).await?.can_post()
+		{
+			return Ok(None);
+		}
+
+		let event = match message_type {
+			MessageType::CheckIn(msg) => {
+				let target_node = &msg.metadata.sender;
+				// Admission is decided for this topic, a check-in naming another one could use it to get in there
+				if msg.data.topic_id != *topic_id {
+					eprintln!(
+						"Dropping check-in from {} for {} received in {}",
+						target_node, msg.data.topic_id, topic_id
+					);
+					return Ok(None);
+				}
+
+				let topic = db.get_topic_by_topic_id(topic_id.clone()).await?;
+				if !db.is_member(topic_id, target_node).await? {
+					let grant = match check_grant(db, &topic, target_node, msg.data.grant.as_deref()).await {
+						Ok(grant) => grant,
 						Err(e) => {
-							eprintln!("Failed to parse blob ticket: {}", e);
-							continue;
+							eprintln!("Not admitting {} to {}: {}", target_node, topic_id, e);
+							return Ok(None);
 						},
 					};
 
-					// Check if file already exists
-					if db.get_file_by_hash(ticket.hash().to_string()).await.is_err() {
-						let new_file = db
-							.create_file(File::new(
-								metadata.sender.clone(),
-								topic_id.clone(),
-								ticket.hash().to_string(),
-								file.file_name.clone(),
-								None,
-								file.size,
-								ticket.format().to_string(),
-								FileStatus::Shared,
-								file.shared_at,
-							))
-							.await?;
+					// Only those who can moderate queue the request, the others wait for its approval.
+					// The ticket is redeemed once the request is approved, a pending one does not use it up
+					if db.requires_approval(topic_id).await? {
+						if db.get_role(topic_id, my_node_id).await?.can_moderate()
+							&& db
+								.create_join_request(topic_id, target_node, &msg.metadata.user, grant)
+								.await?
+						{
+							events.emit(
+								GossipEvent::JoinRequested {
+									topic_id: topic_id.clone(),
+									sender: target_node.clone(),
+									meta: msg.metadata.user.clone(),
+								}
+								.into(),
+							);
+						}
+						return Ok(None);
+					}
+					if let Err(e) = redeem_grant(db, target_node, grant).await {
+						eprintln!("Not admitting {} to {}: {}", target_node, topic_id, e);
+						return Ok(None);
+					}
+				}
+
+				// Update topic with new member
+				update_topic(db, topic_id.clone(), target_node.clone(), msg.metadata.user.clone()).await?;
+
+				let now = chrono::Utc::now().timestamp();
+				db.set_last_seen(topic_id, target_node, now).await?;
+				db.set_blob_holders(topic_id, target_node, &msg.data.holds).await?;
+				if online.lock().await.insert(target_node.clone(), now).is_none() {
+					events.emit(
+						Presence::Online {
+							topic_id: topic_id.clone(),
+							node_id: target_node.clone(),
+							last_seen: now,
+						}
+						.into(),
+					);
+				}
+
+				// The owner's member list is taken as is, it covers members that joined after our ticket was issued.
+				// Other members judge newcomers with what they know, so their lists are not trusted
+				if *target_node == topic.owner {
+					let mut vouches = true;
+					if let Some(policy) = msg.data.policy {
+						// A former owner's list is no longer the one to go by
+						vouches = policy.owner.is_empty() || policy.owner == *target_node;
+						for event in apply_policy(db, topic_id, target_node, *policy).await? {
+							events.emit(event.into());
+						}
+					}
+					if vouches {
+						// Newcomers still waiting here were approved by the owner
+						let pending = db.list_join_requests(topic_id).await?;
+						for member in msg.data.members.iter().filter(|member| **member != *my_node_id) {
+							if pending.iter().any(|request| request.node_id == *member) {
+								let approval = Moderation::ApproveJoin {
+									node_id: member.clone(),
+								};
+								apply_moderation(db, topic_id, approval).await?;
+							} else {
+								add_vouched_member(db, topic_id.clone(), member.clone()).await?;
+							}
+						}
+					}
+				}
 
-						Some(
-							serde_json::json!({
-								"type": "file",
-								"file": new_file,
+				// Check if we need to send files to the target node
+				if let Some(&latest) = msg.data.sync.get(my_node_id) {
+					let files = db
+						.list_files(
+							topic_id.clone(),
+							Some(my_node_id.clone()),
+							Some(TsFilter {
+								timestamp: latest,
+								direction: TsDirection::Newer,
+							}),
+						)
+						.await?;
+
+					// Send files as a batch if there are any
+					if !files.is_empty() {
+						let my_node_addr = my_endpoint
+							.node_addr()
+							.get()
+							.unwrap_or_else(|| NodeAddr::new(my_endpoint.node_id()));
+
+						// Convert database files to model files
+						let batch_files: Vec<model::File> = files
+							.iter()
+							.rev() // Send newest first
+							.map(|file| {
+								let hash = iroh_blobs::Hash::from_str(&file.hash).unwrap();
+								// A shared directory goes out as the collection it was shared as
+								let hash_and_format = if file.format == BlobFormat::HashSeq.to_string() {
+									iroh_blobs::HashAndFormat::hash_seq(hash)
+								} else {
+									iroh_blobs::HashAndFormat::from(hash)
+								};
+								let ticket =
+									BlobTicket::new(my_node_addr.clone(), hash_and_format.hash, hash_and_format.format);
+
+								model::File::new(file.name.clone(), ticket.to_string(), file.size, file.shared_at)
 							})
-							.to_string(),
+							.collect();
+
+						// Send files in chunks if too many
+						for chunk in batch_files.chunks(MAX_FILES_PER_BATCH) {
+							let metadata = model::Metadata::new(me.clone(), my_node_id.clone(), None);
+							let batch_message =
+								model::Message::new(FileBatch::new(chunk.to_vec(), target_node.clone()), metadata);
+							let message = MessageType::FileBatch(batch_message);
+
+							// Send batch message
+							if let Ok(serialized) =
+								SignedMessage::sign_and_encode(my_endpoint.secret_key(), topic_id, &message)
+							{
+								topic_sender.broadcast(serialized.into()).await.ok();
+							}
+						}
+					}
+				}
+
+				// Check if we need to send chat history to the target node.
+				// Members replay their own messages, and the ones of members that are away so the target does not
+				// wait for them to come back. Messages sent in the same second as the target's latest one only go
+				// again when it stored fewer of them than this node, the ones it has are skipped on its side
+				let online_now = online.lock().await;
+				let authors: Vec<String> = msg
+					.data
+					.chat_sync
+					.keys()
+					.filter(|author| {
+						**author == *my_node_id || (*author != target_node && !online_now.contains_key(*author))
+					})
+					.cloned()
+					.collect();
+				drop(online_now);
+				let stored = db.get_latest_message_timestamps_by_members(topic_id, &authors).await?;
+				for author in authors {
+					let latest = msg.data.chat_sync[&author];
+					let seen = msg.data.chat_seen.get(&author).copied().unwrap_or(0);
+					let Some(&(my_latest, my_count)) = stored.get(&author) else {
+						continue;
+					};
+					if my_latest < latest || (my_latest == latest && my_count <= seen) {
+						continue;
+					}
+
+					let messages = db
+						.list_messages_by_node(
+							topic_id.clone(),
+							author.clone(),
+							TsFilter {
+								timestamp: latest,
+								direction: TsDirection::NewerOrSame,
+							},
+							MAX_MESSAGES_PER_SYNC,
 						)
-					} else {
-						None
+						.await?;
+					let same_second = messages.iter().take_while(|message| message.sent_at == latest).count();
+					let skip = if same_second as i64 > seen { 0 } else { same_second };
+					let records: Vec<ChatRecord> = messages
+						.into_iter()
+						.skip(skip)
+						.map(|message| ChatRecord::new(message.content, message.sent_at))
+						.collect();
+
+					// Oldest first, so a capped sync still advances the target's latest timestamp
+					let batch_author = if author == *my_node_id { String::new() } else { author };
+					for chunk in records.chunks(MAX_MESSAGES_PER_BATCH) {
+						let metadata = model::Metadata::new(me.clone(), my_node_id.clone(), None);
+						let batch = ChatBatch::new(chunk.to_vec(), target_node.clone(), batch_author.clone());
+						let message = MessageType::ChatBatch(model::Message::new(batch, metadata));
+
+						if let Ok(serialized) =
+							SignedMessage::sign_and_encode(my_endpoint.secret_key(), topic_id, &message)
+						{
+							topic_sender.broadcast(serialized.into()).await.ok();
+						}
 					}
-				},
-				MessageType::FileBatch(msg) => {
-					let batch = msg.data;
-					let metadata = msg.metadata;
-					let mut created_files = Vec::new();
-
-					// Only process batch if it was intended for us or everyone
-					if batch.sync_request_node == my_node_id || batch.sync_request_node.is_empty() {
-						for file in batch.files {
-							// Parse blob ticket
-							let ticket = match file.blob_ticket.parse::<BlobTicket>() {
-								Ok(ticket) => ticket,
-								Err(e) => {
-									eprintln!("Failed to parse blob ticket in batch: {}", e);
-									continue;
-								},
-							};
-
-							// Check if file already exists
-							if db.get_file_by_hash(ticket.hash().to_string()).await.is_err() {
-								match db
-									.create_file(File::new(
-										metadata.sender.clone(),
-										topic_id.clone(),
-										ticket.hash().to_string(),
-										file.file_name.clone(),
-										None,
-										file.size,
-										ticket.format().to_string(),
-										FileStatus::Shared,
-										file.shared_at,
-									))
-									.await
-								{
-									Ok(new_file) => created_files.push(new_file),
-									Err(e) => eprintln!("Failed to create file from batch: {}", e),
-								}
+				}
+
+				Some(GossipEvent::CheckIn {
+					topic_id: topic_id.clone(),
+					sender: target_node.clone(),
+					meta: msg.metadata.user,
+				})
+			},
+			MessageType::Chat(msg) => {
+				if let Err(e) = db
+					.create_message(Message::new(
+						msg.metadata.sender.clone(),
+						topic_id.clone(),
+						msg.data.content.clone(),
+						msg.metadata.ts,
+					))
+					.await
+				{
+					eprintln!("Failed to store chat message: {}", e);
+				}
+
+				Some(GossipEvent::Chat {
+					topic_id: topic_id.clone(),
+					sender: msg.metadata.sender,
+					content: msg.data.content,
+					sent_at: msg.metadata.ts,
+				})
+			},
+			MessageType::File(msg) => {
+				let file = msg.data;
+				let metadata = msg.metadata;
+
+				// Parse blob ticket
+				let ticket = match file.blob_ticket.parse::<BlobTicket>() {
+					Ok(ticket) => ticket,
+					Err(e) => {
+						eprintln!("Failed to parse blob ticket: {}", e);
+						return Ok(None);
+					},
+				};
+
+				// Check if the sender's file is already in this topic's catalog
+				if db
+					.get_file(topic_id, &ticket.hash().to_string(), &metadata.sender)
+					.await?
+					.is_none()
+				{
+					let new_file = db
+						.create_file(File::new(
+							metadata.sender.clone(),
+							topic_id.clone(),
+							ticket.hash().to_string(),
+							file.file_name.clone(),
+							None,
+							file.size,
+							ticket.format().to_string(),
+							FileStatus::Shared,
+							file.shared_at,
+						))
+						.await?;
+
+					Some(GossipEvent::File {
+						topic_id: topic_id.clone(),
+						file: new_file,
+					})
+				} else {
+					None
+				}
+			},
+			MessageType::FileBatch(msg) => {
+				let batch = msg.data;
+				let metadata = msg.metadata;
+				let mut created_files = Vec::new();
+
+				// Only process batch if it was intended for us or everyone
+				if batch.sync_request_node == *my_node_id || batch.sync_request_node.is_empty() {
+					for file in batch.files {
+						// Parse blob ticket
+						let ticket = match file.blob_ticket.parse::<BlobTicket>() {
+							Ok(ticket) => ticket,
+							Err(e) => {
+								eprintln!("Failed to parse blob ticket in batch: {}", e);
+								continue;
+							},
+						};
+
+						// Check if the sender's file is already in this topic's catalog
+						if db
+							.get_file(topic_id, &ticket.hash().to_string(), &metadata.sender)
+							.await?
+							.is_none()
+						{
+							match db
+								.create_file(File::new(
+									metadata.sender.clone(),
+									topic_id.clone(),
+									ticket.hash().to_string(),
+									file.file_name.clone(),
+									None,
+									file.size,
+									ticket.format().to_string(),
+									FileStatus::Shared,
+									file.shared_at,
+								))
+								.await
+							{
+								Ok(new_file) => created_files.push(new_file),
+								Err(e) => eprintln!("Failed to create file from batch: {}", e),
 							}
 						}
+					}
 
-						if !created_files.is_empty() {
-							Some(
-								serde_json::json!({
-									"type": "file_batch",
-									"files": created_files,
-									"sender": metadata.sender
-								})
-								.to_string(),
-							)
-						} else {
-							None
-						}
+					if !created_files.is_empty() {
+						Some(GossipEvent::FileBatch {
+							topic_id: topic_id.clone(),
+							files: created_files,
+							sender: metadata.sender,
+						})
 					} else {
 						None
 					}
-				},
-			};
+				} else {
+					None
+				}
+			},
+			MessageType::ChatBatch(msg) => {
+				let batch = msg.data;
+				let author = if batch.author.is_empty() {
+					msg.metadata.sender
+				} else {
+					batch.author
+				};
+				let mut created_messages = Vec::new();
+
+				// Only process batch if it was intended for us or everyone, relayed messages need an author that
+				// still posts to the topic
+				let for_us = batch.sync_request_node == *my_node_id || batch.sync_request_node.is_empty();
+				if for_us && db.get_role(topic_id, &author).await?.can_post() {
+					for record in batch.messages {
+						match db
+							.sync_message(Message::new(
+								author.clone(),
+								topic_id.clone(),
+								record.content,
+								record.sent_at,
+							))
+							.await
+						{
+							Ok(Some(new_message)) => created_messages.push(new_message),
+							Ok(None) => (),
+							Err(e) => eprintln!("Failed to store chat message from batch: {}", e),
+						}
+					}
+				}
 
-			if let Some(to_be_emitted) = to_be_emitted {
-				if let Err(e) = app_handle.emit("gossip-message", to_be_emitted) {
-					eprintln!("Failed to emit gossip message: {}", e);
+				if !created_messages.is_empty() {
+					Some(GossipEvent::ChatBatch {
+						topic_id: topic_id.clone(),
+						messages: created_messages,
+						sender: author,
+					})
+				} else {
+					None
 				}
-			}
-		}
+			},
+			MessageType::TicketRevoked(msg) => {
+				// Stored under the sender, so it only affects tickets the sender issued
+				if let Err(e) = db.revoke_ticket(&msg.data.nonce, &msg.metadata.sender).await {
+					eprintln!("Failed to store ticket revocation: {}", e);
+				}
+				None
+			},
+			MessageType::Moderation(msg) => {
+				let topic = db.get_topic_by_topic_id(topic_id.clone()).await?;
+				let ModerationUpdate { seq, action } = msg.data;
+				if let Err(e) = check_moderation(db, &topic, &msg.metadata.sender, &action).await {
+					eprintln!(
+						"Ignoring moderation of {} from {}: {}",
+						topic_id, msg.metadata.sender, e
+					);
+					return Ok(None);
+				}
+				// An action sent again after later ones would undo them
+				if !db.advance_moderation_seq(topic_id, seq).await? {
+					eprintln!(
+						"Ignoring moderation {} of {} from {}, a later one was applied",
+						seq, topic_id, msg.metadata.sender
+					);
+					return Ok(None);
+				}
+				apply_moderation(db, topic_id, action).await?
+			},
+			MessageType::Leave(msg) => {
+				let node_id = msg.metadata.sender;
+				if let Some(last_seen) = online.lock().await.remove(&node_id) {
+					events.emit(
+						Presence::Offline {
+							topic_id: topic_id.clone(),
+							node_id: node_id.clone(),
+							last_seen,
+						}
+						.into(),
+					);
+				}
+
+				// The owner can not leave for good, it has to hand the topic over first
+				let Leave { permanent, nonces } = msg.data;
+				let topic = db.get_topic_by_topic_id(topic_id.clone()).await?;
+				let permanent = permanent && node_id != topic.owner;
+				if permanent {
+					db.spend_tickets(topic_id, &node_id, &nonces).await?;
+					db.remove_member(topic_id, &node_id).await?;
+					// Its files that no other member holds can not be downloaded anymore, so they are gone with it
+					db.delete_undownloaded_files(topic_id, &node_id).await?;
+				}
+				Some(GossipEvent::MemberLeft {
+					topic_id: topic_id.clone(),
+					node_id,
+					permanent,
+				})
+			},
+		};
+		Ok(event)
+	}
+}
+
+// Keeps the topic's connectivity up to date with the swarm membership events the handler receives
+async fn track_connectivity(connectivity: &SharedConnectivity, events: &dyn EventSink, event: &Option<Event>) {
+	let swarm_events = match event {
+		Some(Event::NeighborUp(node_id)) => vec![connectivity.lock().await.neighbor_up(*node_id)],
+		Some(Event::NeighborDown(node_id)) => connectivity.lock().await.neighbor_down(*node_id),
+		Some(Event::Lagged) => vec![connectivity.lock().await.lagged()],
+		Some(Event::Received(_)) | None => Vec::new(),
+	};
+	for swarm_event in swarm_events {
+		events.emit(swarm_event.into());
 	}
-	Ok(())
 }
 
 async fn check_in_task(
-	user_info: UserInfo,
-	my_node_id: String,
+	ctx: SubscriptionContext,
 	topic_id: String,
 	sender: GossipSender,
-	db: Db,
+	online: OnlineMembers,
 ) -> Result<()> {
+	let SubscriptionContext {
+		db,
+		endpoint,
+		user_info,
+		events,
+	} = ctx;
+	let secret_key = endpoint.secret_key();
+	let my_node_id = endpoint.node_id().to_string();
 	let metadata = model::Metadata::new(user_info, my_node_id.clone(), None);
-	let mut check_in = model::Message::new(CheckIn::new(topic_id.clone(), HashMap::new()), metadata);
+	let mut check_in = model::Message::new(CheckIn::new(topic_id.clone(), HashMap::new(), HashMap::new()), metadata);
+	// Members that do not know this node yet admit it with the ticket it joined with
+	check_in.data.grant = db.get_join_grant(&topic_id).await?.map(Box::new);
+	// The others take over the owner's member list and policy, so the owner always sends them fresh
+	let mut is_owner = false;
 
 	// Caching variables to reduce DB calls
 	let mut cached_members: Vec<String> = Vec::new();
 	let mut last_member_refresh = 0i64;
 	let mut sync_map = HashMap::new();
+	let mut chat_sync_map = HashMap::new();
 
 	// Cache refresh interval (60 seconds)
 	const MEMBER_CACHE_REFRESH_INTERVAL: i64 = 60;
@@ -345,28 +724,57 @@ async fn check_in_task(
 		check_in.metadata.ts = current_time;
 
 		// Refresh member cache if it's empty or stale
-		let should_refresh_members =
-			cached_members.is_empty() || (current_time - last_member_refresh) > MEMBER_CACHE_REFRESH_INTERVAL;
+		let should_refresh_members = is_owner
+			|| cached_members.is_empty()
+			|| (current_time - last_member_refresh) > MEMBER_CACHE_REFRESH_INTERVAL;
 
 		if should_refresh_members {
-			let topic = db.get_topic_by_topic_id(topic_id.clone()).await?;
-			let new_members = topic.get_peers();
+			let members = db.list_members(&topic_id).await?;
+			is_owner = members
+				.iter()
+				.any(|member| member.node_id == my_node_id && member.role == Role::Owner);
+			let new_members: Vec<String> = members.iter().map(|member| member.node_id.clone()).collect();
+			check_in.data.policy = None;
+			if is_owner {
+				check_in.data.policy = Some(Box::new(Policy {
+					require_approval: db.requires_approval(&topic_id).await?,
+					banned: db.list_banned(&topic_id).await?,
+					roles: db.list_roles(&topic_id).await?,
+					owner: my_node_id.clone(),
+					removed: db.list_removed(&topic_id, MAX_REMOVED_IN_POLICY).await?,
+				}));
+			} else if let Some(owner) = members.iter().find(|member| member.role == Role::Owner) {
+				// Members that missed a transfer still take a former owner, now an admin, for the owner
+				if members
+					.iter()
+					.any(|member| member.node_id == my_node_id && member.role == Role::Admin)
+				{
+					check_in.data.policy = Some(Box::new(Policy {
+						owner: owner.node_id.clone(),
+						..Default::default()
+					}));
+				}
+			}
 
 			// Check if membership changed
 			let members_changed =
 				new_members.len() != cached_members.len() || !new_members.iter().all(|m| cached_members.contains(m));
 
 			if members_changed {
+				check_in.data.members = new_members.clone();
 				cached_members = new_members;
 				// Pre-allocate HashMap with correct capacity
 				sync_map = HashMap::with_capacity(cached_members.len());
+				chat_sync_map = HashMap::with_capacity(cached_members.len());
 			}
 
 			last_member_refresh = current_time;
 		}
 
-		// Clear the sync map for fresh data
+		// Clear the sync maps for fresh data
 		sync_map.clear();
+		chat_sync_map.clear();
+		check_in.data.chat_seen.clear();
 
 		// Filter out our own node_id from members
 		let other_members: Vec<String> = cached_members
@@ -385,17 +793,48 @@ async fn check_in_task(
 			for (member, timestamp) in timestamps {
 				sync_map.insert(member, timestamp);
 			}
+
+			let chat_timestamps = db
+				.get_latest_message_timestamps_by_members(&topic_id, &other_members)
+				.await?;
+			for (member, (latest, count)) in chat_timestamps {
+				chat_sync_map.insert(member.clone(), latest);
+				check_in.data.chat_seen.insert(member, count);
+			}
 		}
 
 		// Update the check_in data
 		check_in.data.sync = sync_map.clone();
+		check_in.data.chat_sync = chat_sync_map.clone();
+		check_in.data.holds = db.list_held_hashes(&topic_id, MAX_HOLDS_PER_CHECK_IN).await?;
 
-		// Send the check-in message
+		// Send the check-in message, a failed one is sent again with the next heartbeat
 		let check_in_msg = MessageType::CheckIn(check_in.clone());
-		if let Ok(message) = serde_json::to_vec(&check_in_msg) {
-			sender.broadcast(message.into()).await.ok();
+		match SignedMessage::sign_and_encode(secret_key, &topic_id, &check_in_msg) {
+			Ok(message) => {
+				if let Err(e) = sender.broadcast(message.into()).await {
+					eprintln!("Failed to send check-in to {}: {}", topic_id, e);
+				}
+			},
+			Err(e) => eprintln!("Failed to sign check-in to {}: {}", topic_id, e),
 		}
 
-		tokio::time::sleep(std::time::Duration::from_secs(10)).await;
+		// Members that missed their last few check-ins went offline, they come back online with the next one
+		online.lock().await.retain(|node_id, last_seen| {
+			let is_online = current_time - *last_seen <= OFFLINE_AFTER;
+			if !is_online {
+				events.emit(
+					Presence::Offline {
+						topic_id: topic_id.clone(),
+						node_id: node_id.clone(),
+						last_seen: *last_seen,
+					}
+					.into(),
+				);
+			}
+			is_online
+		});
+
+		tokio::time::sleep(std::time::Duration::from_secs(HEARTBEAT_INTERVAL as u64)).await;
 	}
 }
@@ -1,7 +1,15 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
+use iroh::{NodeId, SecretKey};
+use iroh_base::Signature;
 use serde::{Deserialize, Serialize};
 
+use crate::{
+	comm::ticket::Grant,
+	database::member::Role,
+	error::{Error, Result},
+};
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum MessageType {
 	// This type will be sent by each node in the topic as a heartbeat
@@ -16,6 +24,107 @@ pub enum MessageType {
 
 	// This type will be sent by any node that wants to share multiple files at once for syncing
 	FileBatch(Message<FileBatch>),
+
+	// This type will be sent by any node that wants to share its chat history with a node that missed it
+	ChatBatch(Message<ChatBatch>),
+
+	// This type will be sent by the issuer of a ticket so members stop admitting newcomers with it
+	TicketRevoked(Message<TicketRevoked>),
+
+	// This type will be sent by the topic owner to remove, ban or let in members, every member applies it
+	Moderation(Message<ModerationUpdate>),
+
+	// This type will be sent by a member right before it leaves the topic, for a while or for good
+	Leave(Message<Leave>),
+}
+
+impl MessageType {
+	pub fn metadata(&self) -> &Metadata {
+		match self {
+			MessageType::CheckIn(msg) => &msg.metadata,
+			MessageType::Chat(msg) => &msg.metadata,
+			MessageType::File(msg) => &msg.metadata,
+			MessageType::FileBatch(msg) => &msg.metadata,
+			MessageType::ChatBatch(msg) => &msg.metadata,
+			MessageType::TicketRevoked(msg) => &msg.metadata,
+			MessageType::Moderation(msg) => &msg.metadata,
+			MessageType::Leave(msg) => &msg.metadata,
+		}
+	}
+}
+
+/// Seconds a signed message stays valid, older ones are taken for replays and dropped.
+pub const MAX_MESSAGE_AGE: i64 = 300;
+// Seconds a sender's clock may be ahead before its messages are dropped as well
+const MAX_CLOCK_SKEW: i64 = 60;
+
+// Every gossip message travels in this envelope, signed with the secret key of the sending node.
+// Receivers only accept it if the signature matches the node claimed in `Metadata.sender`.
+// The signature also covers the topic and the time of signing, so a message can not be replayed in another topic
+// or long after it was sent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedMessage {
+	from: NodeId,
+	topic_id: String,
+	ts: i64,
+	data: Vec<u8>, // Serialized `MessageType`
+	signature: Signature,
+}
+
+impl SignedMessage {
+	pub fn sign_and_encode(secret_key: &SecretKey, topic_id: &str, message: &MessageType) -> Result<Vec<u8>> {
+		let data = serde_json::to_vec(message)?;
+		let ts = chrono::Utc::now().timestamp();
+		let signature = secret_key.sign(&signed_bytes(topic_id, ts, &data)?);
+		let signed_message = Self {
+			from: secret_key.public(),
+			topic_id: topic_id.to_string(),
+			ts,
+			data,
+			signature,
+		};
+		Ok(serde_json::to_vec(&signed_message)?)
+	}
+
+	/// Decodes a message received in `topic_id`, it has to be signed for that topic within [`MAX_MESSAGE_AGE`] of `now`.
+	pub fn verify_and_decode(bytes: &[u8], topic_id: &str, now: i64) -> Result<(NodeId, MessageType)> {
+		let signed_message: Self = serde_json::from_slice(bytes)?;
+		signed_message
+			.from
+			.verify(
+				&signed_bytes(&signed_message.topic_id, signed_message.ts, &signed_message.data)?,
+				&signed_message.signature,
+			)
+			.map_err(|e| Error::Signature(format!("Invalid signature: {}", e)))?;
+
+		if signed_message.topic_id != topic_id {
+			return Err(Error::Signature(format!(
+				"Message was signed for topic {}",
+				signed_message.topic_id
+			)));
+		}
+		if now - signed_message.ts > MAX_MESSAGE_AGE || signed_message.ts - now > MAX_CLOCK_SKEW {
+			return Err(Error::Signature(format!(
+				"Message was signed at {}, too far from {}",
+				signed_message.ts, now
+			)));
+		}
+
+		let message: MessageType = serde_json::from_slice(&signed_message.data)?;
+		if message.metadata().sender != signed_message.from.to_string() {
+			return Err(Error::Signature(format!(
+				"Message claims sender {} but was signed by {}",
+				message.metadata().sender,
+				signed_message.from
+			)));
+		}
+		Ok((signed_message.from, message))
+	}
+}
+
+// What the signature covers
+fn signed_bytes(topic_id: &str, ts: i64, data: &[u8]) -> Result<Vec<u8>> {
+	Ok(serde_json::to_vec(&(topic_id, ts, data))?)
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
@@ -58,12 +167,33 @@ pub struct UserInfo {
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct CheckIn {
 	pub topic_id: String,
-	pub sync: HashMap<String, i64>,
+	pub sync: HashMap<String, i64>, // Latest file timestamp per member
+	#[serde(default)]
+	pub chat_sync: HashMap<String, i64>, // Latest chat message timestamp per member
+	#[serde(default)]
+	pub chat_seen: HashMap<String, i64>, // How many of the member's messages were stored at its latest timestamp
+	#[serde(default)]
+	pub grant: Option<Box<Grant>>, // The ticket grant the sender joined with, checked by members that do not know it yet
+	#[serde(default)]
+	pub members: Vec<String>, // Members known to the sender, taken over by the others when it is the topic owner
+	#[serde(default)]
+	pub policy: Option<Box<Policy>>, // Sent by the topic owner and former owners, so members that missed a moderation message catch up
+	#[serde(default)]
+	pub holds: Vec<String>, // Hashes of the topic's files the sender downloaded, the others can fetch them from it too
 }
 
 impl CheckIn {
-	pub fn new(topic_id: String, sync: HashMap<String, i64>) -> Self {
-		Self { topic_id, sync }
+	pub fn new(topic_id: String, sync: HashMap<String, i64>, chat_sync: HashMap<String, i64>) -> Self {
+		Self {
+			topic_id,
+			sync,
+			chat_sync,
+			chat_seen: HashMap::new(),
+			grant: None,
+			members: Vec::new(),
+			policy: None,
+			holds: Vec::new(),
+		}
 	}
 }
 
@@ -111,3 +241,235 @@ impl FileBatch {
 		}
 	}
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct ChatRecord {
+	pub content: String,
+	pub sent_at: i64,
+}
+
+impl ChatRecord {
+	pub fn new(content: String, sent_at: i64) -> Self {
+		Self { content, sent_at }
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct ChatBatch {
+	pub messages: Vec<ChatRecord>,
+	pub sync_request_node: String, // The node that requested this batch
+	#[serde(default)]
+	pub author: String, // Who wrote the messages, empty when it is the sender
+}
+
+impl ChatBatch {
+	pub fn new(messages: Vec<ChatRecord>, sync_request_node: String, author: String) -> Self {
+		Self {
+			messages,
+			sync_request_node,
+			author,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct TicketRevoked {
+	pub nonce: String,
+}
+
+impl TicketRevoked {
+	pub fn new(nonce: String) -> Self {
+		Self { nonce }
+	}
+}
+
+/// A member going away, a permanent leave takes it out of the topic like a removal would.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct Leave {
+	pub permanent: bool,
+	#[serde(default)]
+	pub nonces: Vec<String>, // Tickets the member joined with, spent on a permanent leave
+}
+
+/// Membership change, members only apply it when the sender's role allows it.
+/// Roles and ownership are up to the owner, the rest is open to admins as well.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(tag = "action", rename_all = "snake_case", rename_all_fields = "camelCase")]
+pub enum Moderation {
+	// The node can come back with a ticket it did not join with before
+	RemoveMember { node_id: String, nonces: Vec<String> },
+	// The node is removed and its gossip ignored until it is unbanned
+	Ban { node_id: String },
+	Unban { node_id: String },
+	// Newcomers with a valid ticket wait for ApproveJoin
+	RequireApproval { enabled: bool },
+	ApproveJoin { node_id: String },
+	// The node needs a ticket it did not present before to ask again
+	DenyJoin { node_id: String, nonces: Vec<String> },
+	SetRole { node_id: String, role: Role },
+	// The previous owner stays on as an admin
+	TransferOwnership { node_id: String },
+}
+
+/// A moderation action numbered in the order it was taken in the topic, members ignore ones not newer than the last.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct ModerationUpdate {
+	pub seq: i64,
+	pub action: Moderation,
+}
+
+/// What the topic owner enforces, sent along with its check-ins.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub struct Policy {
+	pub require_approval: bool,
+	pub banned: Vec<String>,
+	#[serde(default)]
+	pub roles: BTreeMap<String, Role>, // Members that are not plain members, the owner left out
+	#[serde(default)]
+	pub owner: String, // Empty from owners that did not send it, a former owner only sends this
+	#[serde(default)]
+	pub removed: BTreeMap<String, Vec<String>>, // Nodes put out of the topic, with the tickets they can not come back with
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const TOPIC: &str = "topic";
+
+	fn now() -> i64 {
+		chrono::Utc::now().timestamp()
+	}
+
+	fn chat(sender: String, content: &str) -> MessageType {
+		let metadata = Metadata::new(UserInfo::default(), sender, Some(1_700_000_000));
+		MessageType::Chat(Message::new(Chat::new(content.to_string()), metadata))
+	}
+
+	fn tamper(encoded: &[u8], edit: impl FnOnce(&mut serde_json::Value)) -> Vec<u8> {
+		let mut envelope: serde_json::Value = serde_json::from_slice(encoded).unwrap();
+		edit(&mut envelope);
+		serde_json::to_vec(&envelope).unwrap()
+	}
+
+	#[test]
+	fn signed_message_round_trips() {
+		let secret_key = SecretKey::generate(rand::rngs::OsRng);
+		let message = chat(secret_key.public().to_string(), "hello");
+
+		let encoded = SignedMessage::sign_and_encode(&secret_key, TOPIC, &message).unwrap();
+		let (from, decoded) = SignedMessage::verify_and_decode(&encoded, TOPIC, now()).unwrap();
+
+		assert_eq!(from, secret_key.public());
+		assert_eq!(decoded, message);
+	}
+
+	#[test]
+	fn rejects_tampered_payload() {
+		let secret_key = SecretKey::generate(rand::rngs::OsRng);
+		let message = chat(secret_key.public().to_string(), "hello");
+		let encoded = SignedMessage::sign_and_encode(&secret_key, TOPIC, &message).unwrap();
+
+		let forged = chat(secret_key.public().to_string(), "send me your files");
+		let tampered = tamper(&encoded, |envelope| {
+			envelope["data"] = serde_json::to_value(serde_json::to_vec(&forged).unwrap()).unwrap();
+		});
+
+		assert!(matches!(
+			SignedMessage::verify_and_decode(&tampered, TOPIC, now()),
+			Err(Error::Signature(_))
+		));
+	}
+
+	#[test]
+	fn rejects_tampered_signature() {
+		let secret_key = SecretKey::generate(rand::rngs::OsRng);
+		let message = chat(secret_key.public().to_string(), "hello");
+		let encoded = SignedMessage::sign_and_encode(&secret_key, TOPIC, &message).unwrap();
+
+		let other_key = SecretKey::generate(rand::rngs::OsRng);
+		let tampered = tamper(&encoded, |envelope| {
+			envelope["signature"] = serde_json::to_value(other_key.sign(b"something else")).unwrap();
+		});
+
+		assert!(matches!(
+			SignedMessage::verify_and_decode(&tampered, TOPIC, now()),
+			Err(Error::Signature(_))
+		));
+	}
+
+	#[test]
+	fn rejects_impersonated_sender() {
+		let attacker = SecretKey::generate(rand::rngs::OsRng);
+		let victim = SecretKey::generate(rand::rngs::OsRng);
+
+		// Validly signed by the attacker, but claims to come from the victim
+		let message = chat(victim.public().to_string(), "hello");
+		let encoded = SignedMessage::sign_and_encode(&attacker, TOPIC, &message).unwrap();
+		assert!(matches!(
+			SignedMessage::verify_and_decode(&encoded, TOPIC, now()),
+			Err(Error::Signature(_))
+		));
+
+		// Swapping the envelope's signer for the victim breaks the signature instead
+		let tampered = tamper(&encoded, |envelope| {
+			envelope["from"] = serde_json::to_value(victim.public()).unwrap();
+		});
+		assert!(matches!(
+			SignedMessage::verify_and_decode(&tampered, TOPIC, now()),
+			Err(Error::Signature(_))
+		));
+	}
+
+	#[test]
+	fn rejects_message_signed_for_another_topic() {
+		let secret_key = SecretKey::generate(rand::rngs::OsRng);
+		let message = chat(secret_key.public().to_string(), "hello");
+		let encoded = SignedMessage::sign_and_encode(&secret_key, "other topic", &message).unwrap();
+
+		assert!(matches!(
+			SignedMessage::verify_and_decode(&encoded, TOPIC, now()),
+			Err(Error::Signature(_))
+		));
+
+		// Moving it over to the topic breaks the signature instead
+		let tampered = tamper(&encoded, |envelope| {
+			envelope["topic_id"] = serde_json::Value::from(TOPIC);
+		});
+		assert!(matches!(
+			SignedMessage::verify_and_decode(&tampered, TOPIC, now()),
+			Err(Error::Signature(_))
+		));
+	}
+
+	#[test]
+	fn rejects_replayed_message() {
+		let secret_key = SecretKey::generate(rand::rngs::OsRng);
+		let message = chat(secret_key.public().to_string(), "hello");
+		let encoded = SignedMessage::sign_and_encode(&secret_key, TOPIC, &message).unwrap();
+
+		let later = now() + MAX_MESSAGE_AGE + 1;
+		assert!(matches!(
+			SignedMessage::verify_and_decode(&encoded, TOPIC, later),
+			Err(Error::Signature(_))
+		));
+
+		// Making it look fresh breaks the signature
+		let tampered = tamper(&encoded, |envelope| {
+			envelope["ts"] = serde_json::Value::from(later);
+		});
+		assert!(matches!(
+			SignedMessage::verify_and_decode(&tampered, TOPIC, later),
+			Err(Error::Signature(_))
+		));
+	}
+
+	#[test]
+	fn rejects_unsigned_message() {
+		let secret_key = SecretKey::generate(rand::rngs::OsRng);
+		let message = chat(secret_key.public().to_string(), "hello");
+		let unsigned = serde_json::to_vec(&message).unwrap();
+
+		assert!(SignedMessage::verify_and_decode(&unsigned, TOPIC, now()).is_err());
+	}
+}
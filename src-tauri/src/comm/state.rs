@@ -1,29 +1,59 @@
-use std::path::PathBuf;
+use std::{
+	collections::HashMap,
+	path::{Path, PathBuf},
+};
 
+use anyhow::anyhow;
+use futures_lite::StreamExt;
 use iroh::{protocol::Router, Endpoint};
-use iroh_blobs::{store::fs::FsStore, BlobsProtocol};
+use iroh_blobs::{
+	api::proto::BlobStatus, hashseq::HashSeq, store::fs::FsStore, BlobFormat, BlobsProtocol, Hash, HashAndFormat,
+};
 use iroh_gossip::{api::GossipSender, net::Gossip};
-use tauri::async_runtime::JoinHandle;
+use tokio::task::JoinHandle;
 use tokio_util::sync::CancellationToken;
 
-use crate::{comm::endpoint::new_gossip, error::Result};
+use crate::{
+	comm::{
+		connectivity::{Connectivity, SharedConnectivity},
+		endpoint::new_gossip,
+		OnlineMembers,
+	},
+	error::{Error, Result},
+};
+
+// A live gossip subscription to one topic, with its own check-in and handler tasks
+pub(crate) struct TopicSubscription {
+	pub sender: GossipSender,
+	pub subscriber: JoinHandle<()>,
+	pub cancel_token: CancellationToken,
+	pub connectivity: SharedConnectivity,
+	pub online: OnlineMembers,
+}
+
+impl TopicSubscription {
+	fn stop(self) {
+		self.cancel_token.cancel();
+		self.subscriber.abort();
+	}
+}
 
 pub(crate) struct CommState {
 	pub endpoint: Endpoint,
 	pub gossip: Gossip,
 	router: Router,
 	pub blobs: BlobsProtocol,
-	pub topic_sender: Option<GossipSender>,
-	pub topic_subscriber: Option<JoinHandle<()>>,
-	pub topic_cancel_token: Option<CancellationToken>,
+	pub topics: HashMap<String, TopicSubscription>, // Keyed by topic_id
 	pub store: FsStore,
+	store_path: PathBuf,
 }
 
 impl CommState {
 	pub async fn init_from_endpoint(endpoint: Endpoint, store_path: PathBuf) -> Result<Self> {
 		let gossip = new_gossip(endpoint.clone()).await?;
 
-		let store = FsStore::load(store_path).await?;
+		compact_store(&store_path).await?;
+		let store = FsStore::load(&store_path).await?;
 		let blobs = BlobsProtocol::new(&store, endpoint.clone(), None);
 		let router = Router::builder(endpoint.clone())
 			.accept(iroh_gossip::ALPN, gossip.clone())
@@ -34,15 +64,92 @@ impl CommState {
 			endpoint,
 			gossip,
 			router,
-			topic_sender: None,
 			blobs,
-			topic_subscriber: None,
-			topic_cancel_token: None,
+			topics: HashMap::new(),
 			store,
+			store_path,
 		})
 	}
 
+	/// Brings gossip and blobs up again on a new endpoint, e.g. after the network configuration changed.
+	/// Every subscription is stopped, the ids of the topics that were joined are returned so they can be joined again.
+	pub async fn restart(&mut self, endpoint: Endpoint) -> Result<Vec<String>> {
+		let joined = self.leave_all();
+
+		// Shutting down the router also shuts down the store, so it is loaded again from disk
+		self.close().await;
+		*self = Self::init_from_endpoint(endpoint, self.store_path.clone()).await?;
+		Ok(joined)
+	}
+
+	// A subscription whose task has ended, e.g. because joining the swarm failed, no longer counts as joined
+	pub fn is_joined(&self, topic_id: &str) -> bool {
+		self.topics
+			.get(topic_id)
+			.is_some_and(|subscription| !subscription.subscriber.is_finished())
+	}
+
+	pub fn topic_sender(&self, topic_id: &str) -> Result<GossipSender> {
+		self.topics
+			.get(topic_id)
+			.filter(|subscription| !subscription.subscriber.is_finished())
+			.map(|subscription| subscription.sender.clone())
+			.ok_or_else(|| Error::Generic(anyhow!("Not joined to topic {}", topic_id)))
+	}
+
+	pub async fn connectivity(&self, topic_id: &str) -> Result<Connectivity> {
+		let subscription = self
+			.topics
+			.get(topic_id)
+			.filter(|subscription| !subscription.subscriber.is_finished())
+			.ok_or_else(|| Error::Generic(anyhow!("Not joined to topic {}", topic_id)))?;
+		let connectivity = subscription.connectivity.lock().await.clone();
+		Ok(connectivity)
+	}
+
+	/// The members that checked in lately and did not leave since, `None` if the topic is not joined.
+	pub async fn online_members(&self, topic_id: &str) -> Option<Vec<String>> {
+		let subscription = self
+			.topics
+			.get(topic_id)
+			.filter(|subscription| !subscription.subscriber.is_finished())?;
+		let online = subscription.online.lock().await.keys().cloned().collect();
+		Some(online)
+	}
+
+	/// Stops the subscription to a topic, returns false if it was not joined.
+	pub fn leave_topic(&mut self, topic_id: &str) -> bool {
+		match self.topics.remove(topic_id) {
+			Some(subscription) => {
+				subscription.stop();
+				true
+			},
+			None => false,
+		}
+	}
+
+	/// Stops every subscription, returns the ids of the topics that were joined.
+	pub fn leave_all(&mut self) -> Vec<String> {
+		let joined = self
+			.topics
+			.keys()
+			.filter(|topic_id| self.is_joined(topic_id))
+			.cloned()
+			.collect();
+		for (_, subscription) in self.topics.drain() {
+			subscription.stop();
+		}
+		joined
+	}
+
+	/// Has the store drop the blobs that are no longer tagged the next time it is loaded.
+	pub fn compact_store_on_load(&self) -> Result<()> {
+		std::fs::write(self.store_path.join(COMPACT_MARKER), [])?;
+		Ok(())
+	}
+
 	pub async fn close(&mut self) {
+		self.leave_all();
 		self.store.dump().await.ok();
 		self.gossip.shutdown().await.ok();
 		self.endpoint.close().await;
@@ -50,3 +157,111 @@ impl CommState {
 		self.blobs.shutdown().await.ok();
 	}
 }
+
+// Asks for the store to be compacted when it is loaded next
+const COMPACT_MARKER: &str = "blobs.compact";
+// Where the compacted store is built, and the file that marks it as complete
+const COMPACT_DIR: &str = "blobs.next";
+const COMPACT_DONE: &str = "complete";
+// What makes up a store, its temp directory is left in place
+const STORE_PARTS: [&str; 2] = ["blobs.db", "data"];
+
+// The store can not delete blobs itself, so the tagged ones, along with the children of tagged collections,
+// are copied into a new store that then takes its place. Incomplete blobs, e.g. of paused downloads, are not kept
+async fn compact_store(root: &Path) -> Result<()> {
+	let next = root.join(COMPACT_DIR);
+	if !next.join(COMPACT_DONE).exists() {
+		if !root.join(COMPACT_MARKER).exists() {
+			return Ok(());
+		}
+		// Left over from a compaction that stopped while copying
+		if next.exists() {
+			std::fs::remove_dir_all(&next)?;
+		}
+		let store = FsStore::load(root).await?;
+		let compacted = FsStore::load(&next).await?;
+		let result = copy_tagged(&store, &compacted, &next).await;
+		store.shutdown().await.ok();
+		compacted.shutdown().await.ok();
+		result?;
+		std::fs::write(next.join(COMPACT_DONE), [])?;
+	}
+
+	// Every step can be taken again when the swap is interrupted
+	for part in STORE_PARTS {
+		let (from, to) = (next.join(part), root.join(part));
+		if from.exists() {
+			if to.is_dir() {
+				std::fs::remove_dir_all(&to)?;
+			} else if to.exists() {
+				std::fs::remove_file(&to)?;
+			}
+			std::fs::rename(&from, &to)?;
+		}
+	}
+	std::fs::remove_dir_all(&next)?;
+	std::fs::remove_file(root.join(COMPACT_MARKER)).ok();
+	Ok(())
+}
+
+async fn copy_tagged(store: &FsStore, compacted: &FsStore, dir: &Path) -> Result<()> {
+	let mut list = store
+		.tags()
+		.list()
+		.await
+		.map_err(|e| Error::Generic(anyhow!("Failed to list blob tags: {}", e)))?;
+	let mut tags = Vec::new();
+	while let Some(tag) = list.next().await {
+		tags.push(tag.map_err(|e| Error::Generic(anyhow!("Failed to list blob tags: {}", e)))?);
+	}
+
+	for tag in tags {
+		let mut hashes = vec![tag.hash];
+		if tag.format == BlobFormat::HashSeq && is_complete(store, tag.hash).await? {
+			let bytes = store
+				.get_bytes(tag.hash)
+				.await
+				.map_err(|e| Error::Generic(anyhow!("Failed to read collection {}: {}", tag.hash, e)))?;
+			let children = HashSeq::try_from(bytes)
+				.map_err(|e| Error::EncodeDecode(format!("Failed to read collection {}: {}", tag.hash, e)))?;
+			hashes.extend(children);
+		}
+		for hash in hashes {
+			copy_blob(store, compacted, hash, dir).await?;
+		}
+		compacted
+			.tags()
+			.set(&tag.name, HashAndFormat::new(tag.hash, tag.format))
+			.await
+			.map_err(|e| Error::Generic(anyhow!("Failed to tag blob {}: {}", tag.hash, e)))?;
+	}
+	Ok(())
+}
+
+// Goes through a file next to the new store, the store has no way to take a blob from another one
+async fn copy_blob(store: &FsStore, compacted: &FsStore, hash: Hash, dir: &Path) -> Result<()> {
+	if !is_complete(store, hash).await? || compacted.has(hash).await.unwrap_or(false) {
+		return Ok(());
+	}
+	let path = dir.join(hash.to_hex());
+	store
+		.export(hash, &path)
+		.await
+		.map_err(|e| Error::Generic(anyhow!("Failed to export blob {}: {}", hash, e)))?;
+	// The new store collects no garbage either, the temporary tag does not need to outlive the import
+	let _imported = compacted
+		.add_path(&path)
+		.temp_tag()
+		.await
+		.map_err(|e| Error::Generic(anyhow!("Failed to import blob {}: {}", hash, e)))?;
+	std::fs::remove_file(&path)?;
+	Ok(())
+}
+
+async fn is_complete(store: &FsStore, hash: Hash) -> Result<bool> {
+	let status = store
+		.status(hash)
+		.await
+		.map_err(|e| Error::Generic(anyhow!("Failed to look up blob {}: {}", hash, e)))?;
+	Ok(matches!(status, BlobStatus::Complete { .. }))
+}
@@ -1,6 +1,8 @@
-use anyhow::Result;
-use iroh::NodeId;
+use anyhow::{anyhow, Result};
+use iroh::{NodeId, SecretKey};
+use iroh_base::Signature;
 use iroh_gossip::proto::TopicId;
+use rand::RngCore;
 use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::str::FromStr;
@@ -9,6 +11,63 @@ use std::str::FromStr;
 pub struct Ticket {
 	pub topic: TopicId,
 	pub nodes: Vec<NodeId>,
+	// Tickets issued before grants existed have none
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub grant: Option<Grant>,
+}
+
+/// The terms a ticket was issued under, signed by the member that issued it.
+/// Newcomers present it in their check-ins, and members only admit them while it holds.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Grant {
+	pub topic_id: String,
+	pub issuer: String,
+	pub nonce: String, // Identifies the ticket for counting uses and revoking it
+	pub expires_at: Option<i64>,
+	pub max_uses: Option<u32>,
+	signature: Signature,
+}
+
+impl Grant {
+	pub fn issue(secret_key: &SecretKey, topic_id: String, expires_at: Option<i64>, max_uses: Option<u32>) -> Self {
+		let mut nonce = [0u8; 16];
+		rand::rngs::OsRng.fill_bytes(&mut nonce);
+		let issuer = secret_key.public().to_string();
+		let nonce = data_encoding::HEXLOWER.encode(&nonce);
+		let signature = secret_key.sign(&signed_terms(&topic_id, &issuer, &nonce, expires_at, max_uses));
+		Self {
+			topic_id,
+			issuer,
+			nonce,
+			expires_at,
+			max_uses,
+			signature,
+		}
+	}
+
+	/// Checks that the grant was signed by its issuer and not altered since.
+	pub fn verify(&self) -> Result<()> {
+		let issuer = NodeId::from_str(&self.issuer)?;
+		let terms = signed_terms(
+			&self.topic_id,
+			&self.issuer,
+			&self.nonce,
+			self.expires_at,
+			self.max_uses,
+		);
+		issuer
+			.verify(&terms, &self.signature)
+			.map_err(|e| anyhow!("Invalid ticket signature: {}", e))
+	}
+
+	pub fn is_expired(&self, now: i64) -> bool {
+		self.expires_at.is_some_and(|expires_at| now > expires_at)
+	}
+}
+
+fn signed_terms(topic_id: &str, issuer: &str, nonce: &str, expires_at: Option<i64>, max_uses: Option<u32>) -> Vec<u8> {
+	serde_json::to_vec(&(topic_id, issuer, nonce, expires_at, max_uses)).expect("serde_json::to_vec is infallible")
 }
 
 impl Ticket {
@@ -22,7 +81,7 @@ impl Ticket {
 		serde_json::to_vec(self).expect("serde_json::to_vec is infallible")
 	}
 
-	pub fn new(topic: &str, nodes: Vec<String>) -> Result<Self> {
+	pub fn new(topic: &str, nodes: Vec<String>, grant: Option<Grant>) -> Result<Self> {
 		let topic_id = TopicId::from_str(topic)?;
 		let node_ids: Result<Vec<NodeId>> = nodes
 			.into_iter()
@@ -31,6 +90,7 @@ impl Ticket {
 		Ok(Self {
 			topic: topic_id,
 			nodes: node_ids?,
+			grant,
 		})
 	}
 
@@ -58,3 +118,54 @@ impl FromStr for Ticket {
 		Self::from_bytes(&bytes)
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn grant(secret_key: &SecretKey) -> Grant {
+		let topic_id = TopicId::from_bytes([7; 32]).to_string();
+		Grant::issue(secret_key, topic_id, Some(1_700_000_000), Some(1))
+	}
+
+	#[test]
+	fn ticket_with_grant_round_trips() {
+		let secret_key = SecretKey::generate(rand::rngs::OsRng);
+		let grant = grant(&secret_key);
+		let ticket = Ticket::new(
+			&grant.topic_id,
+			vec![secret_key.public().to_string()],
+			Some(grant.clone()),
+		)
+		.unwrap();
+
+		let parsed = Ticket::from_str(&ticket.to_string()).unwrap();
+		assert_eq!(parsed.grant, Some(grant));
+		parsed.grant.unwrap().verify().unwrap();
+	}
+
+	#[test]
+	fn rejects_altered_terms() {
+		let secret_key = SecretKey::generate(rand::rngs::OsRng);
+
+		let mut extended = grant(&secret_key);
+		extended.expires_at = None;
+		assert!(extended.verify().is_err());
+
+		let mut reused = grant(&secret_key);
+		reused.max_uses = Some(100);
+		assert!(reused.verify().is_err());
+
+		// Claiming another member issued it breaks the signature as well
+		let mut forged = grant(&secret_key);
+		forged.issuer = SecretKey::generate(rand::rngs::OsRng).public().to_string();
+		assert!(forged.verify().is_err());
+	}
+
+	#[test]
+	fn expiry_is_inclusive() {
+		let grant = grant(&SecretKey::generate(rand::rngs::OsRng));
+		assert!(!grant.is_expired(1_700_000_000));
+		assert!(grant.is_expired(1_700_000_001));
+	}
+}
@@ -0,0 +1,82 @@
+use std::{path::PathBuf, sync::Arc};
+
+use serde::Serialize;
+use tauri::{async_runtime, AppHandle, Manager, State};
+use tokio::sync::Mutex;
+
+use crate::{error::Result, AppState};
+
+// Where the node lives, `AppState` is only managed once the database is unlocked
+pub(crate) struct Vault {
+	data_dir: PathBuf,
+	unlocking: Mutex<()>,
+}
+
+impl Vault {
+	pub(crate) fn new(data_dir: PathBuf) -> Self {
+		Self {
+			data_dir,
+			unlocking: Mutex::new(()),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DatabaseStatus {
+	/// No database yet, unlocking sets the passphrase
+	New,
+	/// A database from before encryption, unlocking encrypts it with a new passphrase
+	Unencrypted,
+	Locked,
+	Unlocked,
+}
+
+#[tauri::command]
+pub async fn get_database_status(app_handle: AppHandle, vault: State<'_, Vault>) -> Result<DatabaseStatus> {
+	let status = if app_handle.try_state::<Mutex<AppState>>().is_some() {
+		DatabaseStatus::Unlocked
+	} else if AppState::is_unencrypted(&vault.data_dir)? {
+		DatabaseStatus::Unencrypted
+	} else if AppState::exists(&vault.data_dir) {
+		DatabaseStatus::Locked
+	} else {
+		DatabaseStatus::New
+	};
+	Ok(status)
+}
+
+#[tauri::command]
+pub async fn unlock_database(app_handle: AppHandle, passphrase: String) -> Result<()> {
+	unlock(&app_handle, passphrase).await
+}
+
+#[tauri::command]
+pub async fn change_passphrase(
+	app_state: State<'_, Mutex<AppState>>,
+	passphrase: String,
+	new_passphrase: String,
+) -> Result<()> {
+	let mut state = app_state.lock().await;
+	state.change_passphrase(passphrase, new_passphrase).await
+}
+
+/// Opens the node with `passphrase` and hands it to the commands, a no-op once unlocked.
+pub(crate) async fn unlock(app_handle: &AppHandle, passphrase: String) -> Result<()> {
+	let vault = app_handle.state::<Vault>();
+	let _unlocking = vault.unlocking.lock().await;
+	if app_handle.try_state::<Mutex<AppState>>().is_some() {
+		return Ok(());
+	}
+
+	let app_state = AppState::init(vault.data_dir.clone(), passphrase, Arc::new(app_handle.clone())).await?;
+	app_handle.manage(Mutex::new(app_state));
+
+	// Topics are joined in the background so a slow subscription does not hold up the window
+	let app_handle = app_handle.clone();
+	async_runtime::spawn(async move {
+		let app_state = app_handle.state::<Mutex<AppState>>();
+		app_state.lock().await.rejoin_auto_join_topics().await;
+	});
+	Ok(())
+}
@@ -0,0 +1,75 @@
+use std::path::PathBuf;
+
+use tauri::State;
+use tokio::sync::Mutex;
+
+use crate::{
+	comm::downloads::list_entries,
+	database::{download::Download, file::File},
+	error::Result,
+	AppState,
+};
+
+#[tauri::command]
+pub async fn download_file(
+	app_state: State<'_, Mutex<AppState>>,
+	file: File,
+	target_path: Option<String>,
+) -> Result<Download> {
+	let state = app_state.lock().await;
+	state.download_file(file, target_path.map(PathBuf::from)).await
+}
+
+#[tauri::command]
+pub async fn download_file_entries(
+	app_state: State<'_, Mutex<AppState>>,
+	file: File,
+	paths: Vec<String>,
+	target_path: Option<String>,
+) -> Result<Download> {
+	// Listing the entries can go over the network, so it runs without the lock
+	let ctx = app_state.lock().await.download_context();
+	list_entries(&ctx, file.id).await?;
+	let state = app_state.lock().await;
+	state
+		.queue_file_entries(file, paths, target_path.map(PathBuf::from))
+		.await
+}
+
+#[tauri::command]
+pub async fn list_downloads(app_state: State<'_, Mutex<AppState>>) -> Result<Vec<Download>> {
+	let state = app_state.lock().await;
+	state.list_downloads().await
+}
+
+#[tauri::command]
+pub async fn pause_download(app_state: State<'_, Mutex<AppState>>, file_id: i64) -> Result<()> {
+	let state = app_state.lock().await;
+	state.pause_download(file_id).await
+}
+
+#[tauri::command]
+pub async fn resume_download(app_state: State<'_, Mutex<AppState>>, file_id: i64) -> Result<Download> {
+	let state = app_state.lock().await;
+	state.resume_download(file_id).await
+}
+
+#[tauri::command]
+pub async fn cancel_download(app_state: State<'_, Mutex<AppState>>, file_id: i64) -> Result<()> {
+	let state = app_state.lock().await;
+	state.cancel_download(file_id).await
+}
+
+#[tauri::command]
+pub async fn get_download_dir(app_state: State<'_, Mutex<AppState>>) -> Result<String> {
+	let state = app_state.lock().await;
+	let dir = state.get_download_dir().await?;
+	Ok(dir.to_string_lossy().to_string())
+}
+
+#[tauri::command]
+pub async fn set_download_dir(app_state: State<'_, Mutex<AppState>>, dir: String) -> Result<String> {
+	let state = app_state.lock().await;
+	let dir = state.set_download_dir(dir).await?;
+	Ok(dir.to_string_lossy().to_string())
+}
@@ -0,0 +1,18 @@
+use std::path::PathBuf;
+
+use tauri::State;
+use tokio::sync::Mutex;
+
+use crate::{error::Result, AppState};
+
+#[tauri::command]
+pub async fn export_identity(app_state: State<'_, Mutex<AppState>>, path: String, passphrase: String) -> Result<()> {
+	let state = app_state.lock().await;
+	state.export_identity(PathBuf::from(path), passphrase).await
+}
+
+#[tauri::command]
+pub async fn import_identity(app_state: State<'_, Mutex<AppState>>, path: String, passphrase: String) -> Result<()> {
+	let mut state = app_state.lock().await;
+	state.import_identity(PathBuf::from(path), passphrase).await
+}
@@ -2,36 +2,37 @@ use tauri::State;
 use tokio::sync::Mutex;
 
 use crate::{
-	comm::model::{self, Chat, MessageType, UserInfo},
-	error::{Error, Result},
+	database::message::{Message, DEFAULT_MESSAGE_PAGE_SIZE},
+	error::Result,
 	AppState,
 };
 
+pub(crate) mod database;
+pub(crate) mod download;
 pub(crate) mod file;
+pub(crate) mod identity;
+pub(crate) mod moderation;
+pub(crate) mod network;
 pub(crate) mod node;
+pub(crate) mod ticket;
 pub(crate) mod topic;
 pub(crate) mod user;
 
 #[tauri::command]
-pub async fn send_message(
+pub async fn send_message(app_state: State<'_, Mutex<AppState>>, topic_id: String, message: String) -> Result<()> {
+	let state = app_state.lock().await;
+	state.send_message(topic_id, message).await
+}
+
+#[tauri::command]
+pub async fn list_messages(
 	app_state: State<'_, Mutex<AppState>>,
-	user_info: State<'_, Mutex<UserInfo>>,
-	message: String,
-) -> Result<()> {
+	topic_id: String,
+	before: Option<i64>,
+	limit: Option<i64>,
+) -> Result<Vec<Message>> {
 	let state = app_state.lock().await;
-	let topic_sender = state.comm.topic_sender.clone();
-	let metadata = model::Metadata::new(
-		user_info.lock().await.clone(),
-		state.comm.endpoint.node_id().to_string(),
-		None,
-	);
-	let message = MessageType::Chat(model::Message::new(Chat::new(message), metadata));
-	let message = serde_json::to_vec(&message)?;
-	if let Some(sender) = topic_sender {
-		sender
-			.broadcast(message.into())
-			.await
-			.map_err(|e| Error::GossipSubscription(format!("Failed to send message: {}", e)))?;
-	}
-	Ok(())
+	state
+		.list_messages(topic_id, before, limit.unwrap_or(DEFAULT_MESSAGE_PAGE_SIZE))
+		.await
 }
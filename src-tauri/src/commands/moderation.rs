@@ -0,0 +1,90 @@
+use tauri::State;
+use tokio::sync::Mutex;
+
+use crate::{
+	database::{
+		member::{Role, TopicMember},
+		moderation::JoinRequest,
+	},
+	error::Result,
+	AppState,
+};
+
+#[tauri::command]
+pub async fn remove_member(app_state: State<'_, Mutex<AppState>>, topic_id: String, node_id: String) -> Result<()> {
+	let state = app_state.lock().await;
+	state.remove_member(topic_id, node_id).await
+}
+
+#[tauri::command]
+pub async fn ban_member(app_state: State<'_, Mutex<AppState>>, topic_id: String, node_id: String) -> Result<()> {
+	let state = app_state.lock().await;
+	state.ban_member(topic_id, node_id).await
+}
+
+#[tauri::command]
+pub async fn unban_member(app_state: State<'_, Mutex<AppState>>, topic_id: String, node_id: String) -> Result<()> {
+	let state = app_state.lock().await;
+	state.unban_member(topic_id, node_id).await
+}
+
+#[tauri::command]
+pub async fn list_banned_members(app_state: State<'_, Mutex<AppState>>, topic_id: String) -> Result<Vec<String>> {
+	let state = app_state.lock().await;
+	state.list_banned_members(topic_id).await
+}
+
+#[tauri::command]
+pub async fn set_require_approval(
+	app_state: State<'_, Mutex<AppState>>,
+	topic_id: String,
+	enabled: bool,
+) -> Result<()> {
+	let state = app_state.lock().await;
+	state.set_require_approval(topic_id, enabled).await
+}
+
+#[tauri::command]
+pub async fn list_join_requests(app_state: State<'_, Mutex<AppState>>, topic_id: String) -> Result<Vec<JoinRequest>> {
+	let state = app_state.lock().await;
+	state.list_join_requests(topic_id).await
+}
+
+#[tauri::command]
+pub async fn approve_join(app_state: State<'_, Mutex<AppState>>, topic_id: String, node_id: String) -> Result<()> {
+	let state = app_state.lock().await;
+	state.approve_join(topic_id, node_id).await
+}
+
+#[tauri::command]
+pub async fn deny_join(app_state: State<'_, Mutex<AppState>>, topic_id: String, node_id: String) -> Result<()> {
+	let state = app_state.lock().await;
+	state.deny_join(topic_id, node_id).await
+}
+
+#[tauri::command]
+pub async fn list_member_roles(app_state: State<'_, Mutex<AppState>>, topic_id: String) -> Result<Vec<TopicMember>> {
+	let state = app_state.lock().await;
+	state.list_member_roles(topic_id).await
+}
+
+#[tauri::command]
+pub async fn set_member_role(
+	app_state: State<'_, Mutex<AppState>>,
+	topic_id: String,
+	node_id: String,
+	role: Role,
+) -> Result<()> {
+	let state = app_state.lock().await;
+	state.set_member_role(topic_id, node_id, role).await
+}
+
+#[tauri::command]
+pub async fn transfer_ownership(
+	app_state: State<'_, Mutex<AppState>>,
+	topic_id: String,
+	node_id: String,
+) -> Result<()> {
+	let state = app_state.lock().await;
+	state.transfer_ownership(topic_id, node_id).await
+}
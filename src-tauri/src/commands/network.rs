@@ -0,0 +1,16 @@
+use tauri::State;
+use tokio::sync::Mutex;
+
+use crate::{comm::endpoint::NetworkConfig, error::Result, AppState};
+
+#[tauri::command]
+pub async fn get_network_config(app_state: State<'_, Mutex<AppState>>) -> Result<NetworkConfig> {
+	let state = app_state.lock().await;
+	state.get_network_config().await
+}
+
+#[tauri::command]
+pub async fn set_network_config(app_state: State<'_, Mutex<AppState>>, config: NetworkConfig) -> Result<NetworkConfig> {
+	let mut state = app_state.lock().await;
+	state.set_network_config(config).await
+}
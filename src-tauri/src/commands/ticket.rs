@@ -0,0 +1,19 @@
+use tauri::State;
+use tokio::sync::Mutex;
+
+use crate::{database::ticket::IssuedTicket, error::Result, AppState};
+
+#[tauri::command]
+pub async fn list_issued_tickets(
+	app_state: State<'_, Mutex<AppState>>,
+	topic_id: Option<String>,
+) -> Result<Vec<IssuedTicket>> {
+	let state = app_state.lock().await;
+	state.list_issued_tickets(topic_id).await
+}
+
+#[tauri::command]
+pub async fn revoke_ticket(app_state: State<'_, Mutex<AppState>>, nonce: String) -> Result<()> {
+	let state = app_state.lock().await;
+	state.revoke_ticket(nonce).await
+}
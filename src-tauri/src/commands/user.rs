@@ -4,7 +4,6 @@ use tokio::sync::Mutex;
 use tauri::State;
 
 use crate::{
-	comm::model::UserInfo,
 	database::{
 		node::NodeOperations,
 		user::{User, UserOperations},
@@ -42,26 +41,9 @@ pub struct CreateUserRequest {
 }
 
 #[tauri::command]
-pub async fn create_user(
-	app_state: State<'_, Mutex<AppState>>,
-	user_info: State<'_, Mutex<UserInfo>>,
-	user: CreateUserRequest,
-) -> Result<User> {
-	let state = app_state.lock().await;
-	let db = &state.db;
-	let node = db.get_node_by_node_id(user.node_id).await?;
-	let user = db
-		.create_user(User::new(user.email, user.first_name, user.last_name, Some(node.id)))
-		.await?;
-
-	if node.id == 1 {
-		*user_info.lock().await = UserInfo {
-			id: user.id,
-			email: user.email.clone(),
-			first_name: user.first_name.clone(),
-			last_name: user.last_name.clone(),
-		};
-	}
-
-	Ok(user)
+pub async fn create_user(app_state: State<'_, Mutex<AppState>>, user: CreateUserRequest) -> Result<User> {
+	let mut state = app_state.lock().await;
+	state
+		.create_user(user.email, user.first_name, user.last_name, user.node_id)
+		.await
 }
@@ -0,0 +1,89 @@
+// SQLCipher specific file handling, everything here runs on connections outside the pool
+use std::{
+	io::Read,
+	path::{Path, PathBuf},
+};
+
+use sqlx::{
+	sqlite::{SqliteConnectOptions, SqliteConnection},
+	ConnectOptions, Connection,
+};
+
+use crate::error::Result;
+
+pub(super) fn is_plaintext(db_path: &Path) -> Result<bool> {
+	// Plain SQLite files start with this header, SQLCipher ones with a random salt
+	const HEADER: &[u8; 16] = b"SQLite format 3\0";
+	let mut file = match std::fs::File::open(db_path) {
+		Ok(file) => file,
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+		Err(e) => return Err(e.into()),
+	};
+	let mut header = [0u8; 16];
+	match file.read_exact(&mut header) {
+		Ok(()) => Ok(&header == HEADER),
+		// Empty or truncated files are created fresh
+		Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+		Err(e) => Err(e.into()),
+	}
+}
+
+// Databases created before encryption was added are copied into an encrypted file that replaces them
+pub(super) async fn encrypt_plaintext(db_path: &Path, passphrase: &str) -> Result<()> {
+	// Attaching the new file creates it, which needs the create flag on the connection
+	let mut conn = SqliteConnectOptions::new()
+		.filename(db_path)
+		.create_if_missing(true)
+		.connect()
+		.await?;
+	let target = sibling(db_path, "encrypt");
+	let result = export(&mut conn, &target, passphrase).await;
+	conn.close().await?;
+	result?;
+	replace(db_path, &target)
+}
+
+pub(super) async fn export(conn: &mut SqliteConnection, target: &Path, passphrase: &str) -> Result<()> {
+	remove_if_exists(target)?;
+	let target_name = quote(&target.to_string_lossy());
+	sqlx::query(&format!(
+		"ATTACH DATABASE {} AS exported KEY {}",
+		target_name,
+		quote(passphrase)
+	))
+	.execute(&mut *conn)
+	.await?;
+	let result = sqlx::query("SELECT sqlcipher_export('exported')")
+		.execute(&mut *conn)
+		.await;
+	sqlx::query("DETACH DATABASE exported").execute(&mut *conn).await?;
+	result?;
+	Ok(())
+}
+
+// Moves `source` over the database, dropping the WAL files that belong to the old one
+pub(super) fn replace(db_path: &Path, source: &Path) -> Result<()> {
+	remove_if_exists(&sibling(db_path, "wal"))?;
+	remove_if_exists(&sibling(db_path, "shm"))?;
+	std::fs::rename(source, db_path)?;
+	Ok(())
+}
+
+pub(super) fn sibling(db_path: &Path, suffix: &str) -> PathBuf {
+	let mut path = db_path.as_os_str().to_owned();
+	path.push("-");
+	path.push(suffix);
+	PathBuf::from(path)
+}
+
+fn remove_if_exists(path: &Path) -> Result<()> {
+	match std::fs::remove_file(path) {
+		Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+		_ => Ok(()),
+	}
+}
+
+// Pragma values and attached file names are spliced into SQL, so they are passed as string literals
+pub(super) fn quote(value: &str) -> String {
+	format!("'{}'", value.replace('\'', "''"))
+}
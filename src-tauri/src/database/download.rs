@@ -0,0 +1,216 @@
+use serde::{Deserialize, Serialize};
+
+use super::{file::FileStatus, Db};
+use crate::error::Result;
+
+/// A file in the download queue, with what the transfer needs to know about it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Download {
+	pub file_id: i64,
+	pub topic_id: String,
+	pub node_id: String, // The node that shared the file
+	pub hash: String,
+	pub format: String, // HashSeq for a shared directory
+	pub name: String,
+	pub size: i64,
+	pub status: FileStatus,
+	pub target_path: String,
+	pub error: Option<String>,
+	pub queued_at: i64,
+}
+
+pub trait DownloadOperations {
+	/// Queues the file, or queues it again after it failed or was paused, keeping its place in the queue.
+	/// Returns `None` if it is already queued or downloading.
+	async fn queue_download(&self, file_id: i64, target_path: &str) -> Result<Option<Download>>;
+	async fn get_download(&self, file_id: i64) -> Result<Option<Download>>;
+	/// The queue in the order downloads were asked for.
+	async fn list_downloads(&self) -> Result<Vec<Download>>;
+	/// Moves a queued or running download to `status`, returns false if it was neither.
+	async fn set_download_status(&self, file_id: i64, status: FileStatus) -> Result<bool>;
+	async fn fail_download(&self, file_id: i64, error: &str) -> Result<()>;
+	/// Takes the file out of the queue and gives it `status`, with where it was saved once it is downloaded.
+	async fn finish_download(&self, file_id: i64, status: FileStatus, absolute_path: Option<String>) -> Result<()>;
+	/// Takes the file out of the queue and gives it back the status it had before it was queued.
+	async fn cancel_download(&self, file_id: i64) -> Result<()>;
+	/// Queues the downloads that were running when the node stopped, returns how many.
+	async fn requeue_interrupted_downloads(&self) -> Result<u64>;
+}
+
+impl DownloadOperations for Db {
+	async fn queue_download(&self, file_id: i64, target_path: &str) -> Result<Option<Download>> {
+		let queued_at = chrono::Utc::now().timestamp();
+		let mut tx = self.0.begin().await?;
+		let previous_status = sqlx::query_scalar!(
+			r#"
+                SELECT status as "status: FileStatus"
+                FROM files
+                WHERE id = $1
+                "#,
+			file_id
+		)
+		.fetch_one(&mut *tx)
+		.await?;
+		let queued = sqlx::query!(
+			r#"
+                UPDATE files
+                SET status = $1
+                WHERE id = $2 AND status NOT IN ('Queued', 'Downloading')
+                "#,
+			FileStatus::Queued,
+			file_id
+		)
+		.execute(&mut *tx)
+		.await?;
+		if queued.rows_affected() == 0 {
+			return Ok(None);
+		}
+		// A paused or failed download queued again keeps the status from before it was first queued
+		sqlx::query!(
+			r#"
+                INSERT INTO downloads (file_id, target_path, queued_at, previous_status)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (file_id) DO UPDATE SET target_path = excluded.target_path, error = NULL
+                "#,
+			file_id,
+			target_path,
+			queued_at,
+			previous_status
+		)
+		.execute(&mut *tx)
+		.await?;
+		tx.commit().await?;
+
+		self.get_download(file_id).await
+	}
+
+	async fn get_download(&self, file_id: i64) -> Result<Option<Download>> {
+		let download = sqlx::query_as!(
+			Download,
+			r#"
+                SELECT d.file_id, f.topic_id, f.node_id, f.hash, f.format, f.name, f.size, f.status, d.target_path, d.error, d.queued_at
+                FROM downloads d
+                JOIN files f ON f.id = d.file_id
+                WHERE d.file_id = $1
+                "#,
+			file_id
+		)
+		.fetch_optional(&self.0)
+		.await?;
+		Ok(download)
+	}
+
+	async fn list_downloads(&self) -> Result<Vec<Download>> {
+		let downloads = sqlx::query_as!(
+			Download,
+			r#"
+                SELECT d.file_id, f.topic_id, f.node_id, f.hash, f.format, f.name, f.size, f.status, d.target_path, d.error, d.queued_at
+                FROM downloads d
+                JOIN files f ON f.id = d.file_id
+                ORDER BY d.id
+                "#
+		)
+		.fetch_all(&self.0)
+		.await?;
+		Ok(downloads)
+	}
+
+	async fn set_download_status(&self, file_id: i64, status: FileStatus) -> Result<bool> {
+		let result = sqlx::query!(
+			r#"
+                UPDATE files
+                SET status = $1
+                WHERE id = $2 AND status IN ('Queued', 'Downloading')
+                "#,
+			status,
+			file_id
+		)
+		.execute(&self.0)
+		.await?;
+		Ok(result.rows_affected() > 0)
+	}
+
+	async fn fail_download(&self, file_id: i64, error: &str) -> Result<()> {
+		let mut tx = self.0.begin().await?;
+		sqlx::query!(
+			r#"
+                UPDATE downloads
+                SET error = $1
+                WHERE file_id = $2
+                "#,
+			error,
+			file_id
+		)
+		.execute(&mut *tx)
+		.await?;
+		sqlx::query!(
+			r#"
+                UPDATE files
+                SET status = $1
+                WHERE id = $2
+                "#,
+			FileStatus::Failed,
+			file_id
+		)
+		.execute(&mut *tx)
+		.await?;
+		tx.commit().await?;
+		Ok(())
+	}
+
+	async fn finish_download(&self, file_id: i64, status: FileStatus, absolute_path: Option<String>) -> Result<()> {
+		let mut tx = self.0.begin().await?;
+		sqlx::query!("DELETE FROM downloads WHERE file_id = $1", file_id)
+			.execute(&mut *tx)
+			.await?;
+		sqlx::query!(
+			r#"
+                UPDATE files
+                SET status = $1, absolute_path = COALESCE($2, absolute_path)
+                WHERE id = $3
+                "#,
+			status,
+			absolute_path,
+			file_id
+		)
+		.execute(&mut *tx)
+		.await?;
+		tx.commit().await?;
+		Ok(())
+	}
+
+	async fn cancel_download(&self, file_id: i64) -> Result<()> {
+		let mut tx = self.0.begin().await?;
+		sqlx::query!(
+			r#"
+                UPDATE files
+                SET status = (SELECT previous_status FROM downloads WHERE file_id = $1)
+                WHERE id = $1 AND EXISTS (SELECT 1 FROM downloads WHERE file_id = $1)
+                "#,
+			file_id
+		)
+		.execute(&mut *tx)
+		.await?;
+		sqlx::query!("DELETE FROM downloads WHERE file_id = $1", file_id)
+			.execute(&mut *tx)
+			.await?;
+		tx.commit().await?;
+		Ok(())
+	}
+
+	async fn requeue_interrupted_downloads(&self) -> Result<u64> {
+		let result = sqlx::query!(
+			r#"
+                UPDATE files
+                SET status = $1
+                WHERE status = $2
+                "#,
+			FileStatus::Queued,
+			FileStatus::Downloading
+		)
+		.execute(&self.0)
+		.await?;
+		Ok(result.rows_affected())
+	}
+}
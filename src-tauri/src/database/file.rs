@@ -12,12 +12,17 @@ pub struct TsFilter {
 
 pub enum TsDirection {
 	Newer,
+	NewerOrSame, // Timestamps are in seconds, so this also covers the ones sent in the same second
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Type)]
 #[sqlx(type_name = "TEXT")]
 pub enum FileStatus {
 	Shared,
+	Queued,
+	Downloading,
+	Paused,
+	Failed,
 	Downloaded,
 }
 
@@ -25,6 +30,10 @@ impl From<String> for FileStatus {
 	fn from(value: String) -> Self {
 		match value.as_str() {
 			"Shared" => Self::Shared,
+			"Queued" => Self::Queued,
+			"Downloading" => Self::Downloading,
+			"Paused" => Self::Paused,
+			"Failed" => Self::Failed,
 			"Downloaded" => Self::Downloaded,
 			_ => panic!("Invalid file status"),
 		}
@@ -74,6 +83,33 @@ impl File {
 	}
 }
 
+/// A file inside a shared directory.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct FileEntry {
+	pub file_id: i64,
+	pub position: i64,
+	pub path: String,
+	pub hash: String,
+	pub size: Option<i64>,
+	pub absolute_path: Option<String>,
+	pub selected: bool,
+}
+
+impl FileEntry {
+	pub(crate) fn new(file_id: i64, position: i64, path: String, hash: String, size: Option<i64>) -> Self {
+		Self {
+			file_id,
+			position,
+			path,
+			hash,
+			size,
+			absolute_path: None,
+			selected: true,
+		}
+	}
+}
+
 pub trait FileOperations {
 	async fn create_file(&self, file: File) -> Result<File>;
 	async fn list_files(
@@ -82,13 +118,32 @@ pub trait FileOperations {
 		node_id: Option<String>,
 		ts_filter: Option<TsFilter>,
 	) -> Result<Vec<File>>;
-	async fn update_file(&self, id: i64, status: FileStatus) -> Result<File>;
-	async fn get_file_by_hash(&self, hash: String) -> Result<File>;
+	/// Sets the status, and where the file is on this node when `absolute_path` is given.
+	async fn update_file(&self, id: i64, status: FileStatus, absolute_path: Option<String>) -> Result<File>;
+	async fn get_file_by_id(&self, id: i64) -> Result<File>;
+	/// The file `node_id` shared in the topic with this content, a file is known once per topic and sharer.
+	async fn get_file(&self, topic_id: &str, hash: &str, node_id: &str) -> Result<Option<File>>;
+	/// Forgets the files `node_id` shared in the topic that were not downloaded here and no member holds, returns how many.
+	async fn delete_undownloaded_files(&self, topic_id: &str, node_id: &str) -> Result<u64>;
 	async fn get_latest_file_timestamps_by_members(
 		&self,
 		topic_id: &str,
 		members: &[String],
 	) -> Result<HashMap<String, i64>>;
+	/// Hashes of the files in the topic that were downloaded here, announced in check-ins.
+	/// A collection only counts once all of its entries were downloaded. The `limit` most recently shared come first.
+	async fn list_held_hashes(&self, topic_id: &str, limit: i64) -> Result<Vec<String>>;
+	/// Replaces the hashes `node_id` holds in the topic with the ones it announced, hashes of unknown files are left out.
+	async fn set_blob_holders(&self, topic_id: &str, node_id: &str, hashes: &[String]) -> Result<()>;
+	/// The members that announced holding the file, in the order they announced it.
+	async fn list_blob_holders(&self, topic_id: &str, hash: &str) -> Result<Vec<String>>;
+	/// Adds the entries of a collection, the ones it already has are kept.
+	async fn create_file_entries(&self, entries: &[FileEntry]) -> Result<()>;
+	async fn list_file_entries(&self, file_id: i64) -> Result<Vec<FileEntry>>;
+	/// Selects the entries with the given paths for download, or all of them with `None`.
+	async fn select_file_entries(&self, file_id: i64, paths: Option<&[String]>) -> Result<()>;
+	/// Records where an entry was saved and its size.
+	async fn update_file_entry(&self, file_id: i64, position: i64, size: i64, absolute_path: &str) -> Result<()>;
 }
 
 impl FileOperations for Db {
@@ -131,6 +186,7 @@ impl FileOperations for Db {
 			query.push_str(" AND shared_at ");
 			query.push_str(match ts_filter.direction {
 				TsDirection::Newer => ">",
+				TsDirection::NewerOrSame => ">=",
 			});
 			query.push_str(" ?");
 		}
@@ -147,16 +203,17 @@ impl FileOperations for Db {
 		Ok(files)
 	}
 
-	async fn update_file(&self, id: i64, status: FileStatus) -> Result<File> {
+	async fn update_file(&self, id: i64, status: FileStatus, absolute_path: Option<String>) -> Result<File> {
 		let file = sqlx::query_as!(
 			File,
 			r#"
                 UPDATE files
-                SET status = $1
-                WHERE id = $2
+                SET status = $1, absolute_path = COALESCE($2, absolute_path)
+                WHERE id = $3
                 RETURNING id, node_id, topic_id, hash, name, absolute_path, size, format, status, shared_at
                 "#,
 			status,
+			absolute_path,
 			id
 		)
 		.fetch_one(&self.0)
@@ -164,21 +221,70 @@ impl FileOperations for Db {
 		Ok(file)
 	}
 
-	async fn get_file_by_hash(&self, hash: String) -> Result<File> {
+	async fn delete_undownloaded_files(&self, topic_id: &str, node_id: &str) -> Result<u64> {
+		let mut tx = self.0.begin().await?;
+		let deleted = sqlx::query_scalar!(
+			r#"
+                DELETE FROM files
+                WHERE topic_id = $1 AND node_id = $2 AND status = 'Shared'
+                AND NOT EXISTS (
+                    SELECT 1 FROM blob_holders h
+                    WHERE h.topic_id = files.topic_id AND h.hash = files.hash
+                )
+                RETURNING id
+                "#,
+			topic_id,
+			node_id
+		)
+		.fetch_all(&mut *tx)
+		.await?;
+		for file_id in &deleted {
+			sqlx::query!(
+				r#"
+                    DELETE FROM file_entries
+                    WHERE file_id = $1
+                    "#,
+				file_id
+			)
+			.execute(&mut *tx)
+			.await?;
+		}
+		tx.commit().await?;
+		Ok(deleted.len() as u64)
+	}
+
+	async fn get_file_by_id(&self, id: i64) -> Result<File> {
 		let file = sqlx::query_as!(
 			File,
 			r#"
                 SELECT id, node_id, topic_id, hash, name, absolute_path, size, format, status, shared_at
                 FROM files
-                WHERE hash = $1
+                WHERE id = $1
                 "#,
-			hash
+			id
 		)
 		.fetch_one(&self.0)
 		.await?;
 		Ok(file)
 	}
 
+	async fn get_file(&self, topic_id: &str, hash: &str, node_id: &str) -> Result<Option<File>> {
+		let file = sqlx::query_as!(
+			File,
+			r#"
+                SELECT id, node_id, topic_id, hash, name, absolute_path, size, format, status, shared_at
+                FROM files
+                WHERE topic_id = $1 AND hash = $2 AND node_id = $3
+                "#,
+			topic_id,
+			hash,
+			node_id
+		)
+		.fetch_optional(&self.0)
+		.await?;
+		Ok(file)
+	}
+
 	async fn get_latest_file_timestamps_by_members(
 		&self,
 		topic_id: &str,
@@ -222,4 +328,161 @@ impl FileOperations for Db {
 
 		Ok(result)
 	}
+
+	async fn list_held_hashes(&self, topic_id: &str, limit: i64) -> Result<Vec<String>> {
+		let hashes = sqlx::query_scalar!(
+			r#"
+                SELECT hash
+                FROM files f
+                WHERE topic_id = $1 AND status = 'Downloaded'
+                AND NOT EXISTS (SELECT 1 FROM file_entries e WHERE e.file_id = f.id AND e.absolute_path IS NULL)
+                GROUP BY hash
+                ORDER BY MAX(shared_at) DESC
+                LIMIT $2
+                "#,
+			topic_id,
+			limit
+		)
+		.fetch_all(&self.0)
+		.await?;
+		Ok(hashes)
+	}
+
+	async fn set_blob_holders(&self, topic_id: &str, node_id: &str, hashes: &[String]) -> Result<()> {
+		let announced_at = chrono::Utc::now().timestamp();
+		let mut tx = self.0.begin().await?;
+		sqlx::query!(
+			r#"
+                DELETE FROM blob_holders
+                WHERE topic_id = $1 AND node_id = $2
+                "#,
+			topic_id,
+			node_id
+		)
+		.execute(&mut *tx)
+		.await?;
+		for hash in hashes {
+			sqlx::query!(
+				r#"
+                    INSERT OR IGNORE INTO blob_holders (topic_id, hash, node_id, announced_at)
+                    SELECT $1, $2, $3, $4
+                    WHERE EXISTS (SELECT 1 FROM files WHERE topic_id = $1 AND hash = $2)
+                    "#,
+				topic_id,
+				hash,
+				node_id,
+				announced_at
+			)
+			.execute(&mut *tx)
+			.await?;
+		}
+		tx.commit().await?;
+		Ok(())
+	}
+
+	async fn list_blob_holders(&self, topic_id: &str, hash: &str) -> Result<Vec<String>> {
+		let holders = sqlx::query_scalar!(
+			r#"
+                SELECT node_id
+                FROM blob_holders
+                WHERE topic_id = $1 AND hash = $2
+                ORDER BY announced_at, rowid
+                "#,
+			topic_id,
+			hash
+		)
+		.fetch_all(&self.0)
+		.await?;
+		Ok(holders)
+	}
+
+	async fn create_file_entries(&self, entries: &[FileEntry]) -> Result<()> {
+		let mut tx = self.0.begin().await?;
+		for entry in entries {
+			sqlx::query!(
+				r#"
+                    INSERT OR IGNORE INTO file_entries (file_id, position, path, hash, size, absolute_path, selected)
+                    VALUES ($1, $2, $3, $4, $5, $6, $7)
+                    "#,
+				entry.file_id,
+				entry.position,
+				entry.path,
+				entry.hash,
+				entry.size,
+				entry.absolute_path,
+				entry.selected
+			)
+			.execute(&mut *tx)
+			.await?;
+		}
+		tx.commit().await?;
+		Ok(())
+	}
+
+	async fn list_file_entries(&self, file_id: i64) -> Result<Vec<FileEntry>> {
+		let entries = sqlx::query_as!(
+			FileEntry,
+			r#"
+                SELECT file_id, position, path, hash, size, absolute_path, selected as "selected: bool"
+                FROM file_entries
+                WHERE file_id = $1
+                ORDER BY position
+                "#,
+			file_id
+		)
+		.fetch_all(&self.0)
+		.await?;
+		Ok(entries)
+	}
+
+	async fn select_file_entries(&self, file_id: i64, paths: Option<&[String]>) -> Result<()> {
+		let mut tx = self.0.begin().await?;
+		let selected = paths.is_none();
+		sqlx::query!(
+			r#"
+                UPDATE file_entries
+                SET selected = $1
+                WHERE file_id = $2
+                "#,
+			selected,
+			file_id
+		)
+		.execute(&mut *tx)
+		.await?;
+		for path in paths.unwrap_or_default() {
+			let result = sqlx::query!(
+				r#"
+                    UPDATE file_entries
+                    SET selected = 1
+                    WHERE file_id = $1 AND path = $2
+                    "#,
+				file_id,
+				path
+			)
+			.execute(&mut *tx)
+			.await?;
+			if result.rows_affected() == 0 {
+				return Err(anyhow::anyhow!("{} is not in the collection", path).into());
+			}
+		}
+		tx.commit().await?;
+		Ok(())
+	}
+
+	async fn update_file_entry(&self, file_id: i64, position: i64, size: i64, absolute_path: &str) -> Result<()> {
+		sqlx::query!(
+			r#"
+                UPDATE file_entries
+                SET size = $1, absolute_path = $2
+                WHERE file_id = $3 AND position = $4
+                "#,
+			size,
+			absolute_path,
+			file_id,
+			position
+		)
+		.execute(&self.0)
+		.await?;
+		Ok(())
+	}
 }
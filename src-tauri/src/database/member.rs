@@ -0,0 +1,272 @@
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use sqlx::Type;
+
+use super::Db;
+use crate::error::{Error, Result};
+
+/// What a member may do in a topic, ordered from the fewest to the most rights.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "TEXT", rename_all = "snake_case")]
+pub enum Role {
+	ReadOnly,
+	Member,
+	Admin,
+	Owner,
+}
+
+impl Role {
+	/// Chat and share files
+	pub fn can_post(self) -> bool {
+		self >= Role::Member
+	}
+
+	/// Issue tickets, approve newcomers and remove or ban members
+	pub fn can_moderate(self) -> bool {
+		self >= Role::Admin
+	}
+}
+
+impl fmt::Display for Role {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let role = match self {
+			Self::ReadOnly => "read_only",
+			Self::Member => "member",
+			Self::Admin => "admin",
+			Self::Owner => "owner",
+		};
+		f.write_str(role)
+	}
+}
+
+impl FromStr for Role {
+	type Err = Error;
+
+	fn from_str(value: &str) -> Result<Self> {
+		match value {
+			"read_only" => Ok(Self::ReadOnly),
+			"member" => Ok(Self::Member),
+			"admin" => Ok(Self::Admin),
+			"owner" => Ok(Self::Owner),
+			_ => Err(anyhow!("Invalid role {}, expected read_only, member, admin or owner", value).into()),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TopicMember {
+	pub node_id: String,
+	pub role: Role,
+	pub joined_at: i64,
+	pub last_seen: Option<i64>,
+}
+
+/// A member with its profile, when this node has it, and whether it has checked in lately.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Member {
+	pub node_id: String,
+	pub first_name: Option<String>,
+	pub last_name: Option<String>,
+	pub role: Role,
+	pub joined_at: i64,
+	pub last_seen: Option<i64>,
+	pub is_active: bool,
+}
+
+pub trait MemberOperations {
+	/// Adds `node_id` to the topic as a plain member, false if it already was one.
+	async fn add_member(&self, topic_id: &str, node_id: &str) -> Result<bool>;
+	/// Removes `node_id` from the topic, the owner can not be removed.
+	async fn remove_member(&self, topic_id: &str, node_id: &str) -> Result<()>;
+	async fn is_member(&self, topic_id: &str, node_id: &str) -> Result<bool>;
+	/// The owner and every member, in the order they joined.
+	async fn list_members(&self, topic_id: &str) -> Result<Vec<TopicMember>>;
+	/// Like [`MemberOperations::list_members`] with profiles, members last seen at `online_since` or later are active.
+	async fn list_member_presence(&self, topic_id: &str, online_since: i64) -> Result<Vec<Member>>;
+	/// Records a check-in from `node_id` at `last_seen`.
+	async fn set_last_seen(&self, topic_id: &str, node_id: &str, last_seen: i64) -> Result<()>;
+	/// The role of `node_id`, nodes that are not in the topic count as plain members.
+	async fn get_role(&self, topic_id: &str, node_id: &str) -> Result<Role>;
+	/// Changes the role of a member other than the owner, see [`TopicOperations::set_topic_owner`].
+	///
+	/// [`TopicOperations::set_topic_owner`]: super::topic::TopicOperations::set_topic_owner
+	async fn set_role(&self, topic_id: &str, node_id: &str, role: Role) -> Result<()>;
+	/// Roles other than the owner's, plain members are left out.
+	async fn list_roles(&self, topic_id: &str) -> Result<BTreeMap<String, Role>>;
+}
+
+impl MemberOperations for Db {
+	async fn add_member(&self, topic_id: &str, node_id: &str) -> Result<bool> {
+		let joined_at = chrono::Utc::now().timestamp();
+		let result = sqlx::query!(
+			r#"
+                INSERT OR IGNORE INTO topic_members (topic_id, node_id, joined_at)
+                VALUES ($1, $2, $3)
+                "#,
+			topic_id,
+			node_id,
+			joined_at
+		)
+		.execute(&self.0)
+		.await?;
+		Ok(result.rows_affected() > 0)
+	}
+
+	async fn remove_member(&self, topic_id: &str, node_id: &str) -> Result<()> {
+		let mut tx = self.0.begin().await?;
+		let result = sqlx::query!(
+			r#"
+                DELETE FROM topic_members
+                WHERE topic_id = $1 AND node_id = $2 AND role != 'owner'
+                "#,
+			topic_id,
+			node_id
+		)
+		.execute(&mut *tx)
+		.await?;
+		// Downloads no longer turn to a node that left the topic
+		if result.rows_affected() > 0 {
+			sqlx::query!(
+				r#"
+                    DELETE FROM blob_holders
+                    WHERE topic_id = $1 AND node_id = $2
+                    "#,
+				topic_id,
+				node_id
+			)
+			.execute(&mut *tx)
+			.await?;
+		}
+		tx.commit().await?;
+		Ok(())
+	}
+
+	async fn is_member(&self, topic_id: &str, node_id: &str) -> Result<bool> {
+		let is_member = sqlx::query_scalar!(
+			r#"
+                SELECT EXISTS(
+                    SELECT 1 FROM topic_members WHERE topic_id = $1 AND node_id = $2
+                ) as "is_member!: bool"
+                "#,
+			topic_id,
+			node_id
+		)
+		.fetch_one(&self.0)
+		.await?;
+		Ok(is_member)
+	}
+
+	async fn list_members(&self, topic_id: &str) -> Result<Vec<TopicMember>> {
+		let members = sqlx::query_as!(
+			TopicMember,
+			r#"
+                SELECT node_id, role as "role: Role", joined_at, last_seen
+                FROM topic_members
+                WHERE topic_id = $1
+                ORDER BY role = 'owner' DESC, joined_at, rowid
+                "#,
+			topic_id
+		)
+		.fetch_all(&self.0)
+		.await?;
+		Ok(members)
+	}
+
+	async fn list_member_presence(&self, topic_id: &str, online_since: i64) -> Result<Vec<Member>> {
+		let members = sqlx::query_as!(
+			Member,
+			r#"
+                SELECT
+                    m.node_id,
+                    u.first_name as "first_name?",
+                    u.last_name,
+                    m.role as "role: Role",
+                    m.joined_at,
+                    m.last_seen,
+                    COALESCE(m.last_seen >= $2, 0) as "is_active!: bool"
+                FROM topic_members m
+                LEFT JOIN nodes n ON n.node_id = m.node_id
+                LEFT JOIN users u ON u.node_id = n.id
+                WHERE m.topic_id = $1
+                ORDER BY m.role = 'owner' DESC, m.joined_at, m.rowid
+                "#,
+			topic_id,
+			online_since
+		)
+		.fetch_all(&self.0)
+		.await?;
+		Ok(members)
+	}
+
+	async fn set_last_seen(&self, topic_id: &str, node_id: &str, last_seen: i64) -> Result<()> {
+		sqlx::query!(
+			r#"
+                UPDATE topic_members
+                SET last_seen = $3
+                WHERE topic_id = $1 AND node_id = $2
+                "#,
+			topic_id,
+			node_id,
+			last_seen
+		)
+		.execute(&self.0)
+		.await?;
+		Ok(())
+	}
+
+	async fn get_role(&self, topic_id: &str, node_id: &str) -> Result<Role> {
+		let role = sqlx::query_scalar!(
+			r#"
+                SELECT role as "role: Role"
+                FROM topic_members
+                WHERE topic_id = $1 AND node_id = $2
+                "#,
+			topic_id,
+			node_id
+		)
+		.fetch_optional(&self.0)
+		.await?;
+		Ok(role.unwrap_or(Role::Member))
+	}
+
+	async fn set_role(&self, topic_id: &str, node_id: &str, role: Role) -> Result<()> {
+		if role == Role::Owner {
+			return Err(anyhow!("Ownership is transferred, not assigned").into());
+		}
+		sqlx::query!(
+			r#"
+                UPDATE topic_members
+                SET role = $3
+                WHERE topic_id = $1 AND node_id = $2 AND role != 'owner'
+                "#,
+			topic_id,
+			node_id,
+			role
+		)
+		.execute(&self.0)
+		.await?;
+		Ok(())
+	}
+
+	async fn list_roles(&self, topic_id: &str) -> Result<BTreeMap<String, Role>> {
+		let records = sqlx::query!(
+			r#"
+                SELECT node_id, role as "role: Role"
+                FROM topic_members
+                WHERE topic_id = $1 AND role NOT IN ('member', 'owner')
+                "#,
+			topic_id
+		)
+		.fetch_all(&self.0)
+		.await?;
+		Ok(records
+			.into_iter()
+			.map(|record| (record.node_id, record.role))
+			.collect())
+	}
+}
@@ -0,0 +1,187 @@
+use crate::error::Result;
+
+use super::{
+	file::{TsDirection, TsFilter},
+	Db,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Row};
+use std::collections::HashMap;
+
+pub const DEFAULT_MESSAGE_PAGE_SIZE: i64 = 50;
+pub const MAX_MESSAGE_PAGE_SIZE: i64 = 200;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct Message {
+	pub id: i64,
+	pub node_id: String,
+	pub topic_id: String,
+	pub content: String,
+	pub sent_at: i64,
+}
+
+impl Message {
+	pub(crate) fn new(node_id: String, topic_id: String, content: String, sent_at: i64) -> Self {
+		Self {
+			id: 0, // This will be set by the database
+			node_id,
+			topic_id,
+			content,
+			sent_at,
+		}
+	}
+}
+
+pub trait MessageOperations {
+	async fn create_message(&self, message: Message) -> Result<Message>;
+	/// Returns a page of messages of a topic, newest first.
+	/// `before` is the id of the oldest message the caller already has, `None` starts from the latest message.
+	async fn list_messages(&self, topic_id: String, before: Option<i64>, limit: i64) -> Result<Vec<Message>>;
+	/// Stores a message received through history sync, returns `None` if it was already stored.
+	async fn sync_message(&self, message: Message) -> Result<Option<Message>>;
+	/// Returns the messages a node sent in a topic, oldest first.
+	async fn list_messages_by_node(
+		&self,
+		topic_id: String,
+		node_id: String,
+		ts_filter: TsFilter,
+		limit: i64,
+	) -> Result<Vec<Message>>;
+	/// Returns the latest message timestamp per member and how many of its messages were sent at it, 0 for both
+	/// when it sent none.
+	async fn get_latest_message_timestamps_by_members(
+		&self,
+		topic_id: &str,
+		members: &[String],
+	) -> Result<HashMap<String, (i64, i64)>>;
+}
+
+impl MessageOperations for Db {
+	async fn create_message(&self, message: Message) -> Result<Message> {
+		let message = sqlx::query_as!(
+			Message,
+			r#"
+                INSERT INTO messages (node_id, topic_id, content, sent_at)
+                VALUES ($1, $2, $3, $4)
+                RETURNING id, node_id, topic_id, content, sent_at
+                "#,
+			message.node_id,
+			message.topic_id,
+			message.content,
+			message.sent_at
+		)
+		.fetch_one(&self.0)
+		.await?;
+		Ok(message)
+	}
+
+	async fn list_messages(&self, topic_id: String, before: Option<i64>, limit: i64) -> Result<Vec<Message>> {
+		let mut query = String::from("SELECT id, node_id, topic_id, content, sent_at FROM messages WHERE topic_id = ?");
+		// Messages synced from peers can be inserted out of order, so the cursor is (sent_at, id) and not just the id
+		if before.is_some() {
+			query.push_str(" AND (sent_at, id) < (SELECT sent_at, id FROM messages WHERE id = ?)");
+		}
+		query.push_str(" ORDER BY sent_at DESC, id DESC LIMIT ?");
+
+		let mut q = sqlx::query_as::<_, Message>(&query).bind(&topic_id);
+		if let Some(before) = before {
+			q = q.bind(before);
+		}
+		let messages = q.bind(limit.clamp(1, MAX_MESSAGE_PAGE_SIZE)).fetch_all(&self.0).await?;
+		Ok(messages)
+	}
+
+	async fn sync_message(&self, message: Message) -> Result<Option<Message>> {
+		let message = sqlx::query_as!(
+			Message,
+			r#"
+                INSERT INTO messages (node_id, topic_id, content, sent_at)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT DO NOTHING
+                RETURNING id, node_id, topic_id, content, sent_at
+                "#,
+			message.node_id,
+			message.topic_id,
+			message.content,
+			message.sent_at
+		)
+		.fetch_optional(&self.0)
+		.await?;
+		Ok(message)
+	}
+
+	async fn list_messages_by_node(
+		&self,
+		topic_id: String,
+		node_id: String,
+		ts_filter: TsFilter,
+		limit: i64,
+	) -> Result<Vec<Message>> {
+		let mut query = String::from(
+			"SELECT id, node_id, topic_id, content, sent_at FROM messages WHERE topic_id = ? AND node_id = ? AND sent_at ",
+		);
+		query.push_str(match ts_filter.direction {
+			TsDirection::Newer => ">",
+			TsDirection::NewerOrSame => ">=",
+		});
+		query.push_str(" ? ORDER BY sent_at ASC, id ASC LIMIT ?");
+
+		let messages = sqlx::query_as::<_, Message>(&query)
+			.bind(&topic_id)
+			.bind(&node_id)
+			.bind(ts_filter.timestamp)
+			.bind(limit)
+			.fetch_all(&self.0)
+			.await?;
+		Ok(messages)
+	}
+
+	async fn get_latest_message_timestamps_by_members(
+		&self,
+		topic_id: &str,
+		members: &[String],
+	) -> Result<HashMap<String, (i64, i64)>> {
+		let mut result = HashMap::new();
+
+		if members.is_empty() {
+			return Ok(result);
+		}
+
+		// Create placeholders for SQL IN clause
+		let placeholders = members.iter().map(|_| "?").collect::<Vec<_>>().join(", ");
+		let query = format!(
+			r#"
+                SELECT node_id, sent_at as latest_timestamp, COUNT(*) as latest_count
+                FROM messages m
+                WHERE topic_id = ? AND node_id IN ({})
+                    AND sent_at = (
+                        SELECT MAX(sent_at) FROM messages WHERE topic_id = m.topic_id AND node_id = m.node_id
+                    )
+                GROUP BY node_id
+                "#,
+			placeholders
+		);
+
+		let mut query_builder = sqlx::query(&query).bind(topic_id);
+		for member in members {
+			query_builder = query_builder.bind(member);
+		}
+
+		let rows = query_builder.fetch_all(&self.0).await?;
+
+		for row in rows {
+			let node_id: String = row.get("node_id");
+			let timestamp: i64 = row.get("latest_timestamp");
+			let count: i64 = row.get("latest_count");
+			result.insert(node_id, (timestamp, count));
+		}
+
+		// Fill in 0 for members with no messages
+		for member in members {
+			result.entry(member.clone()).or_insert((0, 0));
+		}
+
+		Ok(result)
+	}
+}
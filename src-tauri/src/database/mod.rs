@@ -1,31 +1,115 @@
-use std::str::FromStr;
+// The `*Operations` traits are only implemented by `Db`, so callers always see its concrete (Send) futures
+#![allow(async_fn_in_trait)]
 
-use sqlx::{migrate::Migrator, sqlite::SqliteConnectOptions, Pool, Sqlite};
+use std::path::Path;
 
-use crate::error::Result;
+use sqlx::{
+	migrate::Migrator,
+	sqlite::{SqliteConnectOptions, SqliteJournalMode},
+	ConnectOptions, Connection, Pool, Sqlite,
+};
 
-pub(crate) mod file;
-pub(crate) mod node;
-pub(crate) mod topic;
-pub(crate) mod user;
+use crate::error::{Error, Result};
+
+#[cfg(feature = "sqlcipher")]
+mod cipher;
+pub mod download;
+pub mod file;
+pub mod member;
+pub mod message;
+pub mod moderation;
+pub mod node;
+pub mod settings;
+pub mod ticket;
+pub mod topic;
+pub mod user;
 static MIGRATOR: Migrator = sqlx::migrate!();
 
+// SQLite reports a database it cannot decrypt as not being a database at all
+const SQLITE_NOTADB: &str = "26";
+
 #[derive(Debug, Clone)]
 pub struct Db(Pool<Sqlite>);
 
 impl Db {
-	pub async fn init(db_path: &str, passphrase: String) -> Result<Self> {
-		let opts = SqliteConnectOptions::from_str(db_path)?
-			.pragma("key", passphrase)
-			.journal_mode(sqlx::sqlite::SqliteJournalMode::Wal)
+	/// Opens the database at `db_path` with `passphrase`, creating it if missing.
+	/// With the `sqlcipher` feature the file is encrypted with the passphrase, a plaintext database is encrypted first.
+	pub async fn init(db_path: &Path, passphrase: &str) -> Result<Self> {
+		#[cfg(feature = "sqlcipher")]
+		if cipher::is_plaintext(db_path)? {
+			cipher::encrypt_plaintext(db_path, passphrase).await?;
+		}
+
+		let opts = connect_options(db_path, passphrase)
+			.journal_mode(SqliteJournalMode::Wal)
 			.create_if_missing(true);
-		let pool = Pool::<Sqlite>::connect_with(opts).await?;
+		let pool = Pool::<Sqlite>::connect_with(opts).await.map_err(passphrase_error)?;
 		MIGRATOR.run(&pool).await?;
 		Ok(Self(pool))
 	}
 
+	/// Whether the database at `db_path` was written before encryption, opening it encrypts it with the passphrase given.
+	pub fn is_plaintext(db_path: &Path) -> Result<bool> {
+		#[cfg(feature = "sqlcipher")]
+		return cipher::is_plaintext(db_path);
+		#[cfg(not(feature = "sqlcipher"))]
+		{
+			let _ = db_path;
+			Ok(false)
+		}
+	}
+
 	pub async fn close(&self) -> Result<()> {
 		self.0.close().await;
 		Ok(())
 	}
+
+	/// Fails with [`Error::Passphrase`] if `passphrase` does not open the database at `db_path`.
+	pub async fn check_passphrase(db_path: &Path, passphrase: &str) -> Result<()> {
+		let mut conn = connect_options(db_path, passphrase)
+			.connect()
+			.await
+			.map_err(passphrase_error)?;
+		let result = sqlx::query("SELECT count(*) FROM sqlite_master")
+			.fetch_one(&mut conn)
+			.await
+			.map_err(passphrase_error);
+		conn.close().await?;
+		result.map(|_| ())
+	}
+
+	/// Re-encrypts the database at `db_path` with `new_passphrase`.
+	/// Every pool on the database has to be closed first, it is reopened with the new passphrase afterwards.
+	#[cfg(feature = "sqlcipher")]
+	pub async fn change_passphrase(db_path: &Path, passphrase: &str, new_passphrase: &str) -> Result<()> {
+		Self::check_passphrase(db_path, passphrase).await?;
+		// Attaching the new file creates it, which needs the create flag on the connection
+		let mut conn = connect_options(db_path, passphrase)
+			.create_if_missing(true)
+			.connect()
+			.await?;
+		let target = cipher::sibling(db_path, "rekey");
+		let result = cipher::export(&mut conn, &target, new_passphrase).await;
+		conn.close().await?;
+		result?;
+		cipher::replace(db_path, &target)
+	}
+}
+
+fn connect_options(db_path: &Path, passphrase: &str) -> SqliteConnectOptions {
+	let opts = SqliteConnectOptions::new().filename(db_path);
+	#[cfg(feature = "sqlcipher")]
+	let opts = opts.pragma("key", cipher::quote(passphrase));
+	#[cfg(not(feature = "sqlcipher"))]
+	let _ = passphrase;
+	opts
+}
+
+fn passphrase_error(e: sqlx::Error) -> Error {
+	match &e {
+		sqlx::Error::Database(db_err) if db_err.code().as_deref() == Some(SQLITE_NOTADB) => {
+			Error::Passphrase("Wrong passphrase".to_string())
+		},
+		_ => e.into(),
+	}
 }
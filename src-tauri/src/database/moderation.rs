@@ -0,0 +1,355 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+use super::Db;
+use crate::{
+	comm::{model::UserInfo, ticket::Grant},
+	error::Result,
+};
+
+/// A newcomer with a valid ticket, waiting for the topic owner to let it in.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct JoinRequest {
+	pub topic_id: String,
+	pub node_id: String,
+	pub email: String,
+	pub first_name: String,
+	pub last_name: Option<String>,
+	pub requested_at: i64,
+}
+
+pub trait ModerationOperations {
+	async fn ban_node(&self, topic_id: &str, node_id: &str) -> Result<()>;
+	async fn unban_node(&self, topic_id: &str, node_id: &str) -> Result<()>;
+	async fn is_banned(&self, topic_id: &str, node_id: &str) -> Result<bool>;
+	async fn list_banned(&self, topic_id: &str) -> Result<Vec<String>>;
+	async fn set_require_approval(&self, topic_id: &str, require_approval: bool) -> Result<()>;
+	async fn requires_approval(&self, topic_id: &str) -> Result<bool>;
+	/// Queues a join request with the grant it came with, returns false if the node already has a pending or denied one.
+	async fn create_join_request(
+		&self,
+		topic_id: &str,
+		node_id: &str,
+		user_info: &UserInfo,
+		grant: &Grant,
+	) -> Result<bool>;
+	async fn list_join_requests(&self, topic_id: &str) -> Result<Vec<JoinRequest>>;
+	async fn get_join_request(&self, topic_id: &str, node_id: &str) -> Result<Option<JoinRequest>>;
+	/// The grant of a pending join request, `None` for requests queued before grants were kept.
+	async fn get_join_request_grant(&self, topic_id: &str, node_id: &str) -> Result<Option<Grant>>;
+	async fn delete_join_request(&self, topic_id: &str, node_id: &str) -> Result<()>;
+	/// Keeps the request as denied, so further check-ins from the node do not queue it again.
+	async fn deny_join_request(&self, topic_id: &str, node_id: &str) -> Result<()>;
+	/// Marks the tickets `node_id` was admitted with as spent for it, along with the ones in `nonces`.
+	async fn spend_tickets(&self, topic_id: &str, node_id: &str, nonces: &[String]) -> Result<()>;
+	async fn is_ticket_spent(&self, topic_id: &str, node_id: &str, nonce: &str) -> Result<bool>;
+	/// The spent tickets of nodes that are out of the topic without being banned, at most `limit` nodes.
+	async fn list_removed(&self, topic_id: &str, limit: i64) -> Result<BTreeMap<String, Vec<String>>>;
+	/// Takes the sequence number for a moderation this node sends to the topic.
+	async fn next_moderation_seq(&self, topic_id: &str) -> Result<i64>;
+	/// Records `seq` as the last moderation applied to the topic, returns false if it is not newer than that one.
+	async fn advance_moderation_seq(&self, topic_id: &str, seq: i64) -> Result<bool>;
+}
+
+impl ModerationOperations for Db {
+	async fn ban_node(&self, topic_id: &str, node_id: &str) -> Result<()> {
+		let banned_at = chrono::Utc::now().timestamp();
+		sqlx::query!(
+			r#"
+                INSERT OR IGNORE INTO topic_bans (topic_id, node_id, banned_at)
+                VALUES ($1, $2, $3)
+                "#,
+			topic_id,
+			node_id,
+			banned_at
+		)
+		.execute(&self.0)
+		.await?;
+		Ok(())
+	}
+
+	async fn unban_node(&self, topic_id: &str, node_id: &str) -> Result<()> {
+		sqlx::query!(
+			r#"
+                DELETE FROM topic_bans
+                WHERE topic_id = $1 AND node_id = $2
+                "#,
+			topic_id,
+			node_id
+		)
+		.execute(&self.0)
+		.await?;
+		Ok(())
+	}
+
+	async fn is_banned(&self, topic_id: &str, node_id: &str) -> Result<bool> {
+		let banned = sqlx::query_scalar!(
+			r#"
+                SELECT EXISTS (SELECT 1 FROM topic_bans WHERE topic_id = $1 AND node_id = $2) as "banned!: bool"
+                "#,
+			topic_id,
+			node_id
+		)
+		.fetch_one(&self.0)
+		.await?;
+		Ok(banned)
+	}
+
+	async fn list_banned(&self, topic_id: &str) -> Result<Vec<String>> {
+		let banned = sqlx::query_scalar!(
+			r#"
+                SELECT node_id
+                FROM topic_bans
+                WHERE topic_id = $1
+                ORDER BY banned_at, node_id
+                "#,
+			topic_id
+		)
+		.fetch_all(&self.0)
+		.await?;
+		Ok(banned)
+	}
+
+	async fn set_require_approval(&self, topic_id: &str, require_approval: bool) -> Result<()> {
+		sqlx::query!(
+			r#"
+                UPDATE topics
+                SET require_approval = $1
+                WHERE topic_id = $2
+                "#,
+			require_approval,
+			topic_id
+		)
+		.execute(&self.0)
+		.await?;
+		Ok(())
+	}
+
+	async fn requires_approval(&self, topic_id: &str) -> Result<bool> {
+		let require_approval = sqlx::query_scalar!(
+			r#"
+                SELECT require_approval as "require_approval: bool"
+                FROM topics
+                WHERE topic_id = $1
+                "#,
+			topic_id
+		)
+		.fetch_optional(&self.0)
+		.await?;
+		Ok(require_approval.unwrap_or(false))
+	}
+
+	async fn create_join_request(
+		&self,
+		topic_id: &str,
+		node_id: &str,
+		user_info: &UserInfo,
+		grant: &Grant,
+	) -> Result<bool> {
+		let requested_at = chrono::Utc::now().timestamp();
+		let grant = serde_json::to_string(grant)?;
+		let result = sqlx::query!(
+			r#"
+                INSERT OR IGNORE INTO join_requests (topic_id, node_id, email, first_name, last_name, requested_at, join_grant)
+                VALUES ($1, $2, $3, $4, $5, $6, $7)
+                "#,
+			topic_id,
+			node_id,
+			user_info.email,
+			user_info.first_name,
+			user_info.last_name,
+			requested_at,
+			grant
+		)
+		.execute(&self.0)
+		.await?;
+		Ok(result.rows_affected() > 0)
+	}
+
+	async fn list_join_requests(&self, topic_id: &str) -> Result<Vec<JoinRequest>> {
+		let requests = sqlx::query_as!(
+			JoinRequest,
+			r#"
+                SELECT topic_id, node_id, email, first_name, last_name, requested_at
+                FROM join_requests
+                WHERE topic_id = $1 AND status = 'pending'
+                ORDER BY requested_at, node_id
+                "#,
+			topic_id
+		)
+		.fetch_all(&self.0)
+		.await?;
+		Ok(requests)
+	}
+
+	async fn get_join_request(&self, topic_id: &str, node_id: &str) -> Result<Option<JoinRequest>> {
+		let request = sqlx::query_as!(
+			JoinRequest,
+			r#"
+                SELECT topic_id, node_id, email, first_name, last_name, requested_at
+                FROM join_requests
+                WHERE topic_id = $1 AND node_id = $2 AND status = 'pending'
+                "#,
+			topic_id,
+			node_id
+		)
+		.fetch_optional(&self.0)
+		.await?;
+		Ok(request)
+	}
+
+	async fn get_join_request_grant(&self, topic_id: &str, node_id: &str) -> Result<Option<Grant>> {
+		let grant = sqlx::query_scalar!(
+			r#"
+                SELECT join_grant
+                FROM join_requests
+                WHERE topic_id = $1 AND node_id = $2 AND status = 'pending'
+                "#,
+			topic_id,
+			node_id
+		)
+		.fetch_optional(&self.0)
+		.await?
+		.flatten();
+		Ok(grant.map(|grant| serde_json::from_str(&grant)).transpose()?)
+	}
+
+	async fn delete_join_request(&self, topic_id: &str, node_id: &str) -> Result<()> {
+		sqlx::query!(
+			r#"
+                DELETE FROM join_requests
+                WHERE topic_id = $1 AND node_id = $2
+                "#,
+			topic_id,
+			node_id
+		)
+		.execute(&self.0)
+		.await?;
+		Ok(())
+	}
+
+	async fn deny_join_request(&self, topic_id: &str, node_id: &str) -> Result<()> {
+		sqlx::query!(
+			r#"
+                UPDATE join_requests
+                SET status = 'denied'
+                WHERE topic_id = $1 AND node_id = $2
+                "#,
+			topic_id,
+			node_id
+		)
+		.execute(&self.0)
+		.await?;
+		Ok(())
+	}
+
+	async fn spend_tickets(&self, topic_id: &str, node_id: &str, nonces: &[String]) -> Result<()> {
+		let mut tx = self.0.begin().await?;
+		sqlx::query!(
+			r#"
+                INSERT OR IGNORE INTO spent_tickets (topic_id, node_id, nonce)
+                SELECT $1, $2, r.nonce
+                FROM ticket_redemptions r
+                LEFT JOIN issued_tickets t ON t.nonce = r.nonce
+                WHERE r.node_id = $2 AND COALESCE(r.topic_id, t.topic_id) = $1
+                "#,
+			topic_id,
+			node_id
+		)
+		.execute(&mut *tx)
+		.await?;
+		for nonce in nonces {
+			sqlx::query!(
+				r#"
+                    INSERT OR IGNORE INTO spent_tickets (topic_id, node_id, nonce)
+                    VALUES ($1, $2, $3)
+                    "#,
+				topic_id,
+				node_id,
+				nonce
+			)
+			.execute(&mut *tx)
+			.await?;
+		}
+		tx.commit().await?;
+		Ok(())
+	}
+
+	async fn is_ticket_spent(&self, topic_id: &str, node_id: &str, nonce: &str) -> Result<bool> {
+		let spent = sqlx::query_scalar!(
+			r#"
+                SELECT EXISTS (
+                    SELECT 1 FROM spent_tickets WHERE topic_id = $1 AND node_id = $2 AND nonce = $3
+                ) as "spent!: bool"
+                "#,
+			topic_id,
+			node_id,
+			nonce
+		)
+		.fetch_one(&self.0)
+		.await?;
+		Ok(spent)
+	}
+
+	async fn list_removed(&self, topic_id: &str, limit: i64) -> Result<BTreeMap<String, Vec<String>>> {
+		let spent = sqlx::query!(
+			r#"
+                SELECT s.node_id, s.nonce
+                FROM spent_tickets s
+                WHERE s.topic_id = $1
+                    AND s.node_id IN (
+                        SELECT node_id
+                        FROM spent_tickets
+                        WHERE topic_id = $1
+                            AND node_id NOT IN (SELECT node_id FROM topic_members WHERE topic_id = $1)
+                            AND node_id NOT IN (SELECT node_id FROM topic_bans WHERE topic_id = $1)
+                        GROUP BY node_id
+                        ORDER BY MAX(rowid) DESC
+                        LIMIT $2
+                    )
+                "#,
+			topic_id,
+			limit
+		)
+		.fetch_all(&self.0)
+		.await?;
+		let mut removed: BTreeMap<String, Vec<String>> = BTreeMap::new();
+		for row in spent {
+			removed.entry(row.node_id).or_default().push(row.nonce);
+		}
+		Ok(removed)
+	}
+
+	async fn next_moderation_seq(&self, topic_id: &str) -> Result<i64> {
+		let seq = sqlx::query_scalar!(
+			r#"
+                UPDATE topics
+                SET moderation_seq = moderation_seq + 1
+                WHERE topic_id = $1
+                RETURNING moderation_seq
+                "#,
+			topic_id
+		)
+		.fetch_one(&self.0)
+		.await?;
+		Ok(seq)
+	}
+
+	async fn advance_moderation_seq(&self, topic_id: &str, seq: i64) -> Result<bool> {
+		let result = sqlx::query!(
+			r#"
+                UPDATE topics
+                SET moderation_seq = $1
+                WHERE topic_id = $2 AND moderation_seq < $1
+                "#,
+			seq,
+			topic_id
+		)
+		.execute(&self.0)
+		.await?;
+		Ok(result.rows_affected() > 0)
+	}
+}
@@ -3,28 +3,33 @@ use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 
+// Only the public identity lives here, secret keys are kept in the keystore
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, FromRow)]
 #[serde(rename_all = "camelCase")]
 pub struct Node {
 	pub id: i64,
 	pub node_id: String,
-	pub(crate) secret_key: Option<String>,
 }
 
 impl Node {
-	pub(crate) fn new(node_id: String, secret_key: Option<String>) -> Self {
+	pub(crate) fn new(node_id: String) -> Self {
 		Self {
 			id: 0, // This will be set by the database
 			node_id,
-			secret_key,
 		}
 	}
 }
 
 pub trait NodeOperations {
 	async fn create_node(&self, node: Node) -> Result<Node>;
+	/// The node with this id, added if it is not known yet. Topics that hear from a new node at once all get the same one.
+	async fn get_or_create_node(&self, node_id: &str) -> Result<Node>;
 	async fn get_node_by_id(&self, id: i64) -> Result<Node>;
 	async fn get_node_by_node_id(&self, node_id: String) -> Result<Node>;
+	async fn update_node(&self, id: i64, node_id: String) -> Result<Node>;
+	/// The base32 secret key older versions stored in the `nodes` table, if it is still there.
+	async fn get_legacy_secret_key(&self, id: i64) -> Result<Option<String>>;
+	async fn clear_legacy_secret_key(&self, id: i64) -> Result<()>;
 }
 
 impl NodeOperations for Db {
@@ -32,23 +37,36 @@ impl NodeOperations for Db {
 		let node = sqlx::query_as!(
 			Node,
 			r#"
-            INSERT INTO nodes (node_id, secret_key)
-            VALUES ($1, $2)
-            RETURNING id, node_id, secret_key
+            INSERT INTO nodes (node_id)
+            VALUES ($1)
+            RETURNING id, node_id
             "#,
-			node.node_id,
-			node.secret_key
+			node.node_id
 		)
 		.fetch_one(&self.0)
 		.await?;
 		Ok(node)
 	}
 
+	async fn get_or_create_node(&self, node_id: &str) -> Result<Node> {
+		sqlx::query!(
+			r#"
+            INSERT INTO nodes (node_id)
+            VALUES ($1)
+            ON CONFLICT (node_id) DO NOTHING
+            "#,
+			node_id
+		)
+		.execute(&self.0)
+		.await?;
+		self.get_node_by_node_id(node_id.to_string()).await
+	}
+
 	async fn get_node_by_id(&self, id: i64) -> Result<Node> {
 		let node = sqlx::query_as!(
 			Node,
 			r#"
-                SELECT id, node_id, secret_key
+                SELECT id, node_id
                 FROM nodes
             WHERE id = $1
             "#,
@@ -63,7 +81,7 @@ impl NodeOperations for Db {
 		let node = sqlx::query_as!(
 			Node,
 			r#"
-                SELECT id, node_id, secret_key
+                SELECT id, node_id
                 FROM nodes
                 WHERE node_id = $1
             "#,
@@ -74,4 +92,52 @@ impl NodeOperations for Db {
 
 		Ok(node)
 	}
+
+	async fn update_node(&self, id: i64, node_id: String) -> Result<Node> {
+		let node = sqlx::query_as!(
+			Node,
+			r#"
+                UPDATE nodes
+                SET node_id = $1
+                WHERE id = $2
+                RETURNING id, node_id
+            "#,
+			node_id,
+			id
+		)
+		.fetch_one(&self.0)
+		.await?;
+
+		Ok(node)
+	}
+
+	async fn get_legacy_secret_key(&self, id: i64) -> Result<Option<String>> {
+		let secret_key = sqlx::query_scalar!(
+			r#"
+                SELECT secret_key
+                FROM nodes
+                WHERE id = $1
+            "#,
+			id
+		)
+		.fetch_optional(&self.0)
+		.await?;
+
+		Ok(secret_key.flatten())
+	}
+
+	async fn clear_legacy_secret_key(&self, id: i64) -> Result<()> {
+		sqlx::query!(
+			r#"
+                UPDATE nodes
+                SET secret_key = NULL
+                WHERE id = $1
+            "#,
+			id
+		)
+		.execute(&self.0)
+		.await?;
+
+		Ok(())
+	}
 }
@@ -0,0 +1,39 @@
+use crate::error::Result;
+
+use super::Db;
+
+pub trait SettingsOperations {
+	async fn get_setting(&self, key: &str) -> Result<Option<String>>;
+	async fn set_setting(&self, key: &str, value: &str) -> Result<()>;
+}
+
+impl SettingsOperations for Db {
+	async fn get_setting(&self, key: &str) -> Result<Option<String>> {
+		let value = sqlx::query_scalar!(
+			r#"
+                SELECT value
+                FROM settings
+                WHERE key = $1
+                "#,
+			key
+		)
+		.fetch_optional(&self.0)
+		.await?;
+		Ok(value)
+	}
+
+	async fn set_setting(&self, key: &str, value: &str) -> Result<()> {
+		sqlx::query!(
+			r#"
+                INSERT INTO settings (key, value)
+                VALUES ($1, $2)
+                ON CONFLICT (key) DO UPDATE SET value = excluded.value
+                "#,
+			key,
+			value
+		)
+		.execute(&self.0)
+		.await?;
+		Ok(())
+	}
+}
@@ -0,0 +1,203 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+use super::Db;
+use crate::{comm::ticket::Grant, error::Result};
+
+/// A ticket this node issued, as listed to the user. The signature stays with the ticket itself.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct IssuedTicket {
+	pub nonce: String,
+	pub topic_id: String,
+	pub expires_at: Option<i64>,
+	pub max_uses: Option<i64>,
+	pub uses: i64, // Newcomers this node saw join with it
+	pub revoked: bool,
+	pub created_at: i64,
+}
+
+pub trait TicketOperations {
+	async fn create_issued_ticket(&self, grant: &Grant) -> Result<()>;
+	async fn list_issued_tickets(&self, topic_id: Option<String>) -> Result<Vec<IssuedTicket>>;
+	async fn revoke_ticket(&self, nonce: &str, issuer: &str) -> Result<()>;
+	async fn is_ticket_revoked(&self, nonce: &str, issuer: &str) -> Result<bool>;
+	/// Whether `node_id` could still be admitted with the ticket, without taking one of its uses.
+	async fn can_redeem_ticket(&self, nonce: &str, node_id: &str, max_uses: Option<u32>) -> Result<bool>;
+	/// Records `node_id` as admitted with the ticket, returns false if it would exceed `max_uses`.
+	async fn redeem_ticket(&self, topic_id: &str, nonce: &str, node_id: &str, max_uses: Option<u32>) -> Result<bool>;
+	/// The tickets `node_id` was admitted to the topic with.
+	async fn list_redeemed_nonces(&self, topic_id: &str, node_id: &str) -> Result<Vec<String>>;
+	async fn set_join_grant(&self, topic_id: &str, grant: &Grant) -> Result<()>;
+	async fn get_join_grant(&self, topic_id: &str) -> Result<Option<Grant>>;
+}
+
+impl TicketOperations for Db {
+	async fn create_issued_ticket(&self, grant: &Grant) -> Result<()> {
+		let created_at = chrono::Utc::now().timestamp();
+		sqlx::query!(
+			r#"
+                INSERT INTO issued_tickets (nonce, topic_id, expires_at, max_uses, created_at)
+                VALUES ($1, $2, $3, $4, $5)
+                "#,
+			grant.nonce,
+			grant.topic_id,
+			grant.expires_at,
+			grant.max_uses,
+			created_at
+		)
+		.execute(&self.0)
+		.await?;
+		Ok(())
+	}
+
+	async fn list_issued_tickets(&self, topic_id: Option<String>) -> Result<Vec<IssuedTicket>> {
+		let tickets = sqlx::query_as!(
+			IssuedTicket,
+			r#"
+                SELECT
+                    t.nonce,
+                    t.topic_id,
+                    t.expires_at,
+                    t.max_uses,
+                    (SELECT COUNT(*) FROM ticket_redemptions r WHERE r.nonce = t.nonce) as "uses!: i64",
+                    EXISTS (SELECT 1 FROM revoked_tickets v WHERE v.nonce = t.nonce) as "revoked!: bool",
+                    t.created_at
+                FROM issued_tickets t
+                WHERE $1 IS NULL OR t.topic_id = $1
+                ORDER BY t.created_at DESC, t.rowid DESC
+                "#,
+			topic_id
+		)
+		.fetch_all(&self.0)
+		.await?;
+		Ok(tickets)
+	}
+
+	async fn revoke_ticket(&self, nonce: &str, issuer: &str) -> Result<()> {
+		sqlx::query!(
+			r#"
+                INSERT OR IGNORE INTO revoked_tickets (nonce, issuer)
+                VALUES ($1, $2)
+                "#,
+			nonce,
+			issuer
+		)
+		.execute(&self.0)
+		.await?;
+		Ok(())
+	}
+
+	async fn is_ticket_revoked(&self, nonce: &str, issuer: &str) -> Result<bool> {
+		let revoked = sqlx::query_scalar!(
+			r#"
+                SELECT EXISTS (SELECT 1 FROM revoked_tickets WHERE nonce = $1 AND issuer = $2) as "revoked!: bool"
+                "#,
+			nonce,
+			issuer
+		)
+		.fetch_one(&self.0)
+		.await?;
+		Ok(revoked)
+	}
+
+	async fn can_redeem_ticket(&self, nonce: &str, node_id: &str, max_uses: Option<u32>) -> Result<bool> {
+		let redeemable = sqlx::query_scalar!(
+			r#"
+                SELECT (
+                    $3 IS NULL
+                    OR EXISTS (SELECT 1 FROM ticket_redemptions WHERE nonce = $1 AND node_id = $2)
+                    OR (SELECT COUNT(*) FROM ticket_redemptions WHERE nonce = $1) < $3
+                ) as "redeemable!: bool"
+                "#,
+			nonce,
+			node_id,
+			max_uses
+		)
+		.fetch_one(&self.0)
+		.await?;
+		Ok(redeemable)
+	}
+
+	async fn redeem_ticket(&self, topic_id: &str, nonce: &str, node_id: &str, max_uses: Option<u32>) -> Result<bool> {
+		let redeemed_at = chrono::Utc::now().timestamp();
+		// The count and the insert run as one statement, so two newcomers can not both take the last use
+		let result = sqlx::query!(
+			r#"
+                INSERT OR IGNORE INTO ticket_redemptions (nonce, node_id, redeemed_at, topic_id)
+                SELECT $1, $2, $3, $5
+                WHERE $4 IS NULL OR (SELECT COUNT(*) FROM ticket_redemptions WHERE nonce = $1) < $4
+                "#,
+			nonce,
+			node_id,
+			redeemed_at,
+			max_uses,
+			topic_id
+		)
+		.execute(&self.0)
+		.await?;
+		if result.rows_affected() > 0 {
+			return Ok(true);
+		}
+
+		// Nothing inserted is fine if the node redeemed it before
+		let redeemed = sqlx::query_scalar!(
+			r#"
+                SELECT EXISTS (SELECT 1 FROM ticket_redemptions WHERE nonce = $1 AND node_id = $2) as "redeemed!: bool"
+                "#,
+			nonce,
+			node_id
+		)
+		.fetch_one(&self.0)
+		.await?;
+		Ok(redeemed)
+	}
+
+	async fn list_redeemed_nonces(&self, topic_id: &str, node_id: &str) -> Result<Vec<String>> {
+		// Redemptions from before they kept their topic only have it through the ticket, if this node issued it
+		let nonces = sqlx::query_scalar!(
+			r#"
+                SELECT r.nonce
+                FROM ticket_redemptions r
+                LEFT JOIN issued_tickets t ON t.nonce = r.nonce
+                WHERE r.node_id = $1 AND COALESCE(r.topic_id, t.topic_id) = $2
+                "#,
+			node_id,
+			topic_id
+		)
+		.fetch_all(&self.0)
+		.await?;
+		Ok(nonces)
+	}
+
+	async fn set_join_grant(&self, topic_id: &str, grant: &Grant) -> Result<()> {
+		let grant = serde_json::to_string(grant)?;
+		sqlx::query!(
+			r#"
+                UPDATE topics
+                SET join_grant = $1
+                WHERE topic_id = $2
+                "#,
+			grant,
+			topic_id
+		)
+		.execute(&self.0)
+		.await?;
+		Ok(())
+	}
+
+	async fn get_join_grant(&self, topic_id: &str) -> Result<Option<Grant>> {
+		let grant = sqlx::query_scalar!(
+			r#"
+                SELECT join_grant
+                FROM topics
+                WHERE topic_id = $1
+                "#,
+			topic_id
+		)
+		.fetch_optional(&self.0)
+		.await?
+		.flatten();
+		Ok(grant.map(|grant| serde_json::from_str(&grant)).transpose()?)
+	}
+}
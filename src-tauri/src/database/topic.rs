@@ -2,51 +2,57 @@ use crate::comm::endpoint::new_topic;
 
 use super::Db;
 use anyhow::Result;
-use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
+use serde::{Deserialize, Deserializer, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct Topic {
 	pub id: i64,
 	pub topic_id: String,
 	pub name: String,
-	pub owner: String,                // References a node_id from the nodes table
-	pub members: Option<Vec<String>>, // JSON array of node_ids
+	pub owner: String, // References a node_id from the nodes table
+	#[serde(deserialize_with = "null_as_empty")]
+	pub members: Vec<String>, // Everyone else in topic_members, in the order they joined
+	pub auto_join: bool, // Subscribe to this topic again on startup
+	#[serde(default)]
+	pub archived: bool, // Hidden from the topic list, its data is kept
+}
+
+// Identity bundles exported before topic_members existed have `null` for a topic without members
+fn null_as_empty<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Vec<String>, D::Error> {
+	Ok(Option::deserialize(deserializer)?.unwrap_or_default())
 }
 
 impl Topic {
-	pub(crate) fn new_topic(name: String, owner: String, members: Option<Vec<String>>) -> Self {
+	pub(crate) fn new_topic(name: String, owner: String, members: Vec<String>) -> Self {
 		Self {
 			id: 0, // This will be set by the database
 			topic_id: new_topic().to_string(),
 			name,
 			owner,
 			members,
+			auto_join: true,
+			archived: false,
 		}
 	}
 
 	// This is an entry in the current node's db of an existing topic that the node has joined
-	pub(crate) fn new_topic_with_id(
-		topic_id: String,
-		name: String,
-		owner: String,
-		members: Option<Vec<String>>,
-	) -> Self {
+	pub(crate) fn new_topic_with_id(topic_id: String, name: String, owner: String, members: Vec<String>) -> Self {
 		Self {
 			id: 0, // Set by db
 			topic_id,
 			name,
 			owner,
 			members,
+			auto_join: true,
+			archived: false,
 		}
 	}
 
-	pub(crate) fn get_peers(&self) -> Vec<String> {
+	/// The owner followed by the other members, each of them once.
+	pub fn get_peers(&self) -> Vec<String> {
 		let mut peers = vec![self.owner.clone()];
-		if let Some(members) = &self.members {
-			peers.extend(members.clone());
-		}
+		peers.extend(self.members.iter().filter(|member| **member != self.owner).cloned());
 		peers
 	}
 }
@@ -56,41 +62,67 @@ pub trait TopicOperations {
 	async fn get_topic_by_id(&self, id: i64) -> Result<Topic>;
 	async fn get_topic_by_topic_id(&self, topic_id: String) -> Result<Topic>;
 	async fn list_topics(&self) -> Result<Vec<Topic>>;
-	async fn update_topic(&self, id: i64, members: Vec<String>) -> Result<Topic>;
+	/// Makes `owner` the owner, the previous owner stays on as an admin.
+	async fn set_topic_owner(&self, id: i64, owner: String) -> Result<Topic>;
+	async fn set_topic_auto_join(&self, id: i64, auto_join: bool) -> Result<Topic>;
+	async fn list_auto_join_topics(&self) -> Result<Vec<Topic>>;
+	async fn set_topic_archived(&self, id: i64, archived: bool) -> Result<Topic>;
+	/// Deletes the topic with its members, files, chat history and moderation state.
+	/// Returns the hashes of its files that no file of another topic refers to.
+	async fn delete_topic(&self, id: i64) -> Result<Vec<String>>;
 }
 
 impl TopicOperations for Db {
 	async fn create_topic(&self, topic: Topic) -> Result<Topic> {
-		let members = topic.members.map(|members| members.join(","));
-		let record = sqlx::query!(
+		let joined_at = chrono::Utc::now().timestamp();
+		let mut tx = self.0.begin().await?;
+		let id = sqlx::query_scalar!(
 			r#"
-                INSERT INTO topics (topic_id, owner, members, name)
-                VALUES ($1, $2, $3, $4)
-                RETURNING id, topic_id, owner, members, name
+                INSERT INTO topics (topic_id, owner, name, auto_join, archived)
+                VALUES ($1, $2, $3, $4, $5)
+                RETURNING id
                 "#,
 			topic.topic_id,
 			topic.owner,
-			members,
 			topic.name,
+			topic.auto_join,
+			topic.archived,
 		)
-		.fetch_one(&self.0)
+		.fetch_one(&mut *tx)
 		.await?;
-		let topic = Topic {
-			id: record.id,
-			topic_id: record.topic_id,
-			name: record.name,
-			owner: record.owner,
-			members: record
-				.members
-				.map(|members| members.split(',').map(String::from).collect()),
-		};
-		Ok(topic)
+		sqlx::query!(
+			r#"
+                INSERT INTO topic_members (topic_id, node_id, joined_at, role)
+                VALUES ($1, $2, $3, 'owner')
+                "#,
+			topic.topic_id,
+			topic.owner,
+			joined_at
+		)
+		.execute(&mut *tx)
+		.await?;
+		for member in &topic.members {
+			sqlx::query!(
+				r#"
+                    INSERT OR IGNORE INTO topic_members (topic_id, node_id, joined_at)
+                    VALUES ($1, $2, $3)
+                    "#,
+				topic.topic_id,
+				member,
+				joined_at
+			)
+			.execute(&mut *tx)
+			.await?;
+		}
+		tx.commit().await?;
+
+		self.get_topic_by_id(id).await
 	}
 
 	async fn get_topic_by_id(&self, id: i64) -> Result<Topic> {
 		let record = sqlx::query!(
 			r#"
-                SELECT id, topic_id, owner, members, name
+                SELECT id, topic_id, owner, name, auto_join as "auto_join: bool", archived as "archived: bool"
                 FROM topics
                 WHERE id = $1
                 "#,
@@ -99,13 +131,13 @@ impl TopicOperations for Db {
 		.fetch_one(&self.0)
 		.await?;
 		let topic = Topic {
+			members: list_member_ids(self, &record.topic_id).await?,
 			id: record.id,
 			topic_id: record.topic_id,
 			name: record.name,
 			owner: record.owner,
-			members: record
-				.members
-				.map(|members| members.split(',').map(String::from).collect()),
+			auto_join: record.auto_join,
+			archived: record.archived,
 		};
 		Ok(topic)
 	}
@@ -113,7 +145,7 @@ impl TopicOperations for Db {
 	async fn get_topic_by_topic_id(&self, topic_id: String) -> Result<Topic> {
 		let record = sqlx::query!(
 			r#"
-                SELECT id, topic_id, owner, members, name
+                SELECT id, topic_id, owner, name, auto_join as "auto_join: bool", archived as "archived: bool"
                 FROM topics
                 WHERE topic_id = $1
                 "#,
@@ -123,13 +155,13 @@ impl TopicOperations for Db {
 		.await?;
 
 		let topic = Topic {
+			members: list_member_ids(self, &record.topic_id).await?,
 			id: record.id,
 			topic_id: record.topic_id,
 			name: record.name,
 			owner: record.owner,
-			members: record
-				.members
-				.map(|members| members.split(',').map(String::from).collect()),
+			auto_join: record.auto_join,
+			archived: record.archived,
 		};
 		Ok(topic)
 	}
@@ -137,51 +169,191 @@ impl TopicOperations for Db {
 	async fn list_topics(&self) -> Result<Vec<Topic>> {
 		let records = sqlx::query!(
 			r#"
-                SELECT id, topic_id, owner, members, name
+                SELECT id, topic_id, owner, name, auto_join as "auto_join: bool", archived as "archived: bool"
                 FROM topics
                 "#
 		)
 		.fetch_all(&self.0)
 		.await?;
-		let topics = records
-			.into_iter()
-			.map(|record| Topic {
+		let mut topics = Vec::with_capacity(records.len());
+		for record in records {
+			topics.push(Topic {
+				members: list_member_ids(self, &record.topic_id).await?,
 				id: record.id,
 				topic_id: record.topic_id,
 				name: record.name,
 				owner: record.owner,
-				members: record
-					.members
-					.map(|members| members.split(',').map(String::from).collect()),
-			})
-			.collect();
+				auto_join: record.auto_join,
+				archived: record.archived,
+			});
+		}
 		Ok(topics)
 	}
 
-	async fn update_topic(&self, id: i64, members: Vec<String>) -> Result<Topic> {
-		let members = members.join(",");
+	async fn set_topic_owner(&self, id: i64, owner: String) -> Result<Topic> {
+		let joined_at = chrono::Utc::now().timestamp();
+		let mut tx = self.0.begin().await?;
+		let topic_id = sqlx::query_scalar!(
+			r#"
+                UPDATE topics
+                SET owner = $1
+                WHERE id = $2
+                RETURNING topic_id
+                "#,
+			owner,
+			id
+		)
+		.fetch_one(&mut *tx)
+		.await?;
+		sqlx::query!(
+			r#"
+                UPDATE topic_members
+                SET role = 'admin'
+                WHERE topic_id = $1 AND role = 'owner'
+                "#,
+			topic_id
+		)
+		.execute(&mut *tx)
+		.await?;
+		sqlx::query!(
+			r#"
+                INSERT INTO topic_members (topic_id, node_id, joined_at, role)
+                VALUES ($1, $2, $3, 'owner')
+                ON CONFLICT (topic_id, node_id) DO UPDATE SET role = 'owner'
+                "#,
+			topic_id,
+			owner,
+			joined_at
+		)
+		.execute(&mut *tx)
+		.await?;
+		tx.commit().await?;
+
+		self.get_topic_by_id(id).await
+	}
+
+	async fn set_topic_auto_join(&self, id: i64, auto_join: bool) -> Result<Topic> {
 		let record = sqlx::query!(
 			r#"
                 UPDATE topics
-                SET members = $1
+                SET auto_join = $1
                 WHERE id = $2
-                RETURNING id, topic_id, owner, members, name
+                RETURNING id, topic_id, owner, name, auto_join as "auto_join: bool", archived as "archived: bool"
                 "#,
-			members,
+			auto_join,
 			id
 		)
 		.fetch_one(&self.0)
 		.await?;
 
 		let topic = Topic {
+			members: list_member_ids(self, &record.topic_id).await?,
 			id: record.id,
 			topic_id: record.topic_id,
 			owner: record.owner,
-			members: record
-				.members
-				.map(|members| members.split(',').map(String::from).collect()),
+			auto_join: record.auto_join,
+			archived: record.archived,
 			name: record.name,
 		};
 		Ok(topic)
 	}
+
+	async fn list_auto_join_topics(&self) -> Result<Vec<Topic>> {
+		let records = sqlx::query!(
+			r#"
+                SELECT id, topic_id, owner, name, auto_join as "auto_join: bool", archived as "archived: bool"
+                FROM topics
+                WHERE auto_join = 1 AND archived = 0
+                "#
+		)
+		.fetch_all(&self.0)
+		.await?;
+		let mut topics = Vec::with_capacity(records.len());
+		for record in records {
+			topics.push(Topic {
+				members: list_member_ids(self, &record.topic_id).await?,
+				id: record.id,
+				topic_id: record.topic_id,
+				name: record.name,
+				owner: record.owner,
+				auto_join: record.auto_join,
+				archived: record.archived,
+			});
+		}
+		Ok(topics)
+	}
+
+	async fn set_topic_archived(&self, id: i64, archived: bool) -> Result<Topic> {
+		sqlx::query!(
+			r#"
+                UPDATE topics
+                SET archived = $1
+                WHERE id = $2
+                "#,
+			archived,
+			id
+		)
+		.execute(&self.0)
+		.await?;
+
+		self.get_topic_by_id(id).await
+	}
+
+	async fn delete_topic(&self, id: i64) -> Result<Vec<String>> {
+		let mut tx = self.0.begin().await?;
+		let topic_id = sqlx::query_scalar!(
+			r#"
+                DELETE FROM topics
+                WHERE id = $1
+                RETURNING topic_id
+                "#,
+			id
+		)
+		.fetch_one(&mut *tx)
+		.await?;
+		let unreferenced = sqlx::query_scalar!(
+			r#"
+                SELECT DISTINCT hash
+                FROM files
+                WHERE topic_id = $1 AND hash NOT IN (SELECT hash FROM files WHERE topic_id != $1)
+                "#,
+			topic_id
+		)
+		.fetch_all(&mut *tx)
+		.await?;
+		for statement in [
+			"DELETE FROM downloads WHERE file_id IN (SELECT id FROM files WHERE topic_id = $1)",
+			"DELETE FROM file_entries WHERE file_id IN (SELECT id FROM files WHERE topic_id = $1)",
+			"DELETE FROM files WHERE topic_id = $1",
+			"DELETE FROM messages WHERE topic_id = $1",
+			"DELETE FROM blob_holders WHERE topic_id = $1",
+			"DELETE FROM topic_members WHERE topic_id = $1",
+			"DELETE FROM topic_bans WHERE topic_id = $1",
+			"DELETE FROM join_requests WHERE topic_id = $1",
+			"DELETE FROM spent_tickets WHERE topic_id = $1",
+			"DELETE FROM ticket_redemptions WHERE topic_id = $1 OR nonce IN (SELECT nonce FROM issued_tickets WHERE topic_id = $1)",
+			"DELETE FROM issued_tickets WHERE topic_id = $1",
+		] {
+			sqlx::query(statement).bind(&topic_id).execute(&mut *tx).await?;
+		}
+		tx.commit().await?;
+
+		Ok(unreferenced)
+	}
+}
+
+// Members other than the owner, who is read from topics.owner
+async fn list_member_ids(db: &Db, topic_id: &str) -> Result<Vec<String>> {
+	let members = sqlx::query_scalar!(
+		r#"
+            SELECT node_id
+            FROM topic_members
+            WHERE topic_id = $1 AND role != 'owner'
+            ORDER BY joined_at, rowid
+            "#,
+		topic_id
+	)
+	.fetch_all(&db.0)
+	.await?;
+	Ok(members)
 }
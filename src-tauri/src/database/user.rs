@@ -27,8 +27,12 @@ impl User {
 
 pub trait UserOperations {
 	async fn create_user(&self, user: User) -> Result<User>;
+	/// Stores the profile of a node that has none yet, the one it has is kept.
+	/// A profile with an email another node already uses is skipped as well.
+	async fn create_user_if_missing(&self, user: User) -> Result<()>;
 	async fn get_user_by_id(&self, id: i64) -> Result<User>;
 	async fn get_user_by_node_id(&self, node_id: i64) -> Result<User>;
+	async fn update_user(&self, user: User) -> Result<User>;
 }
 
 impl UserOperations for Db {
@@ -50,6 +54,23 @@ impl UserOperations for Db {
 		Ok(user)
 	}
 
+	async fn create_user_if_missing(&self, user: User) -> Result<()> {
+		sqlx::query!(
+			r#"
+            INSERT INTO users (node_id, email, first_name, last_name)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT DO NOTHING
+            "#,
+			user.node_id,
+			user.email,
+			user.first_name,
+			user.last_name
+		)
+		.execute(&self.0)
+		.await?;
+		Ok(())
+	}
+
 	async fn get_user_by_id(&self, id: i64) -> Result<User> {
 		let user = sqlx::query_as!(
 			User,
@@ -79,4 +100,23 @@ impl UserOperations for Db {
 		.await?;
 		Ok(user)
 	}
+
+	async fn update_user(&self, user: User) -> Result<User> {
+		let user = sqlx::query_as!(
+			User,
+			r#"
+            UPDATE users
+            SET email = $1, first_name = $2, last_name = $3
+            WHERE id = $4
+            RETURNING id, node_id, email, first_name, last_name
+            "#,
+			user.email,
+			user.first_name,
+			user.last_name,
+			user.id
+		)
+		.fetch_one(&self.0)
+		.await?;
+		Ok(user)
+	}
 }
@@ -24,9 +24,28 @@ pub enum Error {
 	#[error("Gossip Subscription error: {0}")]
 	GossipSubscription(String),
 
+	#[error("Signature error: {0}")]
+	Signature(String),
+
+	#[error("Passphrase error: {0}")]
+	Passphrase(String),
+
+	#[error("Keystore error: {0}")]
+	Keystore(String),
+
+	#[error("Ticket error: {0}")]
+	Ticket(String),
+
+	#[error("Forbidden: {0}")]
+	Forbidden(String),
+
 	#[error(transparent)]
 	Serde(#[from] serde_json::Error),
 
+	#[error(transparent)]
+	Task(#[from] tokio::task::JoinError),
+
+	#[cfg(feature = "gui")]
 	#[error(transparent)]
 	Tauri(#[from] tauri::Error),
 }
@@ -41,7 +60,14 @@ impl Error {
 			Error::EncodeDecode(_) => "encode_decode",
 			Error::Endpoint(_) => "endpoint",
 			Error::GossipSubscription(_) => "gossip_subscription",
+			Error::Signature(_) => "signature",
+			Error::Passphrase(_) => "passphrase",
+			Error::Keystore(_) => "keystore",
+			Error::Ticket(_) => "ticket",
+			Error::Forbidden(_) => "forbidden",
 			Error::Serde(_) => "serde",
+			Error::Task(_) => "task",
+			#[cfg(feature = "gui")]
 			Error::Tauri(_) => "tauri",
 		}
 	}
@@ -55,7 +81,14 @@ impl Error {
 			Error::EncodeDecode(msg) => msg.clone(),
 			Error::Endpoint(msg) => msg.clone(),
 			Error::GossipSubscription(msg) => msg.clone(),
+			Error::Signature(msg) => msg.clone(),
+			Error::Passphrase(msg) => msg.clone(),
+			Error::Keystore(msg) => msg.clone(),
+			Error::Ticket(msg) => msg.clone(),
+			Error::Forbidden(msg) => msg.clone(),
 			Error::Serde(err) => err.to_string(),
+			Error::Task(err) => err.to_string(),
+			#[cfg(feature = "gui")]
 			Error::Tauri(err) => err.to_string(),
 		}
 	}
@@ -0,0 +1,74 @@
+// Passphrase based encryption shared by the key file and identity bundles: a key derived with Argon2id
+// from the passphrase and a per-file salt, and XChaCha20-Poly1305 with a random nonce per sealed value
+use argon2::Argon2;
+use chacha20poly1305::{
+	aead::{Aead, KeyInit, Payload},
+	XChaCha20Poly1305, XNonce,
+};
+use data_encoding::BASE64;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Sealed {
+	nonce: String,
+	ciphertext: String,
+}
+
+pub(crate) struct Cipher(XChaCha20Poly1305);
+
+impl Cipher {
+	pub(crate) fn new_salt() -> String {
+		let mut salt = [0u8; SALT_LEN];
+		rand::rngs::OsRng.fill_bytes(&mut salt);
+		BASE64.encode(&salt)
+	}
+
+	pub(crate) fn derive(passphrase: &str, salt: &str) -> Result<Self> {
+		let mut key = [0u8; 32];
+		Argon2::default()
+			.hash_password_into(passphrase.as_bytes(), &decode(salt)?, &mut key)
+			.map_err(|e| Error::Keystore(format!("Failed to derive the encryption key: {}", e)))?;
+		Ok(Self(XChaCha20Poly1305::new(&key.into())))
+	}
+
+	/// Encrypts `msg`, `aad` is authenticated with it and has to be passed to [`Cipher::open`] again.
+	pub(crate) fn seal(&self, msg: &[u8], aad: &[u8]) -> Result<Sealed> {
+		let mut nonce = [0u8; NONCE_LEN];
+		rand::rngs::OsRng.fill_bytes(&mut nonce);
+		let ciphertext = self
+			.0
+			.encrypt(XNonce::from_slice(&nonce), Payload { msg, aad })
+			.map_err(|_| Error::Keystore("Failed to encrypt".to_string()))?;
+		Ok(Sealed {
+			nonce: BASE64.encode(&nonce),
+			ciphertext: BASE64.encode(&ciphertext),
+		})
+	}
+
+	/// Fails with [`Error::Passphrase`] when the key was derived from another passphrase.
+	pub(crate) fn open(&self, sealed: &Sealed, aad: &[u8]) -> Result<Vec<u8>> {
+		let nonce = decode(&sealed.nonce)?;
+		if nonce.len() != NONCE_LEN {
+			return Err(Error::Keystore("Invalid nonce".to_string()));
+		}
+		let payload = Payload {
+			msg: &decode(&sealed.ciphertext)?,
+			aad,
+		};
+		self.0
+			.decrypt(XNonce::from_slice(&nonce), payload)
+			.map_err(|_| Error::Passphrase("Wrong passphrase".to_string()))
+	}
+}
+
+fn decode(value: &str) -> Result<Vec<u8>> {
+	BASE64
+		.decode(value.as_bytes())
+		.map_err(|_| Error::EncodeDecode("Error Decoding Sealed Value".to_string()))
+}
@@ -0,0 +1,307 @@
+// Keys sealed under the database passphrase, see `crypto` for the scheme
+use std::{
+	collections::BTreeMap,
+	io::Write,
+	path::{Path, PathBuf},
+	sync::RwLock,
+};
+
+use iroh::SecretKey;
+use serde::{Deserialize, Serialize};
+
+use super::{
+	crypto::{Cipher, Sealed},
+	KeyStore,
+};
+use crate::error::{Error, Result};
+
+const VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct KeyFile {
+	version: u32,
+	salt: String,
+	keys: BTreeMap<String, Sealed>,
+}
+
+impl KeyFile {
+	// A new file gets a fresh salt, so rewriting it under a new passphrase shares nothing with the old one
+	fn new() -> Self {
+		Self {
+			version: VERSION,
+			salt: Cipher::new_salt(),
+			keys: BTreeMap::new(),
+		}
+	}
+
+	fn cipher(&self, passphrase: &str) -> Result<Cipher> {
+		Cipher::derive(passphrase, &self.salt)
+	}
+}
+
+/// Keystore in a single JSON file, every key is encrypted with the database passphrase.
+pub struct FileKeyStore {
+	path: PathBuf,
+	passphrase: RwLock<String>,
+}
+
+impl FileKeyStore {
+	pub const FILE_NAME: &'static str = "keystore.json";
+
+	pub fn new(path: PathBuf, passphrase: &str) -> Self {
+		Self {
+			path,
+			passphrase: RwLock::new(passphrase.to_string()),
+		}
+	}
+
+	fn read(&self) -> Result<Option<KeyFile>> {
+		read_from(&self.path)
+	}
+
+	// Written next to the old file and renamed over it, so a crash never leaves a half written keystore
+	fn write(&self, file: &KeyFile) -> Result<()> {
+		write_to(&self.path, file)
+	}
+
+	// Where a passphrase change puts the rekeyed file until it is committed
+	fn staged_path(&self) -> PathBuf {
+		self.path.with_extension("json.next")
+	}
+
+	// A passphrase change that was interrupted after the database was re-encrypted leaves the keys for the new
+	// passphrase staged. When the current file does not open with the passphrase, the staged one is tried
+	fn recover_staged(&self) -> Result<bool> {
+		let Some(staged) = read_from(&self.staged_path())? else {
+			return Ok(false);
+		};
+		let cipher = staged.cipher(&self.passphrase())?;
+		if let Some((id, sealed)) = staged.keys.iter().next() {
+			if open(&cipher, id, sealed).is_err() {
+				return Ok(false);
+			}
+		}
+		std::fs::rename(self.staged_path(), &self.path)?;
+		Ok(true)
+	}
+
+	fn passphrase(&self) -> String {
+		self.passphrase.read().unwrap_or_else(|e| e.into_inner()).clone()
+	}
+}
+
+fn read_from(path: &Path) -> Result<Option<KeyFile>> {
+	let data = match std::fs::read(path) {
+		Ok(data) => data,
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+		Err(e) => return Err(e.into()),
+	};
+	let file: KeyFile = serde_json::from_slice(&data)?;
+	if file.version != VERSION {
+		return Err(Error::Keystore(format!(
+			"Unsupported keystore version {}",
+			file.version
+		)));
+	}
+	Ok(Some(file))
+}
+
+fn write_to(path: &Path, file: &KeyFile) -> Result<()> {
+	let mut tmp = path.as_os_str().to_owned();
+	tmp.push(".tmp");
+	let mut options = std::fs::OpenOptions::new();
+	options.write(true).create(true).truncate(true);
+	#[cfg(unix)]
+	std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+	let mut out = options.open(&tmp)?;
+	out.write_all(&serde_json::to_vec_pretty(file)?)?;
+	out.sync_all()?;
+	std::fs::rename(&tmp, path)?;
+	Ok(())
+}
+
+impl KeyStore for FileKeyStore {
+	fn load(&self, node_id: &str) -> Result<Option<SecretKey>> {
+		let Some(file) = self.read()? else {
+			return Ok(None);
+		};
+		let Some(sealed) = file.keys.get(node_id) else {
+			return Ok(None);
+		};
+		let cipher = file.cipher(&self.passphrase())?;
+		match open(&cipher, node_id, sealed) {
+			Err(Error::Passphrase(_)) if self.recover_staged()? => self.load(node_id),
+			result => result.map(Some),
+		}
+	}
+
+	fn store(&self, node_id: &str, key: &SecretKey) -> Result<()> {
+		let mut file = self.read()?.unwrap_or_else(KeyFile::new);
+		let cipher = file.cipher(&self.passphrase())?;
+		// Keys already in the file have to open with the current passphrase, or the file would mix two of them
+		if let Some((id, sealed)) = file.keys.iter().next() {
+			open(&cipher, id, sealed)?;
+		}
+		file.keys.insert(node_id.to_string(), seal(&cipher, node_id, key)?);
+		self.write(&file)
+	}
+
+	fn remove(&self, node_id: &str) -> Result<()> {
+		if let Some(mut file) = self.read()? {
+			if file.keys.remove(node_id).is_some() {
+				self.write(&file)?;
+			}
+		}
+		Ok(())
+	}
+
+	fn stage_passphrase(&self, new_passphrase: &str) -> Result<()> {
+		let passphrase = self.passphrase.read().unwrap_or_else(|e| e.into_inner());
+		let Some(file) = self.read()? else {
+			return self.discard_passphrase();
+		};
+		let cipher = file.cipher(&passphrase)?;
+		let mut rekeyed = KeyFile::new();
+		let new_cipher = rekeyed.cipher(new_passphrase)?;
+		for (node_id, sealed) in &file.keys {
+			let key = open(&cipher, node_id, sealed)?;
+			rekeyed.keys.insert(node_id.clone(), seal(&new_cipher, node_id, &key)?);
+		}
+		write_to(&self.staged_path(), &rekeyed)
+	}
+
+	fn commit_passphrase(&self, new_passphrase: &str) -> Result<()> {
+		let mut passphrase = self.passphrase.write().unwrap_or_else(|e| e.into_inner());
+		// Nothing is staged when there were no keys to rekey
+		if self.staged_path().exists() {
+			std::fs::rename(self.staged_path(), &self.path)?;
+		}
+		*passphrase = new_passphrase.to_string();
+		Ok(())
+	}
+
+	fn discard_passphrase(&self) -> Result<()> {
+		match std::fs::remove_file(self.staged_path()) {
+			Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+			_ => Ok(()),
+		}
+	}
+}
+
+// The node id is authenticated with the key, so entries can not be swapped between nodes
+fn seal(cipher: &Cipher, node_id: &str, key: &SecretKey) -> Result<Sealed> {
+	cipher.seal(&key.to_bytes(), node_id.as_bytes())
+}
+
+fn open(cipher: &Cipher, node_id: &str, sealed: &Sealed) -> Result<SecretKey> {
+	let bytes: [u8; 32] = cipher
+		.open(sealed, node_id.as_bytes())?
+		.try_into()
+		.map_err(|_| Error::Keystore("Invalid key in the keystore".to_string()))?;
+	Ok(SecretKey::from_bytes(&bytes))
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::Path;
+
+	use data_encoding::BASE64;
+
+	use super::*;
+
+	fn store_in(dir: &Path, passphrase: &str) -> FileKeyStore {
+		FileKeyStore::new(dir.join(FileKeyStore::FILE_NAME), passphrase)
+	}
+
+	#[test]
+	fn stored_key_round_trips() {
+		let dir = tempfile::tempdir().unwrap();
+		let store = store_in(dir.path(), "passphrase");
+		let key = SecretKey::generate(rand::rngs::OsRng);
+		let node_id = key.public().to_string();
+
+		assert!(store.load(&node_id).unwrap().is_none());
+		store.store(&node_id, &key).unwrap();
+
+		let loaded = store_in(dir.path(), "passphrase").load(&node_id).unwrap().unwrap();
+		assert_eq!(loaded.to_bytes(), key.to_bytes());
+
+		// Nothing of the key is readable in the file
+		let written = std::fs::read_to_string(dir.path().join(FileKeyStore::FILE_NAME)).unwrap();
+		assert!(!written.contains(&BASE64.encode(&key.to_bytes())));
+		assert!(!written.contains(&data_encoding::BASE32_NOPAD.encode(&key.to_bytes())));
+	}
+
+	#[test]
+	fn wrong_passphrase_is_rejected() {
+		let dir = tempfile::tempdir().unwrap();
+		let key = SecretKey::generate(rand::rngs::OsRng);
+		let node_id = key.public().to_string();
+		store_in(dir.path(), "correct horse").store(&node_id, &key).unwrap();
+
+		let store = store_in(dir.path(), "battery staple");
+		assert!(matches!(store.load(&node_id), Err(Error::Passphrase(_))));
+		let other = SecretKey::generate(rand::rngs::OsRng);
+		assert!(matches!(
+			store.store(&other.public().to_string(), &other),
+			Err(Error::Passphrase(_))
+		));
+	}
+
+	#[test]
+	fn passphrase_change_rekeys_the_file() {
+		let dir = tempfile::tempdir().unwrap();
+		let store = store_in(dir.path(), "old");
+		let key = SecretKey::generate(rand::rngs::OsRng);
+		let node_id = key.public().to_string();
+		store.store(&node_id, &key).unwrap();
+
+		store.stage_passphrase("new").unwrap();
+		assert_eq!(store.load(&node_id).unwrap().unwrap().to_bytes(), key.to_bytes());
+		assert!(store_in(dir.path(), "old").load(&node_id).unwrap().is_some());
+		store.commit_passphrase("new").unwrap();
+		assert_eq!(store.load(&node_id).unwrap().unwrap().to_bytes(), key.to_bytes());
+		assert!(matches!(
+			store_in(dir.path(), "old").load(&node_id),
+			Err(Error::Passphrase(_))
+		));
+		assert!(store_in(dir.path(), "new").load(&node_id).unwrap().is_some());
+	}
+
+	#[test]
+	fn discarded_passphrase_change_keeps_the_old_passphrase() {
+		let dir = tempfile::tempdir().unwrap();
+		let store = store_in(dir.path(), "old");
+		let key = SecretKey::generate(rand::rngs::OsRng);
+		let node_id = key.public().to_string();
+		store.store(&node_id, &key).unwrap();
+
+		store.stage_passphrase("new").unwrap();
+		store.discard_passphrase().unwrap();
+		assert!(store_in(dir.path(), "old").load(&node_id).unwrap().is_some());
+		assert!(matches!(
+			store_in(dir.path(), "new").load(&node_id),
+			Err(Error::Passphrase(_))
+		));
+	}
+
+	#[test]
+	fn interrupted_passphrase_change_is_recovered() {
+		let dir = tempfile::tempdir().unwrap();
+		let key = SecretKey::generate(rand::rngs::OsRng);
+		let node_id = key.public().to_string();
+		let store = store_in(dir.path(), "old");
+		store.store(&node_id, &key).unwrap();
+
+		// Stopped after the database took the new passphrase and before the keys were committed
+		store.stage_passphrase("new").unwrap();
+		let loaded = store_in(dir.path(), "new").load(&node_id).unwrap().unwrap();
+		assert_eq!(loaded.to_bytes(), key.to_bytes());
+		assert!(!dir.path().join("keystore.json.next").exists());
+		assert!(matches!(
+			store_in(dir.path(), "old").load(&node_id),
+			Err(Error::Passphrase(_))
+		));
+	}
+}
@@ -0,0 +1,42 @@
+// Secret keys of the nodes run from this data directory. They are handed to the endpoint and nothing else,
+// none of the types here implement `Serialize`, so a key can not end up in a command response or an event.
+use std::{path::Path, sync::Arc};
+
+use iroh::SecretKey;
+
+use crate::error::Result;
+
+pub(crate) mod crypto;
+mod file;
+#[cfg(all(feature = "secret-service", target_os = "linux"))]
+mod secret_service;
+
+pub use file::FileKeyStore;
+#[cfg(all(feature = "secret-service", target_os = "linux"))]
+pub use secret_service::SecretServiceKeyStore;
+
+/// Storage for node secret keys. Implementations block, so async code calls them through `spawn_blocking`.
+pub trait KeyStore: Send + Sync {
+	fn load(&self, node_id: &str) -> Result<Option<SecretKey>>;
+	fn store(&self, node_id: &str, key: &SecretKey) -> Result<()>;
+	fn remove(&self, node_id: &str) -> Result<()>;
+	/// Writes the stored keys protected by `new_passphrase` next to the current ones, which stay in use.
+	/// A passphrase change stages the keys first, so it can still be called off when re-encrypting the database fails.
+	fn stage_passphrase(&self, new_passphrase: &str) -> Result<()>;
+	/// Replaces the stored keys with the staged ones, protected by `new_passphrase` from now on.
+	fn commit_passphrase(&self, new_passphrase: &str) -> Result<()>;
+	/// Drops the staged keys, the current passphrase stays.
+	fn discard_passphrase(&self) -> Result<()>;
+}
+
+/// Opens the keystore of `data_dir`. Builds with the `secret-service` feature use the desktop keyring when one
+/// is running, everything else uses the encrypted `keystore.json` protected by `passphrase`.
+pub fn open(data_dir: &Path, passphrase: &str) -> Arc<dyn KeyStore> {
+	let file = FileKeyStore::new(data_dir.join(FileKeyStore::FILE_NAME), passphrase);
+
+	#[cfg(all(feature = "secret-service", target_os = "linux"))]
+	if SecretServiceKeyStore::is_available() {
+		return Arc::new(SecretServiceKeyStore::new(file));
+	}
+	Arc::new(file)
+}
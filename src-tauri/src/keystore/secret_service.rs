@@ -0,0 +1,81 @@
+// Keys in the desktop keyring (GNOME Keyring, KWallet) over the Secret Service D-Bus API
+use iroh::SecretKey;
+use keyring::Entry;
+
+use super::{FileKeyStore, KeyStore};
+use crate::error::{Error, Result};
+
+const SERVICE: &str = "com.crewcast.node";
+
+/// Keystore backed by the Secret Service. Keys found in the encrypted file are moved over the first time they
+/// are loaded, the file stays around for the keys of other data directories.
+pub struct SecretServiceKeyStore {
+	file: FileKeyStore,
+}
+
+impl SecretServiceKeyStore {
+	pub fn new(file: FileKeyStore) -> Self {
+		Self { file }
+	}
+
+	/// Whether a Secret Service answers on the session bus.
+	pub fn is_available() -> bool {
+		let probe = Entry::new(SERVICE, "availability-probe").and_then(|entry| entry.get_secret());
+		matches!(probe, Ok(_) | Err(keyring::Error::NoEntry))
+	}
+}
+
+impl KeyStore for SecretServiceKeyStore {
+	fn load(&self, node_id: &str) -> Result<Option<SecretKey>> {
+		match entry(node_id)?.get_secret() {
+			Ok(bytes) => {
+				let bytes: [u8; 32] = bytes
+					.try_into()
+					.map_err(|_| Error::Keystore("Invalid key in the keyring".to_string()))?;
+				Ok(Some(SecretKey::from_bytes(&bytes)))
+			},
+			Err(keyring::Error::NoEntry) => {
+				let Some(key) = self.file.load(node_id)? else {
+					return Ok(None);
+				};
+				self.store(node_id, &key)?;
+				self.file.remove(node_id)?;
+				Ok(Some(key))
+			},
+			Err(e) => Err(keyring_error(e)),
+		}
+	}
+
+	fn store(&self, node_id: &str, key: &SecretKey) -> Result<()> {
+		entry(node_id)?.set_secret(&key.to_bytes()).map_err(keyring_error)
+	}
+
+	fn remove(&self, node_id: &str) -> Result<()> {
+		match entry(node_id)?.delete_credential() {
+			Ok(()) | Err(keyring::Error::NoEntry) => (),
+			Err(e) => return Err(keyring_error(e)),
+		}
+		self.file.remove(node_id)
+	}
+
+	// The keyring is unlocked with the desktop session, only the file needs the new passphrase
+	fn stage_passphrase(&self, new_passphrase: &str) -> Result<()> {
+		self.file.stage_passphrase(new_passphrase)
+	}
+
+	fn commit_passphrase(&self, new_passphrase: &str) -> Result<()> {
+		self.file.commit_passphrase(new_passphrase)
+	}
+
+	fn discard_passphrase(&self) -> Result<()> {
+		self.file.discard_passphrase()
+	}
+}
+
+fn entry(node_id: &str) -> Result<Entry> {
+	Entry::new(SERVICE, node_id).map_err(keyring_error)
+}
+
+fn keyring_error(error: keyring::Error) -> Error {
+	Error::Keystore(error.to_string())
+}
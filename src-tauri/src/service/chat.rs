@@ -0,0 +1,40 @@
+use crate::{
+	comm::model::{self, Chat, MessageType, SignedMessage},
+	database::{
+		member::MemberOperations,
+		message::{Message, MessageOperations},
+	},
+	error::{Error, Result},
+	AppState,
+};
+
+impl AppState {
+	pub async fn send_message(&self, topic_id: String, message: String) -> Result<()> {
+		let topic_sender = self.comm.topic_sender(&topic_id)?;
+		if !self.db.get_role(&topic_id, &self.node_id()).await?.can_post() {
+			return Err(Error::Forbidden("Read-only members can not chat".to_string()));
+		}
+		let metadata = model::Metadata::new(self.user_info.clone(), self.node_id(), None);
+
+		self.db
+			.create_message(Message::new(
+				metadata.sender.clone(),
+				topic_id.clone(),
+				message.clone(),
+				metadata.ts,
+			))
+			.await?;
+
+		let message = MessageType::Chat(model::Message::new(Chat::new(message), metadata));
+		let message = SignedMessage::sign_and_encode(self.comm.endpoint.secret_key(), &topic_id, &message)?;
+		topic_sender
+			.broadcast(message.into())
+			.await
+			.map_err(|e| Error::GossipSubscription(format!("Failed to send message: {}", e)))?;
+		Ok(())
+	}
+
+	pub async fn list_messages(&self, topic_id: String, before: Option<i64>, limit: i64) -> Result<Vec<Message>> {
+		self.db.list_messages(topic_id, before, limit).await
+	}
+}
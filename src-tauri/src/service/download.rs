@@ -0,0 +1,217 @@
+use std::{
+	fs,
+	path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+
+use crate::{
+	comm::{
+		downloads::{DownloadContext, DownloadManager},
+		events::DownloadProgress,
+	},
+	database::{
+		download::{Download, DownloadOperations},
+		file::{File, FileOperations, FileStatus},
+		settings::SettingsOperations,
+	},
+	error::Result,
+	AppState,
+};
+
+// Settings key of the directory downloads are saved to when no target is given
+const DOWNLOAD_DIR_KEY: &str = "download_dir";
+
+impl AppState {
+	/// The stored download directory, or the user's Downloads folder when none was chosen.
+	pub async fn get_download_dir(&self) -> Result<PathBuf> {
+		if let Some(dir) = self.db.get_setting(DOWNLOAD_DIR_KEY).await? {
+			return Ok(PathBuf::from(dir));
+		}
+		let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"));
+		Ok(match home {
+			Some(home) => PathBuf::from(home).join("Downloads"),
+			None => self.data_dir.join("downloads"),
+		})
+	}
+
+	pub async fn set_download_dir(&self, dir: String) -> Result<PathBuf> {
+		let dir = PathBuf::from(dir);
+		if !dir.is_absolute() {
+			return Err(anyhow!("The download directory must be an absolute path").into());
+		}
+		fs::create_dir_all(&dir)?;
+		self.db.set_setting(DOWNLOAD_DIR_KEY, &dir.to_string_lossy()).await?;
+		Ok(dir)
+	}
+
+	/// Queues a file for download from the node that shared it, the transfer runs in the background.
+	/// Without `target` it goes to the download directory, a `target` directory keeps the file's name.
+	/// A file already at the path is kept and the download is saved as `name (1).ext` instead.
+	/// A shared directory is downloaded whole, with its layout below the target.
+	pub async fn download_file(&self, file: File, target: Option<PathBuf>) -> Result<Download> {
+		self.check_not_downloading(&file).await?;
+		self.db.select_file_entries(file.id, None).await?;
+		self.queue_download(file, target).await
+	}
+
+	/// Queues the entries of a shared directory with the given paths, they keep their place in its layout.
+	pub async fn download_file_entries(
+		&self,
+		file: File,
+		paths: Vec<String>,
+		target: Option<PathBuf>,
+	) -> Result<Download> {
+		self.list_file_entries(file.id).await?;
+		self.queue_file_entries(file, paths, target).await
+	}
+
+	/// Queues a download of some of the files in a shared directory whose entries were already listed.
+	/// Lets the caller fetch the entries without holding the app state, which the fetch would block meanwhile.
+	pub async fn queue_file_entries(
+		&self,
+		file: File,
+		paths: Vec<String>,
+		target: Option<PathBuf>,
+	) -> Result<Download> {
+		if paths.is_empty() {
+			return Err(anyhow!("Choose the files of {} to download", file.name).into());
+		}
+		self.check_not_downloading(&file).await?;
+		self.db.select_file_entries(file.id, Some(&paths)).await?;
+		self.queue_download(file, target).await
+	}
+
+	// Asking again for a queued or running download would start a second transfer, or change what the running one fetches
+	async fn check_not_downloading(&self, file: &File) -> Result<()> {
+		let status = self.db.get_file_by_id(file.id).await?.status;
+		if matches!(status, FileStatus::Queued | FileStatus::Downloading) {
+			return Err(anyhow!("{} is already being downloaded", file.name).into());
+		}
+		Ok(())
+	}
+
+	async fn queue_download(&self, file: File, target: Option<PathBuf>) -> Result<Download> {
+		let target = match target {
+			Some(target) if !target.is_dir() => target,
+			Some(dir) => dir.join(safe_file_name(&file.name)),
+			None => self.get_download_dir().await?.join(safe_file_name(&file.name)),
+		};
+		let download = self
+			.db
+			.queue_download(file.id, &target.to_string_lossy())
+			.await?
+			.ok_or_else(|| anyhow!("{} is already being downloaded", file.name))?;
+		self.events.emit(
+			DownloadProgress::Queued {
+				file_id: file.id,
+				file_name: file.name,
+			}
+			.into(),
+		);
+		self.downloads.wake();
+		Ok(download)
+	}
+
+	/// The downloads that are queued, running, paused or failed, in the order they were asked for.
+	pub async fn list_downloads(&self) -> Result<Vec<Download>> {
+		self.db.list_downloads().await
+	}
+
+	/// Stops a queued or running download until it is resumed, what was fetched so far is kept.
+	pub async fn pause_download(&self, file_id: i64) -> Result<()> {
+		let download = self.get_download(file_id).await?;
+		if !self.db.set_download_status(file_id, FileStatus::Paused).await? {
+			return Err(anyhow!("{} is not queued or downloading", download.name).into());
+		}
+		self.downloads.stop_transfer(file_id).await;
+		self.events.emit(
+			DownloadProgress::Paused {
+				file_id,
+				file_name: download.name,
+			}
+			.into(),
+		);
+		Ok(())
+	}
+
+	/// Queues a paused or failed download again, it keeps its place in the queue.
+	pub async fn resume_download(&self, file_id: i64) -> Result<Download> {
+		let download = self.get_download(file_id).await?;
+		if !matches!(download.status, FileStatus::Paused | FileStatus::Failed) {
+			return Err(anyhow!("{} is not paused or failed", download.name).into());
+		}
+		let download = self
+			.db
+			.queue_download(file_id, &download.target_path)
+			.await?
+			.ok_or_else(|| anyhow!("{} is already being downloaded", download.name))?;
+		self.events.emit(
+			DownloadProgress::Queued {
+				file_id,
+				file_name: download.name.clone(),
+			}
+			.into(),
+		);
+		self.downloads.wake();
+		Ok(download)
+	}
+
+	/// Takes the file out of the queue, stopping its transfer.
+	pub async fn cancel_download(&self, file_id: i64) -> Result<()> {
+		let download = self.get_download(file_id).await?;
+		self.downloads.stop_transfer(file_id).await;
+		self.db.cancel_download(file_id).await?;
+		self.events.emit(
+			DownloadProgress::Cancelled {
+				file_id,
+				file_name: download.name,
+			}
+			.into(),
+		);
+		Ok(())
+	}
+
+	async fn get_download(&self, file_id: i64) -> Result<Download> {
+		self.db
+			.get_download(file_id)
+			.await?
+			.ok_or_else(|| anyhow!("File {} is not in the download queue", file_id).into())
+	}
+
+	// The manager works with the current database, store and endpoint, it is started again whenever one of them is replaced
+	pub(super) async fn start_downloads(&mut self) -> Result<()> {
+		self.downloads.stop();
+		self.downloads = DownloadManager::start(self.download_context()).await?;
+		Ok(())
+	}
+
+	pub(crate) fn download_context(&self) -> DownloadContext {
+		DownloadContext {
+			db: self.db.clone(),
+			store: self.comm.store.clone(),
+			endpoint: self.comm.endpoint.clone(),
+			events: self.events.clone(),
+		}
+	}
+}
+
+// The name is chosen by the sharing node, only its last component is used so it stays in the directory
+fn safe_file_name(name: &str) -> String {
+	Path::new(name)
+		.file_name()
+		.map(|name| name.to_string_lossy().to_string())
+		.unwrap_or_else(|| "download".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn file_names_stay_in_the_directory() {
+		assert_eq!(safe_file_name("../../.bashrc"), ".bashrc");
+		assert_eq!(safe_file_name("notes.txt"), "notes.txt");
+		assert_eq!(safe_file_name(".."), "download");
+	}
+}
@@ -0,0 +1,267 @@
+use std::{
+	fs,
+	path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+use futures_lite::StreamExt;
+use iroh::{NodeAddr, Watcher};
+use iroh_blobs::{api::tags::TagInfo, format::collection::Collection, ticket::BlobTicket};
+
+use crate::{
+	comm::{
+		downloads::list_entries,
+		model::{self, MessageType, SignedMessage},
+	},
+	database::{
+		file::{File, FileEntry, FileOperations, FileStatus},
+		member::MemberOperations,
+	},
+	error::{Error, Result},
+	AppState,
+};
+
+impl AppState {
+	pub async fn list_files(&self, topic_id: String) -> Result<Vec<File>> {
+		self.db.list_files(topic_id, None, None).await
+	}
+
+	/// Shares a file, or a directory with everything in it as one collection that keeps its layout.
+	pub async fn share_file(&self, topic_id: String, file_path: String) -> Result<()> {
+		if !self.comm.is_joined(&topic_id) {
+			return Err(anyhow!("Join a topic to share a file").into());
+		}
+		if !self.db.get_role(&topic_id, &self.node_id()).await?.can_post() {
+			return Err(Error::Forbidden("Read-only members can not share files".to_string()));
+		}
+		let topic_sender = self.comm.topic_sender(&topic_id)?;
+		let endpoint = self.comm.endpoint.clone();
+		let node_id = endpoint.node_id().to_string();
+
+		let blobs = self.comm.blobs.clone();
+
+		// Add file to blob store
+		let file_path = PathBuf::from(file_path);
+		let file_name = file_path
+			.file_name()
+			.ok_or_else(|| Error::Generic(anyhow!("Invalid file path")))?
+			.to_string_lossy()
+			.to_string();
+
+		let (file_tag, file_size, entries) = if file_path.is_dir() {
+			self.add_directory(&file_path).await?
+		} else {
+			let file_tag = blobs
+				.store()
+				.add_path(file_path.clone())
+				.await
+				.map_err(|e| Error::Generic(anyhow!("Failed to add {}: {}", file_path.display(), e)))?;
+			(file_tag, fs::metadata(&file_path)?.len() as i64, Vec::new())
+		};
+
+		// The content is only known once it is imported. The store keeps one copy of it in total however many
+		// topics it is shared in, so dropping the tag this import added leaves the earlier share as it was
+		if self
+			.db
+			.get_file(&topic_id, &file_tag.hash.to_string(), &node_id)
+			.await?
+			.is_some()
+		{
+			blobs
+				.store()
+				.tags()
+				.delete(&file_tag.name)
+				.await
+				.map_err(|e| Error::Generic(anyhow!("Failed to untag {}: {}", file_name, e)))?;
+			return Err(anyhow!("{} is already shared in this topic", file_name).into());
+		}
+
+		// Before the endpoint knows its addresses, the others find it through discovery
+		let node_addr = endpoint
+			.node_addr()
+			.get()
+			.unwrap_or_else(|| NodeAddr::new(endpoint.node_id()));
+		let ticket = BlobTicket::new(node_addr, file_tag.hash, file_tag.format);
+
+		let ts = chrono::Utc::now().timestamp();
+		let metadata = model::Metadata::new(self.user_info.clone(), node_id.clone(), Some(ts));
+		let message = model::Message::new(
+			model::File::new(file_name.clone(), ticket.to_string(), file_size, ts),
+			metadata,
+		);
+		let message = SignedMessage::sign_and_encode(endpoint.secret_key(), &topic_id, &MessageType::File(message))?;
+
+		let file = self
+			.db
+			.create_file(File::new(
+				node_id,
+				topic_id,
+				ticket.hash().to_string(),
+				file_name,
+				Some(file_path.to_string_lossy().to_string()),
+				file_size,
+				file_tag.format.to_string(),
+				FileStatus::Shared,
+				ts,
+			))
+			.await?;
+		let entries: Vec<FileEntry> = entries
+			.into_iter()
+			.map(|entry| FileEntry {
+				file_id: file.id,
+				..entry
+			})
+			.collect();
+		self.db.create_file_entries(&entries).await?;
+
+		topic_sender
+			.broadcast(message.into())
+			.await
+			.map_err(|e| Error::GossipSubscription(format!("Failed to send message: {}", e)))?;
+
+		Ok(())
+	}
+
+	/// The files in a shared directory, with whether a download of it fetches them.
+	/// Nodes other than the one that shared it fetch the list the first time it is asked for.
+	pub async fn list_file_entries(&self, file_id: i64) -> Result<Vec<FileEntry>> {
+		list_entries(&self.download_context(), file_id).await
+	}
+
+	// Adds the files below the directory to the store as one collection named by their relative paths.
+	// Returns the collection's tag, its total size and its entries without a file id
+	async fn add_directory(&self, dir: &Path) -> Result<(TagInfo, i64, Vec<FileEntry>)> {
+		let mut paths = Vec::new();
+		collect_files(dir, &mut paths)?;
+		if paths.is_empty() {
+			return Err(anyhow!("{} has no files to share", dir.display()).into());
+		}
+
+		let store = self.comm.blobs.store();
+		// The temporary tags keep the files until the collection's tag covers them
+		let mut temp_tags = Vec::with_capacity(paths.len());
+		let mut collection = Collection::default();
+		let mut entries = Vec::with_capacity(paths.len());
+		let mut total_size = 0;
+		for (position, path) in paths.iter().enumerate() {
+			let temp_tag = store
+				.add_path(path)
+				.temp_tag()
+				.await
+				.map_err(|e| Error::Generic(anyhow!("Failed to add {}: {}", path.display(), e)))?;
+			let name = relative_name(dir, path);
+			let size = fs::metadata(path)?.len() as i64;
+			total_size += size;
+			collection.push(name.clone(), *temp_tag.hash());
+			entries.push(FileEntry {
+				absolute_path: Some(path.to_string_lossy().to_string()),
+				..FileEntry::new(0, position as i64, name, temp_tag.hash().to_string(), Some(size))
+			});
+			temp_tags.push(temp_tag);
+		}
+		let collection_tag = collection
+			.store(store)
+			.await
+			.map_err(|e| Error::Generic(anyhow!("Failed to store the collection: {}", e)))?;
+		let name = store
+			.tags()
+			.create(&collection_tag)
+			.await
+			.map_err(|e| Error::Generic(anyhow!("Failed to tag the collection: {}", e)))?;
+		Ok((
+			TagInfo::new(name, *collection_tag.hash_and_format()),
+			total_size,
+			entries,
+		))
+	}
+
+	/// The nodes a download of the file fetches from, the one that shared it and the members that downloaded it.
+	pub async fn list_file_holders(&self, file_id: i64) -> Result<Vec<String>> {
+		let file = self.db.get_file_by_id(file_id).await?;
+		let mut holders = self.db.list_blob_holders(&file.topic_id, &file.hash).await?;
+		holders.retain(|holder| *holder != file.node_id);
+		holders.insert(0, file.node_id);
+		Ok(holders)
+	}
+
+	/// Where a file on this node is, for opening it or showing it in its folder.
+	pub async fn get_file_path(&self, file_id: i64) -> Result<PathBuf> {
+		let file = self.db.get_file_by_id(file_id).await?;
+		match file.absolute_path.map(PathBuf::from) {
+			Some(path) if path.exists() => Ok(path),
+			Some(path) => Err(anyhow!("{} no longer exists", path.display()).into()),
+			None => Err(anyhow!("Download {} first", file.name).into()),
+		}
+	}
+
+	// Drops the store's tags on the hashes, the store only keeps data that is tagged or belongs to a tagged collection.
+	// The untagged data is dropped from disk the next time the store is loaded
+	pub(super) async fn untag_blobs(&self, hashes: &[String]) -> Result<()> {
+		let tags = self.comm.blobs.store().tags();
+		let mut list = tags
+			.list()
+			.await
+			.map_err(|e| Error::Generic(anyhow!("Failed to list blob tags: {}", e)))?;
+		let mut untag = Vec::new();
+		while let Some(tag) = list.next().await {
+			let tag = tag.map_err(|e| Error::Generic(anyhow!("Failed to list blob tags: {}", e)))?;
+			if hashes.contains(&tag.hash.to_string()) {
+				untag.push(tag.name);
+			}
+		}
+		if untag.is_empty() {
+			return Ok(());
+		}
+		for name in untag {
+			tags.delete(name)
+				.await
+				.map_err(|e| Error::Generic(anyhow!("Failed to delete blob tag: {}", e)))?;
+		}
+		self.comm.compact_store_on_load()
+	}
+}
+
+// The files below `dir` in a stable order, symbolic links are not followed
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+	let mut children = fs::read_dir(dir)?
+		.map(|entry| entry.map(|entry| entry.path()))
+		.collect::<std::io::Result<Vec<_>>>()?;
+	children.sort();
+	for child in children {
+		let file_type = fs::symlink_metadata(&child)?.file_type();
+		if file_type.is_dir() {
+			collect_files(&child, files)?;
+		} else if file_type.is_file() {
+			files.push(child);
+		}
+	}
+	Ok(())
+}
+
+// The path below the shared directory with `/` between the components on every platform
+fn relative_name(dir: &Path, path: &Path) -> String {
+	path.strip_prefix(dir)
+		.unwrap_or(path)
+		.components()
+		.map(|component| component.as_os_str().to_string_lossy())
+		.collect::<Vec<_>>()
+		.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn directories_are_walked_in_order() {
+		let dir = tempfile::tempdir().unwrap();
+		fs::create_dir_all(dir.path().join("b/c")).unwrap();
+		for name in ["z.txt", "b/c/x.txt", "b/a.txt"] {
+			fs::write(dir.path().join(name), name).unwrap();
+		}
+		let mut files = Vec::new();
+		collect_files(dir.path(), &mut files).unwrap();
+		let names: Vec<_> = files.iter().map(|file| relative_name(dir.path(), file)).collect();
+		assert_eq!(names, ["b/a.txt", "b/c/x.txt", "z.txt"]);
+	}
+}
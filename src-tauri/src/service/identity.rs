@@ -0,0 +1,201 @@
+use std::{collections::HashSet, path::PathBuf};
+
+use anyhow::anyhow;
+use data_encoding::BASE64;
+use iroh::SecretKey;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	comm::model::UserInfo,
+	database::{
+		file::{File, FileOperations, FileStatus},
+		node::NodeOperations,
+		topic::{Topic, TopicOperations},
+		user::{User, UserOperations},
+	},
+	error::{Error, Result},
+	keystore::crypto::{Cipher, Sealed},
+	AppState,
+};
+
+use super::{init_node, network::load_network_config, store_secret_key};
+
+const VERSION: u32 = 1;
+// Authenticated with the contents, so a bundle can not be passed off as another kind of sealed file
+const BUNDLE_AAD: &[u8] = b"crewcast-identity";
+
+// Written to disk as an `Envelope` only, the plaintext never leaves this module
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IdentityBundle {
+	node_id: String,
+	secret_key: String,
+	user: Option<User>,
+	topics: Vec<Topic>,
+	files: Vec<File>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+	version: u32,
+	salt: String,
+	#[serde(flatten)]
+	sealed: Sealed,
+}
+
+impl IdentityBundle {
+	fn secret_key(&self) -> Result<SecretKey> {
+		let bytes: [u8; 32] = BASE64
+			.decode(self.secret_key.as_bytes())
+			.ok()
+			.and_then(|bytes| bytes.try_into().ok())
+			.ok_or_else(|| Error::EncodeDecode("Error Decoding Secret".to_string()))?;
+		let secret_key = SecretKey::from_bytes(&bytes);
+		if secret_key.public().to_string() != self.node_id {
+			return Err(Error::Keystore(
+				"The bundled key does not belong to its node".to_string(),
+			));
+		}
+		Ok(secret_key)
+	}
+
+	// Key derivation is slow on purpose, so sealing and opening run off the async runtime
+	async fn write(self, path: PathBuf, passphrase: String) -> Result<()> {
+		tokio::task::spawn_blocking(move || {
+			let salt = Cipher::new_salt();
+			let sealed = Cipher::derive(&passphrase, &salt)?.seal(&serde_json::to_vec(&self)?, BUNDLE_AAD)?;
+			let envelope = Envelope {
+				version: VERSION,
+				salt,
+				sealed,
+			};
+			std::fs::write(path, serde_json::to_vec_pretty(&envelope)?)?;
+			Ok(())
+		})
+		.await?
+	}
+
+	async fn read(path: PathBuf, passphrase: String) -> Result<Self> {
+		tokio::task::spawn_blocking(move || {
+			let envelope: Envelope = serde_json::from_slice(&std::fs::read(path)?)?;
+			if envelope.version != VERSION {
+				return Err(Error::Keystore(format!(
+					"Unsupported bundle version {}",
+					envelope.version
+				)));
+			}
+			let plaintext = Cipher::derive(&passphrase, &envelope.salt)?.open(&envelope.sealed, BUNDLE_AAD)?;
+			Ok(serde_json::from_slice(&plaintext)?)
+		})
+		.await?
+	}
+}
+
+impl AppState {
+	/// Writes this node's key, profile, topics and file catalog to `path`, encrypted with `passphrase`.
+	pub async fn export_identity(&self, path: PathBuf, passphrase: String) -> Result<()> {
+		if passphrase.is_empty() {
+			return Err(Error::Passphrase("The passphrase can not be empty".to_string()));
+		}
+		let node = self.db.get_node_by_id(1).await?;
+		let user = self.db.get_user_by_node_id(node.id).await.ok();
+		let topics = self.db.list_topics().await?;
+		let mut files = Vec::new();
+		for topic in &topics {
+			files.extend(self.db.list_files(topic.topic_id.clone(), None, None).await?);
+		}
+
+		let bundle = IdentityBundle {
+			node_id: node.node_id,
+			secret_key: BASE64.encode(&self.comm.endpoint.secret_key().to_bytes()),
+			user,
+			topics,
+			files,
+		};
+		bundle.write(path, passphrase).await
+	}
+
+	/// Restores the identity exported to `path`: this node takes over its key and profile, and its topics and
+	/// file catalog are added to the ones already here. The node comes back up under the restored key.
+	pub async fn import_identity(&mut self, path: PathBuf, passphrase: String) -> Result<()> {
+		let bundle = IdentityBundle::read(path, passphrase).await?;
+		let secret_key = bundle.secret_key()?;
+
+		let node = self.db.get_node_by_id(1).await?;
+		if node.node_id != bundle.node_id {
+			// Peers are stored in the same table, a node that already met the identity can not become it
+			if self.db.get_node_by_node_id(bundle.node_id.clone()).await.is_ok() {
+				return Err(anyhow!(
+					"This identity is already known here as another member, import it into a new data directory"
+				)
+				.into());
+			}
+			store_secret_key(&self.keystore, bundle.node_id.clone(), secret_key).await?;
+			self.db.update_node(node.id, bundle.node_id.clone()).await?;
+			let keystore = self.keystore.clone();
+			tokio::task::spawn_blocking(move || keystore.remove(&node.node_id)).await??;
+		}
+
+		if let Some(imported) = bundle.user {
+			let user = match self.db.get_user_by_node_id(node.id).await {
+				Ok(user) => {
+					self.db
+						.update_user(User {
+							id: user.id,
+							..imported
+						})
+						.await?
+				},
+				Err(_) => {
+					self.db
+						.create_user(User::new(
+							imported.email,
+							imported.first_name,
+							imported.last_name,
+							Some(node.id),
+						))
+						.await?
+				},
+			};
+			self.user_info = UserInfo {
+				id: user.id,
+				email: user.email,
+				first_name: user.first_name,
+				last_name: user.last_name,
+			};
+		}
+
+		for topic in bundle.topics {
+			if self.db.get_topic_by_topic_id(topic.topic_id.clone()).await.is_err() {
+				self.db.create_topic(topic).await?;
+			}
+		}
+		let topic_ids: HashSet<String> = bundle.files.iter().map(|file| file.topic_id.clone()).collect();
+		let mut existing = HashSet::new();
+		for topic_id in topic_ids {
+			for file in self.db.list_files(topic_id, None, None).await? {
+				existing.insert((file.topic_id, file.node_id, file.hash));
+			}
+		}
+		for file in bundle.files {
+			if !existing.insert((file.topic_id.clone(), file.node_id.clone(), file.hash.clone())) {
+				continue;
+			}
+			// Paths belong to the old device and the blobs are not in this store, they can be downloaded again
+			self.db
+				.create_file(File {
+					absolute_path: None,
+					status: FileStatus::Shared,
+					..file
+				})
+				.await?;
+		}
+
+		let builder = load_network_config(&self.db).await?.endpoint_builder()?;
+		let endpoint = init_node(&self.db, &self.keystore, builder).await?;
+		let joined = self.comm.restart(endpoint).await?;
+		self.rejoin_topic_ids(joined).await?;
+		self.rejoin_auto_join_topics().await;
+		Ok(())
+	}
+}
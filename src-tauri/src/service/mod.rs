@@ -0,0 +1,365 @@
+use std::{
+	path::{Path, PathBuf},
+	sync::Arc,
+};
+
+use iroh::{endpoint::Builder, Endpoint, SecretKey};
+
+use crate::{
+	comm::{
+		downloads::{DownloadContext, DownloadManager},
+		endpoint::create_endpoint,
+		events::EventSink,
+		model::UserInfo,
+		state::CommState,
+		SubscriptionContext,
+	},
+	database::{
+		node::{Node, NodeOperations},
+		user::{User, UserOperations},
+		Db,
+	},
+	error::{Error, Result},
+	keystore::{self, KeyStore},
+};
+
+pub mod chat;
+pub mod download;
+pub mod file;
+pub mod identity;
+pub mod moderation;
+pub mod network;
+pub mod ticket;
+pub mod topic;
+
+// The node itself, independent of the frontend driving it. Used by the Tauri commands and the headless CLI
+pub struct AppState {
+	pub db: Db,
+	data_dir: PathBuf,
+	pub(crate) comm: CommState,
+	pub user_info: UserInfo,
+	pub(crate) events: Arc<dyn EventSink>,
+	pub(crate) keystore: Arc<dyn KeyStore>,
+	pub(crate) downloads: DownloadManager,
+}
+
+impl AppState {
+	/// Opens the database and blob store in `data_dir` and brings up the node stored there.
+	/// The endpoint is set up from the stored network configuration.
+	pub async fn init(data_dir: PathBuf, passphrase: String, events: Arc<dyn EventSink>) -> Result<Self> {
+		Self::init_with(data_dir, passphrase, events, None).await
+	}
+
+	/// Like [`AppState::init`], but binds the node's endpoint from `builder` instead of the stored network configuration.
+	/// The stored node key is always used, so the node keeps its identity.
+	pub async fn init_with_endpoint(
+		data_dir: PathBuf,
+		passphrase: String,
+		events: Arc<dyn EventSink>,
+		builder: Builder,
+	) -> Result<Self> {
+		Self::init_with(data_dir, passphrase, events, Some(builder)).await
+	}
+
+	async fn init_with(
+		data_dir: PathBuf,
+		passphrase: String,
+		events: Arc<dyn EventSink>,
+		builder: Option<Builder>,
+	) -> Result<Self> {
+		if passphrase.is_empty() {
+			return Err(Error::Passphrase("The passphrase can not be empty".to_string()));
+		}
+		if !data_dir.exists() {
+			std::fs::create_dir_all(&data_dir)?;
+		}
+		// create the database pool
+		let db = Db::init(&Self::db_path(&data_dir), &passphrase).await?;
+
+		let builder = match builder {
+			Some(builder) => builder,
+			None => network::load_network_config(&db).await?.endpoint_builder()?,
+		};
+		let keystore = keystore::open(&data_dir, &passphrase);
+		let endpoint = init_node(&db, &keystore, builder).await?;
+		let comm = CommState::init_from_endpoint(endpoint, data_dir.clone()).await?;
+
+		let user_info = match db.get_user_by_id(1).await {
+			Ok(user) => UserInfo {
+				id: user.id,
+				email: user.email,
+				first_name: user.first_name,
+				last_name: user.last_name,
+			},
+			Err(_) => UserInfo::default(),
+		};
+
+		let downloads = DownloadManager::start(DownloadContext {
+			db: db.clone(),
+			store: comm.store.clone(),
+			endpoint: comm.endpoint.clone(),
+			events: events.clone(),
+		})
+		.await?;
+
+		Ok(Self {
+			db,
+			data_dir,
+			comm,
+			user_info,
+			events,
+			keystore,
+			downloads,
+		})
+	}
+
+	/// Whether `data_dir` already holds a node, i.e. opening it needs the passphrase it was set up with.
+	pub fn exists(data_dir: &Path) -> bool {
+		Self::db_path(data_dir).exists()
+	}
+
+	/// Whether `data_dir` holds a node from before its data was encrypted, the passphrase it is opened with becomes
+	/// its first one.
+	pub fn is_unencrypted(data_dir: &Path) -> Result<bool> {
+		Db::is_plaintext(&Self::db_path(data_dir))
+	}
+
+	fn db_path(data_dir: &Path) -> PathBuf {
+		data_dir.join("data.db")
+	}
+
+	/// Re-encrypts the database and the keystore with `new_passphrase`, after checking `passphrase` against the current one.
+	pub async fn change_passphrase(&mut self, passphrase: String, new_passphrase: String) -> Result<()> {
+		if new_passphrase.is_empty() {
+			return Err(Error::Passphrase("The passphrase can not be empty".to_string()));
+		}
+		let db_path = Self::db_path(&self.data_dir);
+		Db::check_passphrase(&db_path, &passphrase).await?;
+
+		#[cfg(feature = "sqlcipher")]
+		{
+			// The keys are rekeyed next to the current ones first, a keystore that can not be written stops the
+			// change before the database is touched
+			let keystore = self.keystore.clone();
+			let staged = new_passphrase.clone();
+			tokio::task::spawn_blocking(move || keystore.stage_passphrase(&staged)).await??;
+
+			// Subscriptions hold their own handle on the pool, so they are stopped while the file is swapped
+			let joined = self.comm.leave_all();
+			self.downloads.stop();
+			self.db.close().await?;
+			let result = self.rekey(&db_path, &passphrase, &new_passphrase).await;
+			let discarded = if result.is_err() && Db::check_passphrase(&db_path, &passphrase).await.is_ok() {
+				let keystore = self.keystore.clone();
+				tokio::task::spawn_blocking(move || keystore.discard_passphrase())
+					.await
+					.map_err(Error::from)
+					.and_then(|discarded| discarded)
+			} else {
+				Ok(())
+			};
+
+			// The database is on the new passphrase only when everything went through, but a failed rollback leaves it
+			// there as well. Whatever failed, the node comes back up with its downloads and topics
+			let (current, other) = if result.is_ok() {
+				(&new_passphrase, &passphrase)
+			} else {
+				(&passphrase, &new_passphrase)
+			};
+			let reopened = match Db::init(&db_path, current).await {
+				Ok(db) => Ok(db),
+				Err(e) => Db::init(&db_path, other).await.map_err(|_| e),
+			}
+			.map(|db| self.db = db);
+			let restarted = self.start_downloads().await;
+			let rejoined = self.rejoin_topic_ids(joined).await;
+			result.and(discarded).and(reopened).and(restarted).and(rejoined)
+		}
+		#[cfg(not(feature = "sqlcipher"))]
+		Err(Error::Passphrase(
+			"This build stores the database unencrypted".to_string(),
+		))
+	}
+
+	// Re-encrypts the closed database and then puts the staged keys in place. When that fails, the database is
+	// taken back to the old passphrase, so it always opens with the same passphrase as the keystore
+	#[cfg(feature = "sqlcipher")]
+	async fn rekey(&self, db_path: &Path, passphrase: &str, new_passphrase: &str) -> Result<()> {
+		Db::change_passphrase(db_path, passphrase, new_passphrase).await?;
+		let keystore = self.keystore.clone();
+		let committed = new_passphrase.to_string();
+		if let Err(e) = tokio::task::spawn_blocking(move || keystore.commit_passphrase(&committed)).await? {
+			Db::change_passphrase(db_path, new_passphrase, passphrase).await?;
+			return Err(e);
+		}
+		Ok(())
+	}
+
+	pub fn node_id(&self) -> String {
+		self.comm.endpoint.node_id().to_string()
+	}
+
+	pub fn endpoint(&self) -> &Endpoint {
+		&self.comm.endpoint
+	}
+
+	pub async fn create_user(
+		&mut self,
+		email: String,
+		first_name: String,
+		last_name: Option<String>,
+		node_id: String,
+	) -> Result<User> {
+		let node = self.db.get_node_by_node_id(node_id).await?;
+		let user = self
+			.db
+			.create_user(User::new(email, first_name, last_name, Some(node.id)))
+			.await?;
+
+		if node.id == 1 {
+			self.user_info = UserInfo {
+				id: user.id,
+				email: user.email.clone(),
+				first_name: user.first_name.clone(),
+				last_name: user.last_name.clone(),
+			};
+		}
+
+		Ok(user)
+	}
+
+	pub async fn close(&mut self) -> Result<()> {
+		self.downloads.stop();
+		self.comm.close().await;
+		self.db.close().await
+	}
+
+	fn subscription_context(&self) -> SubscriptionContext {
+		SubscriptionContext {
+			db: self.db.clone(),
+			endpoint: self.comm.endpoint.clone(),
+			user_info: self.user_info.clone(),
+			events: self.events.clone(),
+		}
+	}
+}
+
+// Binds the endpoint with the key of node 1, the local node. A fresh data directory gets a new key,
+// one stored in the `nodes` table by older versions is moved to the keystore first
+async fn init_node(db: &Db, keystore: &Arc<dyn KeyStore>, builder: Builder) -> Result<Endpoint> {
+	let secret_key = match db.get_node_by_id(1).await {
+		Ok(node) => load_secret_key(db, keystore, &node).await?,
+		Err(_) => {
+			let secret_key = SecretKey::generate(rand::rngs::OsRng);
+			let node_id = secret_key.public().to_string();
+			store_secret_key(keystore, node_id.clone(), secret_key.clone()).await?;
+			db.create_node(Node::new(node_id)).await?;
+			secret_key
+		},
+	};
+	create_endpoint(secret_key, builder).await
+}
+
+async fn load_secret_key(db: &Db, keystore: &Arc<dyn KeyStore>, node: &Node) -> Result<SecretKey> {
+	let node_id = node.node_id.clone();
+	let store = keystore.clone();
+	if let Some(secret_key) = tokio::task::spawn_blocking(move || store.load(&node_id)).await?? {
+		return Ok(secret_key);
+	}
+
+	let encoded = db
+		.get_legacy_secret_key(node.id)
+		.await?
+		.ok_or_else(|| Error::Keystore(format!("No key found for node {}", node.node_id)))?;
+	let mut key_bytes = [0u8; 32];
+	data_encoding::BASE32_NOPAD
+		.decode_mut(encoded.as_bytes(), &mut key_bytes)
+		.map_err(|_| Error::EncodeDecode("Error Decoding Secret".to_string()))?;
+	let secret_key = SecretKey::from_bytes(&key_bytes);
+	if secret_key.public().to_string() != node.node_id {
+		return Err(Error::Keystore(format!(
+			"The stored key does not belong to node {}",
+			node.node_id
+		)));
+	}
+
+	// Only cleared once the keystore holds the key, an interrupted move is picked up again on the next start
+	store_secret_key(keystore, node.node_id.clone(), secret_key.clone()).await?;
+	db.clear_legacy_secret_key(node.id).await?;
+	Ok(secret_key)
+}
+
+async fn store_secret_key(keystore: &Arc<dyn KeyStore>, node_id: String, secret_key: SecretKey) -> Result<()> {
+	let keystore = keystore.clone();
+	tokio::task::spawn_blocking(move || keystore.store(&node_id, &secret_key)).await?
+}
+
+#[cfg(all(test, feature = "sqlcipher"))]
+mod tests {
+	use iroh::RelayMode;
+
+	use super::*;
+	use crate::{comm::events::MemorySink, keystore::FileKeyStore};
+
+	// Stages like the real keystore, but can not put the staged keys in place
+	struct UncommittableKeyStore(Arc<dyn KeyStore>);
+
+	impl KeyStore for UncommittableKeyStore {
+		fn load(&self, node_id: &str) -> Result<Option<SecretKey>> {
+			self.0.load(node_id)
+		}
+
+		fn store(&self, node_id: &str, key: &SecretKey) -> Result<()> {
+			self.0.store(node_id, key)
+		}
+
+		fn remove(&self, node_id: &str) -> Result<()> {
+			self.0.remove(node_id)
+		}
+
+		fn stage_passphrase(&self, new_passphrase: &str) -> Result<()> {
+			self.0.stage_passphrase(new_passphrase)
+		}
+
+		fn commit_passphrase(&self, _new_passphrase: &str) -> Result<()> {
+			Err(Error::Keystore("Disk full".to_string()))
+		}
+
+		fn discard_passphrase(&self) -> Result<()> {
+			self.0.discard_passphrase()
+		}
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn failed_keystore_commit_keeps_the_old_passphrase() {
+		let dir = tempfile::tempdir().unwrap();
+		let mut state = AppState::init_with_endpoint(
+			dir.path().to_path_buf(),
+			"old".to_string(),
+			Arc::new(MemorySink::default()),
+			Endpoint::builder().relay_mode(RelayMode::Disabled),
+		)
+		.await
+		.unwrap();
+		state.start_new_topic("crew".to_string()).await.unwrap();
+		let joined = state.list_joined_topics();
+		state.keystore = Arc::new(UncommittableKeyStore(state.keystore.clone()));
+
+		assert!(matches!(
+			state.change_passphrase("old".to_string(), "new".to_string()).await,
+			Err(Error::Keystore(_))
+		));
+
+		// The database went back to the passphrase the keystore still has, and the node kept running
+		assert_eq!(state.list_joined_topics(), joined);
+		assert_eq!(state.list_topics().await.unwrap().len(), 1);
+		let node_id = state.node_id();
+		state.close().await.unwrap();
+		Db::check_passphrase(&AppState::db_path(dir.path()), "old")
+			.await
+			.unwrap();
+		let keystore = FileKeyStore::new(dir.path().join(FileKeyStore::FILE_NAME), "old");
+		assert!(keystore.load(&node_id).unwrap().is_some());
+		assert!(!dir.path().join("keystore.json.next").exists());
+	}
+}
@@ -0,0 +1,135 @@
+use anyhow::anyhow;
+
+use crate::{
+	comm::{
+		endpoint::{apply_moderation, check_moderation, redeem_grant, update_topic},
+		model::{self, MessageType, Moderation, ModerationUpdate, SignedMessage, UserInfo},
+	},
+	database::{
+		member::{MemberOperations, Role, TopicMember},
+		moderation::{JoinRequest, ModerationOperations},
+		ticket::TicketOperations,
+		topic::TopicOperations,
+	},
+	error::{Error, Result},
+	AppState,
+};
+
+impl AppState {
+	/// Removes a member from the topic, it needs a ticket it did not join with before to come back.
+	pub async fn remove_member(&self, topic_id: String, node_id: String) -> Result<()> {
+		let nonces = self.db.list_redeemed_nonces(&topic_id, &node_id).await?;
+		self.moderate(&topic_id, Moderation::RemoveMember { node_id, nonces })
+			.await
+	}
+
+	/// Removes a member from the topic and has every member ignore its gossip until it is unbanned.
+	pub async fn ban_member(&self, topic_id: String, node_id: String) -> Result<()> {
+		self.moderate(&topic_id, Moderation::Ban { node_id }).await
+	}
+
+	/// Lifts a ban, the node still needs a new ticket to join again.
+	pub async fn unban_member(&self, topic_id: String, node_id: String) -> Result<()> {
+		self.moderate(&topic_id, Moderation::Unban { node_id }).await
+	}
+
+	pub async fn list_banned_members(&self, topic_id: String) -> Result<Vec<String>> {
+		self.db.list_banned(&topic_id).await
+	}
+
+	/// With approval required, newcomers with a valid ticket are queued until [`AppState::approve_join`].
+	pub async fn set_require_approval(&self, topic_id: String, enabled: bool) -> Result<()> {
+		self.moderate(&topic_id, Moderation::RequireApproval { enabled }).await
+	}
+
+	pub async fn list_join_requests(&self, topic_id: String) -> Result<Vec<JoinRequest>> {
+		self.db.list_join_requests(&topic_id).await
+	}
+
+	/// Admits a queued newcomer, the other members admit it once they get the approval.
+	pub async fn approve_join(&self, topic_id: String, node_id: String) -> Result<()> {
+		let topic = self.db.get_topic_by_topic_id(topic_id.clone()).await?;
+		let moderation = Moderation::ApproveJoin {
+			node_id: node_id.clone(),
+		};
+		check_moderation(&self.db, &topic, &self.node_id(), &moderation).await?;
+
+		let request = self
+			.db
+			.get_join_request(&topic_id, &node_id)
+			.await?
+			.ok_or_else(|| anyhow!("No pending join request from {}", node_id))?;
+		// The newcomer takes its use of the ticket only now, the ticket may have been used up while it waited
+		if let Some(grant) = self.db.get_join_request_grant(&topic_id, &node_id).await? {
+			redeem_grant(&self.db, &node_id, &grant).await?;
+		}
+		let user_info = UserInfo {
+			id: 0,
+			email: request.email,
+			first_name: request.first_name,
+			last_name: request.last_name,
+		};
+		update_topic(&self.db, topic_id.clone(), node_id, user_info).await?;
+		self.moderate(&topic_id, moderation).await
+	}
+
+	/// Turns a queued newcomer away, it needs a new ticket to ask again.
+	pub async fn deny_join(&self, topic_id: String, node_id: String) -> Result<()> {
+		if self.db.get_join_request(&topic_id, &node_id).await?.is_none() {
+			return Err(anyhow!("No pending join request from {}", node_id).into());
+		}
+		let mut nonces = self.db.list_redeemed_nonces(&topic_id, &node_id).await?;
+		// The ticket it asked with was never redeemed, it is spent for the newcomer all the same
+		if let Some(grant) = self.db.get_join_request_grant(&topic_id, &node_id).await? {
+			nonces.push(grant.nonce);
+		}
+		self.moderate(&topic_id, Moderation::DenyJoin { node_id, nonces }).await
+	}
+
+	/// The owner and every member with their role.
+	pub async fn list_member_roles(&self, topic_id: String) -> Result<Vec<TopicMember>> {
+		self.db.list_members(&topic_id).await
+	}
+
+	/// Makes a member an admin, a plain member or read-only, only the owner can.
+	pub async fn set_member_role(&self, topic_id: String, node_id: String, role: Role) -> Result<()> {
+		self.moderate(&topic_id, Moderation::SetRole { node_id, role }).await
+	}
+
+	/// Hands the topic over to another member, this node stays on as an admin.
+	pub async fn transfer_ownership(&self, topic_id: String, node_id: String) -> Result<()> {
+		let topic = self.db.get_topic_by_topic_id(topic_id.clone()).await?;
+		if node_id == topic.owner || !self.db.is_member(&topic_id, &node_id).await? {
+			return Err(anyhow!("The topic can only be transferred to another member").into());
+		}
+		self.moderate(&topic_id, Moderation::TransferOwnership { node_id })
+			.await
+	}
+
+	// Checks this node may take the action, applies it here first and then tells the members online in the topic
+	async fn moderate(&self, topic_id: &str, moderation: Moderation) -> Result<()> {
+		let topic = self.db.get_topic_by_topic_id(topic_id.to_string()).await?;
+		check_moderation(&self.db, &topic, &self.node_id(), &moderation).await?;
+		if let Some(event) = apply_moderation(&self.db, topic_id, moderation.clone()).await? {
+			self.events.emit(event.into());
+		}
+
+		let seq = self.db.next_moderation_seq(topic_id).await?;
+
+		if self.comm.is_joined(topic_id) {
+			let topic_sender = self.comm.topic_sender(topic_id)?;
+			let metadata = model::Metadata::new(self.user_info.clone(), self.node_id(), None);
+			let update = ModerationUpdate {
+				seq,
+				action: moderation,
+			};
+			let message = MessageType::Moderation(model::Message::new(update, metadata));
+			let message = SignedMessage::sign_and_encode(self.comm.endpoint.secret_key(), topic_id, &message)?;
+			topic_sender
+				.broadcast(message.into())
+				.await
+				.map_err(|e| Error::GossipSubscription(format!("Failed to send moderation: {}", e)))?;
+		}
+		Ok(())
+	}
+}
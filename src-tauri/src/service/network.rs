@@ -0,0 +1,55 @@
+use iroh::Endpoint;
+
+use crate::{
+	comm::endpoint::NetworkConfig,
+	database::{settings::SettingsOperations, Db},
+	error::Result,
+	AppState,
+};
+
+use super::init_node;
+
+impl AppState {
+	pub async fn get_network_config(&self) -> Result<NetworkConfig> {
+		load_network_config(&self.db).await
+	}
+
+	/// Moves the node onto an endpoint built from the configuration and stores it once the node runs on it.
+	/// Topics that were joined are joined again on the new endpoint.
+	/// If the node can not be brought up on it, it goes back to the stored configuration.
+	pub async fn set_network_config(&mut self, config: NetworkConfig) -> Result<NetworkConfig> {
+		// Bind the new endpoint first, so an invalid configuration leaves the running node untouched
+		let builder = config.endpoint_builder()?;
+		let endpoint = init_node(&self.db, &self.keystore, builder).await?;
+
+		let joined = self.list_joined_topics();
+		if let Err(e) = self.move_to_endpoint(endpoint).await {
+			let previous = load_network_config(&self.db).await?;
+			let endpoint = init_node(&self.db, &self.keystore, previous.endpoint_builder()?).await?;
+			self.move_to_endpoint(endpoint).await?;
+			self.rejoin_topic_ids(joined).await?;
+			return Err(e);
+		}
+		self.db
+			.set_setting(NetworkConfig::SETTINGS_KEY, &serde_json::to_string(&config)?)
+			.await?;
+		self.rejoin_topic_ids(joined).await?;
+
+		Ok(config)
+	}
+
+	async fn move_to_endpoint(&mut self, endpoint: Endpoint) -> Result<()> {
+		// Transfers run on the store and endpoint that are replaced
+		self.downloads.stop();
+		self.comm.restart(endpoint).await?;
+		self.start_downloads().await
+	}
+}
+
+// Nodes without a stored configuration use the default one
+pub(super) async fn load_network_config(db: &Db) -> Result<NetworkConfig> {
+	match db.get_setting(NetworkConfig::SETTINGS_KEY).await? {
+		Some(value) => Ok(serde_json::from_str(&value)?),
+		None => Ok(NetworkConfig::default()),
+	}
+}
@@ -0,0 +1,72 @@
+use crate::{
+	comm::{
+		model::{self, MessageType, SignedMessage, TicketRevoked},
+		ticket::{Grant, Ticket},
+	},
+	database::{
+		ticket::{IssuedTicket, TicketOperations},
+		topic::Topic,
+	},
+	error::{Error, Result},
+	AppState,
+};
+
+impl AppState {
+	pub async fn list_issued_tickets(&self, topic_id: Option<String>) -> Result<Vec<IssuedTicket>> {
+		self.db.list_issued_tickets(topic_id).await
+	}
+
+	/// Stops members from admitting newcomers with a ticket this node issued.
+	/// Members online in the topic are told right away, newcomers already admitted stay.
+	pub async fn revoke_ticket(&self, nonce: String) -> Result<()> {
+		let ticket = self
+			.db
+			.list_issued_tickets(None)
+			.await?
+			.into_iter()
+			.find(|ticket| ticket.nonce == nonce)
+			.ok_or_else(|| Error::Ticket("Only tickets issued by this node can be revoked".to_string()))?;
+		self.db.revoke_ticket(&nonce, &self.node_id()).await?;
+
+		if self.comm.is_joined(&ticket.topic_id) {
+			let topic_sender = self.comm.topic_sender(&ticket.topic_id)?;
+			let metadata = model::Metadata::new(self.user_info.clone(), self.node_id(), None);
+			let message = MessageType::TicketRevoked(model::Message::new(TicketRevoked::new(nonce), metadata));
+			let message = SignedMessage::sign_and_encode(self.comm.endpoint.secret_key(), &ticket.topic_id, &message)?;
+			topic_sender
+				.broadcast(message.into())
+				.await
+				.map_err(|e| Error::GossipSubscription(format!("Failed to send revocation: {}", e)))?;
+		}
+		Ok(())
+	}
+
+	// Signs a grant for `topic` and records it as issued, returns the `name:ticket` invitation
+	pub(super) async fn issue_ticket(
+		&self,
+		topic: &Topic,
+		expires_in: Option<i64>,
+		max_uses: Option<u32>,
+	) -> Result<String> {
+		if expires_in.is_some_and(|seconds| seconds <= 0) {
+			return Err(Error::Ticket(
+				"A ticket has to be valid for at least a second".to_string(),
+			));
+		}
+		if max_uses == Some(0) {
+			return Err(Error::Ticket("A ticket has to allow at least one use".to_string()));
+		}
+
+		let expires_at = expires_in.map(|seconds| chrono::Utc::now().timestamp() + seconds);
+		let grant = Grant::issue(
+			self.comm.endpoint.secret_key(),
+			topic.topic_id.clone(),
+			expires_at,
+			max_uses,
+		);
+		self.db.create_issued_ticket(&grant).await?;
+
+		let ticket = Ticket::new(&topic.topic_id, topic.get_peers(), Some(grant))?;
+		Ok(format!("{name}:{ticket}", name = topic.name))
+	}
+}
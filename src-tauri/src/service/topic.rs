@@ -0,0 +1,346 @@
+use anyhow::anyhow;
+use std::str::FromStr;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+	comm::{
+		connectivity::Connectivity,
+		events::TopicStatus,
+		model::{self, Leave, MessageType, SignedMessage},
+		state::TopicSubscription,
+		subscribe,
+		ticket::Ticket,
+		OnlineMembers, OFFLINE_AFTER,
+	},
+	database::{
+		member::{Member, MemberOperations},
+		ticket::TicketOperations,
+		topic::{Topic, TopicOperations},
+	},
+	error::{Error, Result},
+	AppState,
+};
+
+impl AppState {
+	pub async fn list_topics(&self) -> Result<Vec<Topic>> {
+		let topics = self.db.list_topics().await?;
+		Ok(topics.into_iter().filter(|topic| !topic.archived).collect())
+	}
+
+	pub async fn list_archived_topics(&self) -> Result<Vec<Topic>> {
+		let topics = self.db.list_topics().await?;
+		Ok(topics.into_iter().filter(|topic| topic.archived).collect())
+	}
+
+	pub async fn get_topic_by_topic_id(&self, topic_id: String) -> Result<Topic> {
+		let topic = self.db.get_topic_by_topic_id(topic_id).await?;
+		Ok(topic)
+	}
+
+	/// Everyone in the topic with their profile and presence, this node is active while it is in the topic.
+	/// Without the topic joined, members that checked in lately count as active.
+	pub async fn list_members(&self, topic_id: String) -> Result<Vec<Member>> {
+		let now = chrono::Utc::now().timestamp();
+		let mut members = self.db.list_member_presence(&topic_id, now - OFFLINE_AFTER).await?;
+		if let Some(online) = self.comm.online_members(&topic_id).await {
+			let my_node_id = self.node_id();
+			for member in &mut members {
+				if member.node_id == my_node_id {
+					member.last_seen = Some(now);
+					member.is_active = true;
+				} else {
+					// A member that left is offline right away, not only once its check-ins are missed
+					member.is_active = online.contains(&member.node_id);
+				}
+			}
+		}
+		Ok(members)
+	}
+
+	/// The direct gossip neighbors of a joined topic, and whether this node lost them all or fell behind.
+	pub async fn topic_connectivity(&self, topic_id: String) -> Result<Connectivity> {
+		self.comm.connectivity(&topic_id).await
+	}
+
+	/// Creates a topic owned by this node, joins it and returns its `name:ticket` invitation.
+	pub async fn start_new_topic(&mut self, name: String) -> Result<String> {
+		let topic = Topic::new_topic(name, self.node_id(), Vec::new());
+		let topic = self.db.create_topic(topic).await?;
+
+		let topic = self.setup_topic_subscription(topic).await?;
+		self.issue_ticket(&topic, None, None).await
+	}
+
+	pub async fn join_topic_with_ticket(&mut self, key: &str) -> Result<Topic> {
+		let (name, ticket) = parse_ticket(key)?;
+		if self.comm.is_joined(&ticket.topic.to_string()) {
+			return Err(anyhow!("Already joined this topic").into());
+		}
+		let nodes_str = ticket.nodes_to_string();
+		let (owner, members) = nodes_str.split_first().ok_or_else(|| {
+			Error::Generic(anyhow!(
+				"Ticket must contain at least one node as owner and possibly more members"
+			))
+		})?;
+		let mut members = members.to_vec();
+		members.push(self.node_id());
+		let topic = match self.db.get_topic_by_topic_id(ticket.topic.to_string()).await {
+			// A topic this node left, archived or was removed from keeps what it knew, the ticket only adds to it
+			Ok(mut topic) => {
+				for member in std::iter::once(owner).chain(&members) {
+					self.db.add_member(&topic.topic_id, member).await?;
+				}
+				if topic.archived {
+					topic = self.db.set_topic_archived(topic.id, false).await?;
+				}
+				self.db.set_topic_auto_join(topic.id, true).await?
+			},
+			Err(e) if !matches!(e.downcast_ref(), Some(sqlx::Error::RowNotFound)) => return Err(e.into()),
+			Err(_) => {
+				let topic = Topic::new_topic_with_id(ticket.topic.to_string(), name, owner.to_string(), members);
+				self.db.create_topic(topic).await?
+			},
+		};
+		// Presented in check-ins, so the members admit this node
+		if let Some(grant) = &ticket.grant {
+			self.db.set_join_grant(&topic.topic_id, grant).await?;
+		}
+		self.setup_topic_subscription(topic).await
+	}
+
+	pub async fn join_topic_with_id(&mut self, id: i64) -> Result<Topic> {
+		let mut topic = self.db.get_topic_by_id(id).await?;
+		// The members let this node back in with a new ticket only
+		if !self.db.is_member(&topic.topic_id, &self.node_id()).await? {
+			return Err(Error::Forbidden(
+				"This node is no longer in the topic, join it again with a new ticket".to_string(),
+			));
+		}
+		// Joining an archived topic brings it back to the topic list
+		if topic.archived {
+			topic = self.db.set_topic_archived(topic.id, false).await?;
+		}
+		if !topic.auto_join {
+			topic = self.db.set_topic_auto_join(topic.id, true).await?;
+		}
+		// Switching back to a topic that is still subscribed does not need a new subscription
+		if self.comm.is_joined(&topic.topic_id) {
+			return Ok(topic);
+		}
+		self.setup_topic_subscription(topic).await
+	}
+
+	/// Subscribes to the topic for this session only, auto join and the archive are left as they are.
+	pub async fn open_topic(&mut self, topic_id: &str) -> Result<Topic> {
+		let topic = self.db.get_topic_by_topic_id(topic_id.to_string()).await?;
+		if self.comm.is_joined(&topic.topic_id) {
+			return Ok(topic);
+		}
+		self.setup_topic_subscription(topic).await
+	}
+
+	pub fn list_joined_topics(&self) -> Vec<String> {
+		self.comm
+			.topics
+			.keys()
+			.filter(|topic_id| self.comm.is_joined(topic_id))
+			.cloned()
+			.collect()
+	}
+
+	/// Leaves the topic and tells the members online, so they do not wait for missed check-ins.
+	/// A permanent leave takes this node out of the topic, it needs a ticket to come back.
+	pub async fn leave_topic(&mut self, topic_id: String, permanent: bool) -> Result<()> {
+		let topic = self.db.get_topic_by_topic_id(topic_id.clone()).await?;
+		let my_node_id = self.node_id();
+		if permanent && topic.owner == my_node_id {
+			return Err(Error::Forbidden(
+				"Transfer the topic to another member before leaving it for good".to_string(),
+			));
+		}
+
+		if self.comm.is_joined(&topic_id) {
+			let topic_sender = self.comm.topic_sender(&topic_id)?;
+			let metadata = model::Metadata::new(self.user_info.clone(), my_node_id.clone(), None);
+			// Members spend the ticket this node joined with, it takes a new one to come back
+			let nonces = match permanent {
+				true => self
+					.db
+					.get_join_grant(&topic_id)
+					.await?
+					.map(|grant| grant.nonce)
+					.into_iter()
+					.collect(),
+				false => Vec::new(),
+			};
+			let message = MessageType::Leave(model::Message::new(Leave { permanent, nonces }, metadata));
+			let message = SignedMessage::sign_and_encode(self.comm.endpoint.secret_key(), &topic_id, &message)?;
+			// Leaving does not depend on anyone hearing about it
+			if let Err(e) = topic_sender.broadcast(message.into()).await {
+				eprintln!("Failed to announce leaving {}: {}", topic_id, e);
+			}
+		}
+		if self.comm.leave_topic(&topic_id) || permanent {
+			// A topic that was left on purpose is not joined again on the next start
+			self.db.set_topic_auto_join(topic.id, false).await?;
+		}
+		if permanent {
+			self.db.remove_member(&topic_id, &my_node_id).await?;
+		}
+		Ok(())
+	}
+
+	/// Leaves the topic and hides it from the topic list, its members, files and chat history are kept.
+	pub async fn archive_topic(&mut self, topic_id: String) -> Result<Topic> {
+		self.leave_topic(topic_id.clone(), false).await?;
+		let topic = self.db.get_topic_by_topic_id(topic_id).await?;
+		let topic = self.db.set_topic_archived(topic.id, true).await?;
+		Ok(topic)
+	}
+
+	/// Brings an archived topic back to the topic list, joining it again is up to the user.
+	pub async fn unarchive_topic(&self, topic_id: String) -> Result<Topic> {
+		let topic = self.db.get_topic_by_topic_id(topic_id).await?;
+		let topic = self.db.set_topic_archived(topic.id, false).await?;
+		Ok(topic)
+	}
+
+	/// Leaves the topic and deletes it with its files, chat history and moderation state from this node.
+	/// The blobs of its files that no other topic shares are dropped from the store the next time the node starts.
+	/// The other members keep the topic, this node can come back with a new ticket.
+	pub async fn delete_topic(&mut self, topic_id: String) -> Result<()> {
+		let topic = self.db.get_topic_by_topic_id(topic_id.clone()).await?;
+		if self.comm.is_joined(&topic_id) {
+			self.leave_topic(topic_id, false).await?;
+		}
+		let unreferenced = self.db.delete_topic(topic.id).await?;
+		self.untag_blobs(&unreferenced).await
+	}
+
+	pub async fn set_topic_auto_join(&self, topic_id: String, auto_join: bool) -> Result<Topic> {
+		let topic = self.db.get_topic_by_topic_id(topic_id).await?;
+		let topic = self.db.set_topic_auto_join(topic.id, auto_join).await?;
+		Ok(topic)
+	}
+
+	/// Issues a new invitation to the topic, optionally expiring after `expires_in` seconds or after `max_uses` newcomers.
+	pub async fn get_ticket_for_topic(
+		&self,
+		topic_id: String,
+		expires_in: Option<i64>,
+		max_uses: Option<u32>,
+	) -> Result<String> {
+		let topic = self.db.get_topic_by_topic_id(topic_id).await?;
+		// Members only admit newcomers with tickets from admins
+		if !self.db.get_role(&topic.topic_id, &self.node_id()).await?.can_moderate() {
+			return Err(Error::Forbidden("Only admins can invite".to_string()));
+		}
+		self.issue_ticket(&topic, expires_in, max_uses).await
+	}
+
+	/// Subscribes again to every topic flagged with auto_join, called once on startup.
+	pub async fn rejoin_auto_join_topics(&mut self) {
+		let topics = match self.db.list_auto_join_topics().await {
+			Ok(topics) => topics,
+			Err(e) => {
+				eprintln!("Failed to list auto join topics: {}", e);
+				return;
+			},
+		};
+
+		self.rejoin_topics(topics).await;
+	}
+
+	// Joins topics again after their subscriptions were stopped to swap out the endpoint or database
+	pub(super) async fn rejoin_topic_ids(&mut self, topic_ids: Vec<String>) -> Result<()> {
+		let mut topics = Vec::with_capacity(topic_ids.len());
+		for topic_id in topic_ids {
+			topics.push(self.db.get_topic_by_topic_id(topic_id).await?);
+		}
+		self.rejoin_topics(topics).await;
+		Ok(())
+	}
+
+	// Subscribes to each topic that is not joined yet, reporting the outcome through topic-status
+	pub(super) async fn rejoin_topics(&mut self, topics: Vec<Topic>) {
+		for topic in topics {
+			if self.comm.is_joined(&topic.topic_id) {
+				continue;
+			}
+			let topic_id = topic.topic_id.clone();
+			let status = match self.setup_topic_subscription(topic).await {
+				Ok(_) => TopicStatus::Joining { topic_id },
+				Err(e) => TopicStatus::Failed {
+					topic_id,
+					error: e.to_string(),
+				},
+			};
+			self.events.emit(status.into());
+		}
+	}
+
+	async fn setup_topic_subscription(&mut self, topic: Topic) -> Result<Topic> {
+		let gossip = self.comm.gossip.clone();
+		let ticket = Ticket::new(&topic.topic_id, topic.get_peers(), None)?;
+		let (sender, receiver) = gossip
+			.subscribe(ticket.topic, ticket.nodes.clone())
+			.await
+			.map_err(|e| Error::GossipSubscription(format!("Failed to subscribe to gossip: {}", e)))?
+			.split();
+		let sender_copy = sender.clone();
+		let topic_id_copy = topic.topic_id.clone();
+		let cancellation_token = CancellationToken::new();
+		let cancellation_token_copy = cancellation_token.clone();
+		let ctx = self.subscription_context();
+		let connectivity = Connectivity::shared(topic.topic_id.clone());
+		let connectivity_copy = connectivity.clone();
+		let online = OnlineMembers::default();
+		let online_copy = online.clone();
+		let subscriber = tokio::spawn(async move {
+			let events = ctx.events.clone();
+			if let Err(e) = subscribe(
+				receiver,
+				sender_copy,
+				ctx,
+				topic_id_copy.clone(),
+				connectivity_copy,
+				online_copy,
+				cancellation_token_copy,
+			)
+			.await
+			{
+				events.emit(
+					TopicStatus::Failed {
+						topic_id: topic_id_copy,
+						error: e.to_string(),
+					}
+					.into(),
+				);
+			}
+		});
+		self.comm.topics.insert(
+			topic.topic_id.clone(),
+			TopicSubscription {
+				sender,
+				subscriber,
+				cancel_token: cancellation_token,
+				connectivity,
+				online,
+			},
+		);
+		Ok(topic)
+	}
+}
+
+// Helper: Parse and validate ticket
+fn parse_ticket(key: &str) -> Result<(String, Ticket)> {
+	let parts = key.split(':').collect::<Vec<_>>();
+	if parts.len() != 2 {
+		return Err(crate::error::Error::Generic(anyhow!(
+			"Invalid ticket format, expected 'name:ticket'"
+		)));
+	}
+	let (name, ticket_key) = (parts[0].to_string(), parts[1]);
+	let ticket = Ticket::from_str(ticket_key)?;
+	Ok((name, ticket))
+}
@@ -0,0 +1,174 @@
+// In-process network of CrewCast nodes. Endpoints bind to localhost with relays disabled and find each
+// other through a shared static discovery, so the tests run without any network access.
+#![allow(dead_code)]
+
+use std::{
+	future::Future,
+	net::{Ipv4Addr, SocketAddrV4},
+	path::PathBuf,
+	sync::Arc,
+	time::Duration,
+};
+
+use crewcast_lib::{
+	comm::events::{DownloadProgress, Event, GossipEvent, MemorySink, TopicStatus},
+	AppState,
+};
+use iroh::{discovery::static_provider::StaticProvider, Endpoint, NodeAddr, RelayMode};
+use tempfile::TempDir;
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+
+pub const TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Default)]
+pub struct TestNet {
+	discovery: StaticProvider,
+}
+
+impl TestNet {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Starts a node with its own data directory and a user profile named `name`.
+	pub async fn spawn(&self, name: &str) -> TestNode {
+		let dir = tempfile::tempdir().expect("failed to create data dir");
+		let mut node = self.start(name, dir).await;
+		let node_id = node.node_id();
+		node.state
+			.create_user(format!("{name}@crewcast.test"), name.to_string(), None, node_id)
+			.await
+			.expect("failed to create user");
+		node
+	}
+
+	/// Stops the node and starts it again on the same data directory, like an app restart.
+	pub async fn restart(&self, node: TestNode) -> TestNode {
+		let TestNode {
+			name, mut state, dir, ..
+		} = node;
+		state.close().await.expect("failed to close node");
+		self.start(&name, dir).await
+	}
+
+	async fn start(&self, name: &str, dir: TempDir) -> TestNode {
+		let sink = MemorySink::default();
+		let events = sink.subscribe();
+
+		let builder = Endpoint::builder()
+			.relay_mode(RelayMode::Disabled)
+			.bind_addr_v4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0))
+			.add_discovery(self.discovery.clone());
+		let state = AppState::init_with_endpoint(
+			dir.path().to_path_buf(),
+			"password".to_string(),
+			Arc::new(sink),
+			builder,
+		)
+		.await
+		.expect("failed to start node");
+
+		let endpoint = state.endpoint();
+		self.discovery
+			.add_node_info(NodeAddr::from_parts(endpoint.node_id(), None, endpoint.bound_sockets()));
+
+		TestNode {
+			name: name.to_string(),
+			state,
+			events,
+			dir,
+		}
+	}
+}
+
+pub struct TestNode {
+	pub name: String,
+	pub state: AppState,
+	events: Receiver<Event>,
+	dir: TempDir,
+}
+
+impl TestNode {
+	pub fn node_id(&self) -> String {
+		self.state.node_id()
+	}
+
+	pub fn dir(&self) -> &std::path::Path {
+		self.dir.path()
+	}
+
+	/// Waits for the first event `matcher` picks, events before it are dropped.
+	pub async fn wait_for<T>(&mut self, mut matcher: impl FnMut(Event) -> Option<T>) -> T {
+		let events = &mut self.events;
+		let found = tokio::time::timeout(TIMEOUT, async {
+			loop {
+				match events.recv().await {
+					Ok(event) => {
+						if let Some(found) = matcher(event) {
+							return found;
+						}
+					},
+					Err(RecvError::Lagged(_)) => (),
+					Err(RecvError::Closed) => panic!("event sink closed"),
+				}
+			}
+		})
+		.await;
+		found.unwrap_or_else(|_| panic!("{} timed out waiting for an event", self.name))
+	}
+
+	pub async fn wait_online(&mut self, topic_id: &str) {
+		self.wait_for(|event| match event {
+			Event::TopicStatus(TopicStatus::Online { topic_id: online }) if online == topic_id => Some(()),
+			Event::TopicStatus(TopicStatus::Failed {
+				topic_id: failed,
+				error,
+			}) if failed == topic_id => {
+				panic!("failed to join {}: {}", topic_id, error)
+			},
+			_ => None,
+		})
+		.await
+	}
+
+	/// Waits for a queued download to end, with where it was saved or why it failed.
+	pub async fn wait_download(&mut self, file_id: i64) -> Result<PathBuf, String> {
+		self.wait_for(|event| match event {
+			Event::DownloadProgress(DownloadProgress::Complete {
+				file_id: done, path, ..
+			}) if done == file_id => Some(Ok(PathBuf::from(path))),
+			Event::DownloadProgress(DownloadProgress::Failed {
+				file_id: failed, error, ..
+			}) if failed == file_id => Some(Err(error)),
+			_ => None,
+		})
+		.await
+	}
+
+	pub async fn wait_gossip<T>(&mut self, mut matcher: impl FnMut(GossipEvent) -> Option<T>) -> T {
+		self.wait_for(|event| match event {
+			Event::Gossip(gossip) => matcher(gossip),
+			_ => None,
+		})
+		.await
+	}
+
+	pub async fn close(mut self) {
+		self.state.close().await.expect("failed to close node");
+	}
+}
+
+/// Polls `check` until it holds, for state that is not announced through an event.
+pub async fn eventually<F, Fut>(what: &str, mut check: F)
+where
+	F: FnMut() -> Fut,
+	Fut: Future<Output = bool>,
+{
+	let result = tokio::time::timeout(TIMEOUT, async {
+		while !check().await {
+			tokio::time::sleep(Duration::from_millis(200)).await;
+		}
+	})
+	.await;
+	result.unwrap_or_else(|_| panic!("timed out waiting for {}", what));
+}
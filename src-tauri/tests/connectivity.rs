@@ -0,0 +1,41 @@
+mod common;
+
+use common::{eventually, TestNet};
+use crewcast_lib::comm::events::{Event, SwarmEvent};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn losing_every_neighbor_is_reported() {
+	let net = TestNet::new();
+	let mut alice = net.spawn("alice").await;
+	let ticket = alice.state.start_new_topic("crew".to_string()).await.unwrap();
+	let topic_id = alice.state.list_joined_topics().remove(0);
+
+	let mut bob = net.spawn("bob").await;
+	let topic = bob.state.join_topic_with_ticket(&ticket).await.unwrap();
+	bob.wait_online(&topic.topic_id).await;
+	let bob_id = bob.node_id();
+	eventually("alice to see bob as a neighbor", || async {
+		let connectivity = alice.state.topic_connectivity(topic_id.clone()).await.unwrap();
+		connectivity.neighbors.iter().any(|neighbor| neighbor.node_id == bob_id)
+	})
+	.await;
+	let connectivity = bob.state.topic_connectivity(topic_id.clone()).await.unwrap();
+	assert!(connectivity
+		.neighbors
+		.iter()
+		.any(|neighbor| neighbor.node_id == alice.node_id()));
+	assert!(!connectivity.partitioned);
+
+	bob.close().await;
+	alice
+		.wait_for(|event| match event {
+			Event::Swarm(SwarmEvent::Partitioned { topic_id: partitioned }) if partitioned == topic_id => Some(()),
+			_ => None,
+		})
+		.await;
+	let connectivity = alice.state.topic_connectivity(topic_id.clone()).await.unwrap();
+	assert!(connectivity.neighbors.is_empty());
+	assert!(connectivity.partitioned);
+
+	alice.close().await;
+}
@@ -0,0 +1,121 @@
+#![cfg(feature = "sqlcipher")]
+
+mod common;
+
+use common::TestNet;
+use crewcast_lib::{
+	database::{settings::SettingsOperations, Db},
+	error::Error,
+};
+use sqlx::{sqlite::SqliteConnectOptions, ConnectOptions, Connection};
+
+const PLAINTEXT_HEADER: &[u8] = b"SQLite format 3\0";
+
+#[tokio::test]
+async fn wrong_passphrase_is_rejected() {
+	let dir = tempfile::tempdir().unwrap();
+	let db_path = dir.path().join("data.db");
+
+	let db = Db::init(&db_path, "correct horse").await.unwrap();
+	db.close().await.unwrap();
+
+	assert!(matches!(
+		Db::init(&db_path, "battery staple").await,
+		Err(Error::Passphrase(_))
+	));
+	assert!(matches!(
+		Db::check_passphrase(&db_path, "battery staple").await,
+		Err(Error::Passphrase(_))
+	));
+	Db::check_passphrase(&db_path, "correct horse").await.unwrap();
+}
+
+#[tokio::test]
+async fn plaintext_database_is_encrypted_on_open() {
+	let dir = tempfile::tempdir().unwrap();
+	let db_path = dir.path().join("data.db");
+
+	// A database written before encryption existed
+	let mut conn = SqliteConnectOptions::new()
+		.filename(&db_path)
+		.create_if_missing(true)
+		.connect()
+		.await
+		.unwrap();
+	sqlx::query("CREATE TABLE settings (key TEXT PRIMARY KEY NOT NULL, value TEXT NOT NULL)")
+		.execute(&mut conn)
+		.await
+		.unwrap();
+	sqlx::query("INSERT INTO settings (key, value) VALUES ('network', 'plaintext marker')")
+		.execute(&mut conn)
+		.await
+		.unwrap();
+	conn.close().await.unwrap();
+	assert!(std::fs::read(&db_path).unwrap().starts_with(PLAINTEXT_HEADER));
+	assert!(Db::is_plaintext(&db_path).unwrap());
+
+	let db = Db::init(&db_path, "passphrase").await.unwrap();
+	assert_eq!(
+		db.get_setting("network").await.unwrap().as_deref(),
+		Some("plaintext marker")
+	);
+	db.close().await.unwrap();
+
+	assert!(!Db::is_plaintext(&db_path).unwrap());
+	let bytes = std::fs::read(&db_path).unwrap();
+	assert!(!bytes.starts_with(PLAINTEXT_HEADER));
+	assert!(!bytes.windows(16).any(|window| window == b"plaintext marker"));
+}
+
+#[tokio::test]
+async fn passphrase_change_keeps_data() {
+	let dir = tempfile::tempdir().unwrap();
+	let db_path = dir.path().join("data.db");
+
+	let db = Db::init(&db_path, "old").await.unwrap();
+	db.set_setting("network", "kept").await.unwrap();
+	db.close().await.unwrap();
+
+	assert!(matches!(
+		Db::change_passphrase(&db_path, "wrong", "new").await,
+		Err(Error::Passphrase(_))
+	));
+	Db::change_passphrase(&db_path, "old", "new").await.unwrap();
+
+	assert!(matches!(
+		Db::check_passphrase(&db_path, "old").await,
+		Err(Error::Passphrase(_))
+	));
+	let db = Db::init(&db_path, "new").await.unwrap();
+	assert_eq!(db.get_setting("network").await.unwrap().as_deref(), Some("kept"));
+	db.close().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn running_node_changes_passphrase() {
+	let net = TestNet::new();
+	let mut alice = net.spawn("alice").await;
+	alice.state.start_new_topic("crew".to_string()).await.unwrap();
+	let joined = alice.state.list_joined_topics();
+
+	assert!(matches!(
+		alice
+			.state
+			.change_passphrase("wrong".to_string(), "new".to_string())
+			.await,
+		Err(Error::Passphrase(_))
+	));
+	alice
+		.state
+		.change_passphrase("password".to_string(), "new".to_string())
+		.await
+		.unwrap();
+
+	// The node keeps running on the re-encrypted database
+	assert_eq!(alice.state.list_joined_topics(), joined);
+	assert_eq!(alice.state.list_topics().await.unwrap().len(), 1);
+	let db_path = alice.dir().join("data.db");
+	Db::check_passphrase(&db_path, "new").await.unwrap();
+
+	alice.close().await;
+}
@@ -0,0 +1,275 @@
+mod common;
+
+use common::{eventually, TestNet};
+use crewcast_lib::database::{
+	download::DownloadOperations,
+	file::{FileOperations, FileStatus},
+};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn the_download_queue_survives_a_restart() {
+	let net = TestNet::new();
+	let mut alice = net.spawn("alice").await;
+	let ticket = alice.state.start_new_topic("crew".to_string()).await.unwrap();
+	let topic_id = alice.state.list_joined_topics().remove(0);
+	let mut bob = net.spawn("bob").await;
+	bob.state.join_topic_with_ticket(&ticket).await.unwrap();
+	bob.wait_online(&topic_id).await;
+
+	for name in ["notes.txt", "plans.txt"] {
+		let path = alice.dir().join(name);
+		std::fs::write(&path, name).unwrap();
+		alice
+			.state
+			.share_file(topic_id.clone(), path.to_string_lossy().to_string())
+			.await
+			.unwrap();
+	}
+	eventually("bob to see both files", || async {
+		bob.state.list_files(topic_id.clone()).await.unwrap().len() == 2
+	})
+	.await;
+	let files = bob.state.list_files(topic_id.clone()).await.unwrap();
+	let file = |name: &str| files.iter().find(|file| file.name == name).unwrap().clone();
+	let (notes, plans) = (file("notes.txt"), file("plans.txt"));
+	bob.state
+		.set_download_dir(bob.dir().join("downloads").to_string_lossy().to_string())
+		.await
+		.unwrap();
+
+	// With the node that shared them gone, one download is paused and the other fails
+	alice.state.close().await.unwrap();
+	bob.state.download_file(notes.clone(), None).await.unwrap();
+	// Asking again while it is queued or running does not start a second transfer
+	assert!(bob.state.download_file(notes.clone(), None).await.is_err());
+	bob.state.pause_download(notes.id).await.unwrap();
+	bob.state.download_file(plans.clone(), None).await.unwrap();
+	assert!(bob.wait_download(plans.id).await.is_err());
+	let downloads = bob.state.list_downloads().await.unwrap();
+	let statuses: Vec<_> = downloads
+		.iter()
+		.map(|download| (download.file_id, download.status.clone()))
+		.collect();
+	assert_eq!(
+		statuses,
+		[(notes.id, FileStatus::Paused), (plans.id, FileStatus::Failed)]
+	);
+	assert!(downloads[1].error.is_some());
+	assert!(bob.state.pause_download(plans.id).await.is_err());
+
+	// The queue is kept, a paused or failed download waits to be resumed
+	let mut bob = net.restart(bob).await;
+	let downloads = bob.state.list_downloads().await.unwrap();
+	let statuses: Vec<_> = downloads.iter().map(|download| download.status.clone()).collect();
+	assert_eq!(statuses, [FileStatus::Paused, FileStatus::Failed]);
+
+	bob.state.cancel_download(plans.id).await.unwrap();
+	let alice = net.restart(alice).await;
+	bob.state.resume_download(notes.id).await.unwrap();
+	let saved = bob.wait_download(notes.id).await.unwrap();
+	assert_eq!(saved, bob.dir().join("downloads").join("notes.txt"));
+	assert_eq!(std::fs::read(&saved).unwrap(), b"notes.txt");
+
+	assert!(bob.state.list_downloads().await.unwrap().is_empty());
+	let files = bob.state.list_files(topic_id).await.unwrap();
+	let status = |id: i64| files.iter().find(|file| file.id == id).unwrap().status.clone();
+	assert_eq!(status(notes.id), FileStatus::Downloaded);
+	assert_eq!(status(plans.id), FileStatus::Shared);
+
+	// Cancelling a second download of a file leaves it downloaded where it was saved
+	bob.state.db.queue_download(notes.id, "again.txt").await.unwrap();
+	bob.state.cancel_download(notes.id).await.unwrap();
+	let notes = bob.state.db.get_file_by_id(notes.id).await.unwrap();
+	assert_eq!(notes.status, FileStatus::Downloaded);
+	assert_eq!(notes.absolute_path, Some(saved.to_string_lossy().to_string()));
+
+	alice.close().await;
+	bob.close().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn members_that_downloaded_a_file_serve_it() {
+	let net = TestNet::new();
+	let mut alice = net.spawn("alice").await;
+	let ticket = alice.state.start_new_topic("crew".to_string()).await.unwrap();
+	let topic_id = alice.state.list_joined_topics().remove(0);
+	let mut bob = net.spawn("bob").await;
+	bob.state.join_topic_with_ticket(&ticket).await.unwrap();
+	bob.wait_online(&topic_id).await;
+	let mut carol = net.spawn("carol").await;
+	carol.state.join_topic_with_ticket(&ticket).await.unwrap();
+	carol.wait_online(&topic_id).await;
+
+	let path = alice.dir().join("notes.txt");
+	std::fs::write(&path, "notes").unwrap();
+	alice
+		.state
+		.share_file(topic_id.clone(), path.to_string_lossy().to_string())
+		.await
+		.unwrap();
+	for node in [&bob, &carol] {
+		eventually("the file to reach the members", || async {
+			node.state.list_files(topic_id.clone()).await.unwrap().len() == 1
+		})
+		.await;
+	}
+	let file = bob.state.list_files(topic_id.clone()).await.unwrap().remove(0);
+	bob.state
+		.download_file(file.clone(), Some(bob.dir().join("notes.txt")))
+		.await
+		.unwrap();
+	bob.wait_download(file.id).await.unwrap();
+
+	// Bob announces the blob in the next check-in
+	let file = carol.state.list_files(topic_id.clone()).await.unwrap().remove(0);
+	eventually("carol to learn that bob holds the file", || async {
+		carol.state.list_file_holders(file.id).await.unwrap() == [alice.node_id(), bob.node_id()]
+	})
+	.await;
+
+	// With the node that shared it gone the file still comes from bob
+	alice.close().await;
+	carol
+		.state
+		.download_file(file.clone(), Some(carol.dir().join("notes.txt")))
+		.await
+		.unwrap();
+	let saved = carol.wait_download(file.id).await.unwrap();
+	assert_eq!(std::fs::read(&saved).unwrap(), b"notes");
+
+	bob.close().await;
+	carol.close().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn shared_directories_keep_their_layout() {
+	let net = TestNet::new();
+	let mut alice = net.spawn("alice").await;
+	let ticket = alice.state.start_new_topic("crew".to_string()).await.unwrap();
+	let topic_id = alice.state.list_joined_topics().remove(0);
+	let mut bob = net.spawn("bob").await;
+	bob.state.join_topic_with_ticket(&ticket).await.unwrap();
+	bob.wait_online(&topic_id).await;
+
+	let shared = alice.dir().join("photos");
+	std::fs::create_dir_all(shared.join("2024")).unwrap();
+	for name in ["cover.jpg", "2024/beach.jpg", "2024/hills.jpg"] {
+		std::fs::write(shared.join(name), name).unwrap();
+	}
+	alice
+		.state
+		.share_file(topic_id.clone(), shared.to_string_lossy().to_string())
+		.await
+		.unwrap();
+	let file = alice.state.list_files(topic_id.clone()).await.unwrap().remove(0);
+	assert_eq!((file.name.as_str(), file.format.as_str()), ("photos", "HashSeq"));
+	let sizes: Vec<_> = alice
+		.state
+		.list_file_entries(file.id)
+		.await
+		.unwrap()
+		.into_iter()
+		.map(|entry| entry.size)
+		.collect();
+	assert_eq!(sizes, [Some(14), Some(14), Some(9)]);
+
+	eventually("bob to see the directory", || async {
+		!bob.state.list_files(topic_id.clone()).await.unwrap().is_empty()
+	})
+	.await;
+	let file = bob.state.list_files(topic_id.clone()).await.unwrap().remove(0);
+	let entries = bob.state.list_file_entries(file.id).await.unwrap();
+	let paths: Vec<_> = entries.iter().map(|entry| entry.path.as_str()).collect();
+	assert_eq!(paths, ["2024/beach.jpg", "2024/hills.jpg", "cover.jpg"]);
+	assert!(entries.iter().all(|entry| entry.size.is_none()));
+
+	// Only the chosen entries are fetched
+	let target = bob.dir().join("downloads");
+	std::fs::create_dir_all(&target).unwrap();
+	bob.state
+		.download_file_entries(file.clone(), vec!["2024/hills.jpg".to_string()], Some(target.clone()))
+		.await
+		.unwrap();
+	let saved = bob.wait_download(file.id).await.unwrap();
+	assert_eq!(saved, target.join("photos"));
+	assert_eq!(std::fs::read(saved.join("2024/hills.jpg")).unwrap(), b"2024/hills.jpg");
+	assert!(!saved.join("cover.jpg").exists());
+	assert!(bob
+		.state
+		.download_file_entries(file.clone(), vec!["../secrets".to_string()], None)
+		.await
+		.is_err());
+
+	// The whole directory goes next to it
+	bob.state
+		.download_file(file.clone(), Some(target.clone()))
+		.await
+		.unwrap();
+	let saved = bob.wait_download(file.id).await.unwrap();
+	assert_eq!(saved, target.join("photos (1)"));
+	for name in ["cover.jpg", "2024/beach.jpg", "2024/hills.jpg"] {
+		assert_eq!(std::fs::read(saved.join(name)).unwrap(), name.as_bytes());
+	}
+	let entries = bob.state.list_file_entries(file.id).await.unwrap();
+	assert!(entries
+		.iter()
+		.all(|entry| entry.size.is_some() && entry.absolute_path.is_some()));
+
+	alice.close().await;
+	bob.close().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn a_file_shared_in_two_topics_is_in_both_catalogs() {
+	let net = TestNet::new();
+	let mut alice = net.spawn("alice").await;
+	let mut bob = net.spawn("bob").await;
+	let mut topic_ids = Vec::new();
+	for name in ["crew", "ops"] {
+		let ticket = alice.state.start_new_topic(name.to_string()).await.unwrap();
+		let topics = alice.state.list_topics().await.unwrap();
+		let topic_id = topics.into_iter().find(|topic| topic.name == name).unwrap().topic_id;
+		bob.state.join_topic_with_ticket(&ticket).await.unwrap();
+		bob.wait_online(&topic_id).await;
+		topic_ids.push(topic_id);
+	}
+
+	let path = alice.dir().join("notes.txt");
+	std::fs::write(&path, "notes").unwrap();
+	let path = path.to_string_lossy().to_string();
+	for topic_id in &topic_ids {
+		alice.state.share_file(topic_id.clone(), path.clone()).await.unwrap();
+	}
+	assert!(alice.state.share_file(topic_ids[0].clone(), path).await.is_err());
+
+	let mut files = Vec::new();
+	for topic_id in &topic_ids {
+		eventually("bob to see the file in both topics", || async {
+			bob.state.list_files(topic_id.clone()).await.unwrap().len() == 1
+		})
+		.await;
+		files.push(bob.state.list_files(topic_id.clone()).await.unwrap().remove(0));
+	}
+	assert_eq!(files[0].hash, files[1].hash);
+	assert_ne!(files[0].id, files[1].id);
+
+	// The content is fetched once, the second topic's download comes from the store
+	bob.state
+		.download_file(files[0].clone(), Some(bob.dir().join("crew")))
+		.await
+		.unwrap();
+	bob.wait_download(files[0].id).await.unwrap();
+	alice.close().await;
+	bob.state
+		.download_file(files[1].clone(), Some(bob.dir().join("ops")))
+		.await
+		.unwrap();
+	let saved = bob.wait_download(files[1].id).await.unwrap();
+	assert_eq!(std::fs::read(&saved).unwrap(), b"notes");
+	for topic_id in &topic_ids {
+		let file = bob.state.list_files(topic_id.clone()).await.unwrap().remove(0);
+		assert_eq!(file.status, FileStatus::Downloaded);
+	}
+
+	bob.close().await;
+}
@@ -0,0 +1,56 @@
+mod common;
+
+use common::TestNet;
+use crewcast_lib::{comm::endpoint::NetworkConfig, error::Error};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn identity_moves_to_a_new_device() {
+	let net = TestNet::new();
+	let mut laptop = net.spawn("alice").await;
+	laptop.state.start_new_topic("crew".to_string()).await.unwrap();
+	let topic_id = laptop.state.list_joined_topics().remove(0);
+	let path = laptop.dir().join("minutes.txt");
+	std::fs::write(&path, b"minutes").unwrap();
+	laptop
+		.state
+		.share_file(topic_id.clone(), path.to_string_lossy().to_string())
+		.await
+		.unwrap();
+
+	let bundle = laptop.dir().join("alice.crewcast");
+	laptop
+		.state
+		.export_identity(bundle.clone(), "bundle passphrase".to_string())
+		.await
+		.unwrap();
+	let written = std::fs::read_to_string(&bundle).unwrap();
+	assert!(!written.contains("alice@crewcast.test"));
+	assert!(!written.contains("minutes.txt"));
+
+	let mut desktop = net.spawn("desktop").await;
+	desktop.state.set_network_config(NetworkConfig::LanOnly).await.unwrap();
+	assert!(matches!(
+		desktop.state.import_identity(bundle.clone(), "wrong".to_string()).await,
+		Err(Error::Passphrase(_))
+	));
+	desktop
+		.state
+		.import_identity(bundle, "bundle passphrase".to_string())
+		.await
+		.unwrap();
+
+	assert_eq!(desktop.node_id(), laptop.node_id());
+	assert_eq!(desktop.state.user_info.first_name, "alice");
+	assert_eq!(desktop.state.user_info.email, "alice@crewcast.test");
+	let topic = desktop.state.get_topic_by_topic_id(topic_id.clone()).await.unwrap();
+	assert_eq!(topic.owner, laptop.node_id());
+	// The topic was auto-join on the laptop, so the restored node joins it again
+	assert_eq!(desktop.state.list_joined_topics(), vec![topic_id.clone()]);
+	let files = desktop.state.list_files(topic_id).await.unwrap();
+	assert_eq!(files.len(), 1);
+	assert_eq!(files[0].name, "minutes.txt");
+	assert_eq!(files[0].absolute_path, None);
+
+	laptop.close().await;
+	desktop.close().await;
+}
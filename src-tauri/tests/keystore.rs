@@ -0,0 +1,87 @@
+use std::sync::Arc;
+
+use crewcast_lib::{
+	comm::events::MemorySink,
+	database::node::NodeOperations,
+	error::Error,
+	keystore::{FileKeyStore, KeyStore},
+	AppState,
+};
+use iroh::{Endpoint, RelayMode, SecretKey};
+use sqlx::{sqlite::SqliteConnectOptions, ConnectOptions, Connection};
+
+async fn open(dir: &std::path::Path, passphrase: &str) -> crewcast_lib::error::Result<AppState> {
+	AppState::init_with_endpoint(
+		dir.to_path_buf(),
+		passphrase.to_string(),
+		Arc::new(MemorySink::default()),
+		Endpoint::builder().relay_mode(RelayMode::Disabled),
+	)
+	.await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn node_key_lives_in_the_keystore() {
+	let dir = tempfile::tempdir().unwrap();
+	let mut state = open(dir.path(), "password").await.unwrap();
+	let node_id = state.node_id();
+
+	// The nodes table, and with it everything sent to the frontend, only knows the public id
+	assert_eq!(state.db.get_legacy_secret_key(1).await.unwrap(), None);
+	let node = state.db.get_node_by_id(1).await.unwrap();
+	assert_eq!(
+		serde_json::to_value(&node).unwrap(),
+		serde_json::json!({ "id": 1, "nodeId": node_id })
+	);
+	state.close().await.unwrap();
+
+	let key = FileKeyStore::new(dir.path().join(FileKeyStore::FILE_NAME), "password")
+		.load(&node_id)
+		.unwrap()
+		.unwrap();
+	assert_eq!(key.public().to_string(), node_id);
+
+	let mut state = open(dir.path(), "password").await.unwrap();
+	assert_eq!(state.node_id(), node_id);
+	state.close().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn legacy_key_moves_to_the_keystore() {
+	let dir = tempfile::tempdir().unwrap();
+	let secret_key = SecretKey::generate(rand::rngs::OsRng);
+	let node_id = secret_key.public().to_string();
+
+	// A node set up before the keystore existed kept its key in the nodes table
+	let mut conn = SqliteConnectOptions::new()
+		.filename(dir.path().join("data.db"))
+		.create_if_missing(true)
+		.connect()
+		.await
+		.unwrap();
+	sqlx::query(
+		"CREATE TABLE nodes (id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL, node_id TEXT NOT NULL UNIQUE, secret_key TEXT)",
+	)
+	.execute(&mut conn)
+	.await
+	.unwrap();
+	sqlx::query("INSERT INTO nodes (node_id, secret_key) VALUES ($1, $2)")
+		.bind(&node_id)
+		.bind(data_encoding::BASE32_NOPAD.encode(&secret_key.to_bytes()))
+		.execute(&mut conn)
+		.await
+		.unwrap();
+	conn.close().await.unwrap();
+
+	let mut state = open(dir.path(), "password").await.unwrap();
+	assert_eq!(state.node_id(), node_id);
+	assert_eq!(state.db.get_legacy_secret_key(1).await.unwrap(), None);
+	state.close().await.unwrap();
+
+	let store = FileKeyStore::new(dir.path().join(FileKeyStore::FILE_NAME), "password");
+	assert_eq!(store.load(&node_id).unwrap().unwrap().to_bytes(), secret_key.to_bytes());
+	assert!(matches!(
+		FileKeyStore::new(dir.path().join(FileKeyStore::FILE_NAME), "wrong").load(&node_id),
+		Err(Error::Passphrase(_))
+	));
+}
@@ -0,0 +1,243 @@
+mod common;
+
+use std::time::Duration;
+
+use common::{eventually, TestNet, TestNode};
+use crewcast_lib::{
+	comm::{endpoint::apply_policy, events::GossipEvent, model::Policy},
+	database::{
+		member::{MemberOperations, Role},
+		moderation::ModerationOperations,
+	},
+	error::Error,
+};
+
+async fn peers(node: &TestNode, topic_id: &str) -> Vec<String> {
+	node.state
+		.get_topic_by_topic_id(topic_id.to_string())
+		.await
+		.unwrap()
+		.get_peers()
+}
+
+async fn join(node: &mut TestNode, ticket: &str) {
+	let topic = node.state.join_topic_with_ticket(ticket).await.unwrap();
+	node.wait_online(&topic.topic_id).await;
+}
+
+async fn wait_join_request(owner: &mut TestNode, newcomer: &TestNode) {
+	let newcomer_id = newcomer.node_id();
+	owner
+		.wait_gossip(|event| match event {
+			GossipEvent::JoinRequested { sender, .. } if sender == newcomer_id => Some(()),
+			_ => None,
+		})
+		.await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn owner_approves_removes_and_bans_members() {
+	let net = TestNet::new();
+	let mut alice = net.spawn("alice").await;
+	let ticket = alice.state.start_new_topic("crew".to_string()).await.unwrap();
+	let topic_id = alice.state.list_joined_topics().remove(0);
+
+	let mut bob = net.spawn("bob").await;
+	join(&mut bob, &ticket).await;
+	eventually("alice to admit bob", || async {
+		peers(&alice, &topic_id).await.contains(&bob.node_id())
+	})
+	.await;
+
+	// Only the owner moderates
+	assert!(matches!(
+		bob.state.set_require_approval(topic_id.clone(), true).await,
+		Err(Error::Forbidden(_))
+	));
+	alice.state.set_require_approval(topic_id.clone(), true).await.unwrap();
+
+	// Newcomers with a valid ticket wait for the owner
+	let mut carol = net.spawn("carol").await;
+	join(&mut carol, &ticket).await;
+	wait_join_request(&mut alice, &carol).await;
+	let mut dave = net.spawn("dave").await;
+	join(&mut dave, &ticket).await;
+	wait_join_request(&mut alice, &dave).await;
+	assert_eq!(alice.state.list_join_requests(topic_id.clone()).await.unwrap().len(), 2);
+	assert!(!peers(&bob, &topic_id).await.contains(&carol.node_id()));
+
+	alice
+		.state
+		.approve_join(topic_id.clone(), carol.node_id())
+		.await
+		.unwrap();
+	alice.state.deny_join(topic_id.clone(), dave.node_id()).await.unwrap();
+	assert!(alice
+		.state
+		.list_join_requests(topic_id.clone())
+		.await
+		.unwrap()
+		.is_empty());
+	// From here on tickets alone decide, a denied newcomer needs a new one like a removed member
+	alice.state.set_require_approval(topic_id.clone(), false).await.unwrap();
+	eventually("bob to admit carol", || async {
+		peers(&bob, &topic_id).await.contains(&carol.node_id())
+	})
+	.await;
+
+	// Banned nodes are dropped everywhere and their messages ignored
+	alice.state.ban_member(topic_id.clone(), carol.node_id()).await.unwrap();
+	eventually("bob to drop carol", || async {
+		!peers(&bob, &topic_id).await.contains(&carol.node_id())
+	})
+	.await;
+	assert_eq!(
+		bob.state.list_banned_members(topic_id.clone()).await.unwrap(),
+		vec![carol.node_id()]
+	);
+	carol
+		.state
+		.send_message(topic_id.clone(), "still here".to_string())
+		.await
+		.unwrap();
+
+	// A removed member can not come back with the ticket it joined with
+	assert!(matches!(
+		alice.state.remove_member(topic_id.clone(), alice.node_id()).await,
+		Err(Error::Forbidden(_))
+	));
+	alice
+		.state
+		.remove_member(topic_id.clone(), bob.node_id())
+		.await
+		.unwrap();
+	assert!(!peers(&alice, &topic_id).await.contains(&bob.node_id()));
+
+	// Give bob, carol and dave time for another check-in
+	tokio::time::sleep(Duration::from_secs(12)).await;
+	let peers = peers(&alice, &topic_id).await;
+	assert!(!peers.contains(&bob.node_id()));
+	assert!(!peers.contains(&carol.node_id()));
+	assert!(!peers.contains(&dave.node_id()));
+	assert!(alice
+		.state
+		.list_join_requests(topic_id.clone())
+		.await
+		.unwrap()
+		.is_empty());
+	let messages = alice.state.list_messages(topic_id.clone(), None, 10).await.unwrap();
+	assert!(messages.iter().all(|message| message.node_id != carol.node_id()));
+
+	for node in [alice, bob, carol, dave] {
+		node.close().await;
+	}
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn queued_newcomers_do_not_use_up_tickets() {
+	let net = TestNet::new();
+	let mut alice = net.spawn("alice").await;
+	alice.state.start_new_topic("crew".to_string()).await.unwrap();
+	let topic_id = alice.state.list_joined_topics().remove(0);
+	alice.state.set_require_approval(topic_id.clone(), true).await.unwrap();
+	let single_use = alice
+		.state
+		.get_ticket_for_topic(topic_id.clone(), None, Some(1))
+		.await
+		.unwrap();
+	let uses = |tickets: Vec<crewcast_lib::database::ticket::IssuedTicket>| {
+		tickets.iter().find(|ticket| ticket.max_uses == Some(1)).unwrap().uses
+	};
+
+	// Both wait with the same single use ticket, the one approved first takes its use
+	let mut bob = net.spawn("bob").await;
+	join(&mut bob, &single_use).await;
+	wait_join_request(&mut alice, &bob).await;
+	let mut carol = net.spawn("carol").await;
+	join(&mut carol, &single_use).await;
+	wait_join_request(&mut alice, &carol).await;
+	assert_eq!(
+		uses(alice.state.list_issued_tickets(Some(topic_id.clone())).await.unwrap()),
+		0
+	);
+
+	alice
+		.state
+		.approve_join(topic_id.clone(), carol.node_id())
+		.await
+		.unwrap();
+	assert!(matches!(
+		alice.state.approve_join(topic_id.clone(), bob.node_id()).await,
+		Err(Error::Ticket(_))
+	));
+	assert_eq!(
+		uses(alice.state.list_issued_tickets(Some(topic_id.clone())).await.unwrap()),
+		1
+	);
+	let peers = peers(&alice, &topic_id).await;
+	assert!(peers.contains(&carol.node_id()));
+	assert!(!peers.contains(&bob.node_id()));
+
+	for node in [alice, bob, carol] {
+		node.close().await;
+	}
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn moderation_older_than_the_last_applied_is_ignored() {
+	let net = TestNet::new();
+	let mut alice = net.spawn("alice").await;
+	alice.state.start_new_topic("crew".to_string()).await.unwrap();
+	let topic_id = alice.state.list_joined_topics().remove(0);
+
+	// Every action the owner takes is numbered after the ones before it
+	for _ in 0..2 {
+		alice.state.set_require_approval(topic_id.clone(), true).await.unwrap();
+	}
+	let db = &alice.state.db;
+	for seq in [1, 2] {
+		assert!(!db.advance_moderation_seq(&topic_id, seq).await.unwrap());
+	}
+	assert!(db.advance_moderation_seq(&topic_id, 3).await.unwrap());
+	assert!(!db.advance_moderation_seq(&topic_id, 3).await.unwrap());
+	assert_eq!(db.next_moderation_seq(&topic_id).await.unwrap(), 4);
+
+	alice.close().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn owner_policy_repairs_missed_removals_and_transfers() {
+	let net = TestNet::new();
+	let mut alice = net.spawn("alice").await;
+	alice.state.start_new_topic("crew".to_string()).await.unwrap();
+	let topic_id = alice.state.list_joined_topics().remove(0);
+	let db = &alice.state.db;
+	let (owner, bob, carol) = (alice.node_id(), "b".repeat(64), "c".repeat(64));
+	for member in [&bob, &carol] {
+		db.add_member(&topic_id, member).await.unwrap();
+	}
+
+	// Bob was removed while this node was away, the owner's policy still names him
+	let policy = Policy {
+		owner: owner.clone(),
+		removed: [(bob.clone(), vec!["nonce".to_string()])].into(),
+		..Default::default()
+	};
+	apply_policy(db, &topic_id, &owner, policy).await.unwrap();
+	assert!(!db.is_member(&topic_id, &bob).await.unwrap());
+	assert!(db.is_ticket_spent(&topic_id, &bob, "nonce").await.unwrap());
+	assert_eq!(db.list_removed(&topic_id, 10).await.unwrap().len(), 1);
+
+	// A former owner only hands the topic over, the rest of its policy is not applied
+	let policy = Policy {
+		owner: carol.clone(),
+		require_approval: true,
+		..Default::default()
+	};
+	apply_policy(db, &topic_id, &owner, policy).await.unwrap();
+	assert_eq!(db.get_role(&topic_id, &carol).await.unwrap(), Role::Owner);
+	assert_eq!(db.get_role(&topic_id, &owner).await.unwrap(), Role::Admin);
+	assert!(!db.requires_approval(&topic_id).await.unwrap());
+
+	alice.close().await;
+}
@@ -0,0 +1,215 @@
+mod common;
+
+use common::{eventually, TestNet, TestNode};
+use crewcast_lib::{
+	comm::events::GossipEvent,
+	database::{
+		member::MemberOperations,
+		message::{Message, MessageOperations},
+		node::NodeOperations,
+		user::UserOperations,
+	},
+};
+
+// Creates a topic on `owner` and joins it from `member`, returns the topic id
+async fn create_and_join(owner: &mut TestNode, member: &mut TestNode) -> String {
+	let ticket = owner.state.start_new_topic("crew".to_string()).await.unwrap();
+	let topic = member.state.join_topic_with_ticket(&ticket).await.unwrap();
+
+	owner.wait_online(&topic.topic_id).await;
+	member.wait_online(&topic.topic_id).await;
+	topic.topic_id
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn check_in_propagates_membership() {
+	let net = TestNet::new();
+	let mut alice = net.spawn("alice").await;
+	let mut bob = net.spawn("bob").await;
+
+	let topic_id = create_and_join(&mut alice, &mut bob).await;
+
+	// The ticket already names alice, bob is only known to her once he checks in
+	let bob_topic = bob.state.get_topic_by_topic_id(topic_id.clone()).await.unwrap();
+	assert!(bob_topic.get_peers().contains(&alice.node_id()));
+
+	let bob_id = bob.node_id();
+	let meta = alice
+		.wait_gossip(|event| match event {
+			GossipEvent::CheckIn { sender, meta, .. } if sender == bob_id => Some(meta),
+			_ => None,
+		})
+		.await;
+	assert_eq!(meta.first_name, "bob");
+
+	let alice_topic = alice.state.get_topic_by_topic_id(topic_id.clone()).await.unwrap();
+	assert!(alice_topic.get_peers().contains(&bob_id));
+
+	let bob_node = alice.state.db.get_node_by_node_id(bob_id).await.unwrap();
+	let bob_user = alice.state.db.get_user_by_node_id(bob_node.id).await.unwrap();
+	assert_eq!(bob_user.email, "bob@crewcast.test");
+
+	alice.close().await;
+	bob.close().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn shared_file_syncs_and_downloads() {
+	let net = TestNet::new();
+	let mut alice = net.spawn("alice").await;
+	let mut bob = net.spawn("bob").await;
+
+	let topic_id = create_and_join(&mut alice, &mut bob).await;
+
+	let content = b"minutes of the last crew meeting".repeat(1024);
+	let path = alice.dir().join("minutes.txt");
+	// A file that can not be read is an error, not a crash
+	assert!(alice
+		.state
+		.share_file(topic_id.clone(), path.to_string_lossy().to_string())
+		.await
+		.is_err());
+	std::fs::write(&path, &content).unwrap();
+	alice
+		.state
+		.share_file(topic_id.clone(), path.to_string_lossy().to_string())
+		.await
+		.unwrap();
+
+	// Members online while the file is shared get it straight away
+	let shared = bob
+		.wait_gossip(|event| match event {
+			GossipEvent::File { file, .. } => Some(file),
+			_ => None,
+		})
+		.await;
+	assert_eq!(shared.name, "minutes.txt");
+	assert_eq!(shared.size, content.len() as i64);
+
+	// Late joiners catch up through the FileBatch sent in reply to their check-in
+	eventually("bob to check in with alice", || async {
+		let topic = alice.state.get_topic_by_topic_id(topic_id.clone()).await.unwrap();
+		topic.get_peers().contains(&bob.node_id())
+	})
+	.await;
+	let ticket = alice
+		.state
+		.get_ticket_for_topic(topic_id.clone(), None, None)
+		.await
+		.unwrap();
+	let mut carol = net.spawn("carol").await;
+	carol.state.join_topic_with_ticket(&ticket).await.unwrap();
+	carol.wait_online(&topic_id).await;
+
+	let alice_id = alice.node_id();
+	let files = carol
+		.wait_gossip(|event| match event {
+			GossipEvent::FileBatch { files, sender, .. } if sender == alice_id => Some(files),
+			_ => None,
+		})
+		.await;
+	assert_eq!(files.len(), 1);
+	assert_eq!(files[0].hash, shared.hash);
+
+	// Downloads are exported to the download directory, without overwriting an earlier download
+	let downloads = carol.dir().join("downloads");
+	carol
+		.state
+		.set_download_dir(downloads.to_string_lossy().to_string())
+		.await
+		.unwrap();
+	let download = carol.state.download_file(files[0].clone(), None).await.unwrap();
+	let saved = carol.wait_download(download.file_id).await.unwrap();
+	assert_eq!(saved, downloads.join(&files[0].name));
+	assert_eq!(std::fs::read(&saved).unwrap(), content);
+	carol.state.download_file(files[0].clone(), None).await.unwrap();
+	let again = carol.wait_download(download.file_id).await.unwrap();
+	assert_ne!(again, saved);
+	assert_eq!(std::fs::read(&again).unwrap(), content);
+	let file = carol.state.get_file_path(files[0].id).await.unwrap();
+	assert_eq!(file, again);
+
+	alice.close().await;
+	bob.close().await;
+	carol.close().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn chat_history_is_relayed_while_its_author_is_away() {
+	let net = TestNet::new();
+	let mut alice = net.spawn("alice").await;
+	let mut bob = net.spawn("bob").await;
+	let topic_id = create_and_join(&mut alice, &mut bob).await;
+	let ticket = alice
+		.state
+		.get_ticket_for_topic(topic_id.clone(), None, None)
+		.await
+		.unwrap();
+	let mut carol = net.spawn("carol").await;
+	carol.state.join_topic_with_ticket(&ticket).await.unwrap();
+	carol.wait_online(&topic_id).await;
+	eventually("bob to admit carol", || async {
+		bob.state.db.is_member(&topic_id, &carol.node_id()).await.unwrap()
+	})
+	.await;
+
+	// Bob missed one of the two messages alice sent in the same second, then alice went away
+	let alice_id = alice.node_id();
+	let sent_at = chrono::Utc::now().timestamp() - 100;
+	let message = |content: &str| Message {
+		id: 0,
+		node_id: alice_id.clone(),
+		topic_id: topic_id.clone(),
+		content: content.to_string(),
+		sent_at,
+	};
+	carol.state.db.sync_message(message("one")).await.unwrap();
+	carol.state.db.sync_message(message("two")).await.unwrap();
+	bob.state.db.sync_message(message("one")).await.unwrap();
+	alice.close().await;
+
+	let messages = bob
+		.wait_gossip(|event| match event {
+			GossipEvent::ChatBatch { messages, sender, .. } if sender == alice_id => Some(messages),
+			_ => None,
+		})
+		.await;
+	assert_eq!(
+		messages
+			.iter()
+			.map(|message| message.content.as_str())
+			.collect::<Vec<_>>(),
+		["two"]
+	);
+
+	bob.close().await;
+	carol.close().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn peers_sharing_an_email_are_both_admitted() {
+	let net = TestNet::new();
+	let mut alice = net.spawn("alice").await;
+	let mut bob = net.spawn("bob").await;
+	let topic_id = create_and_join(&mut alice, &mut bob).await;
+	let ticket = alice
+		.state
+		.get_ticket_for_topic(topic_id.clone(), None, None)
+		.await
+		.unwrap();
+
+	// Same profile on a second device, only the first one seen keeps it
+	let mut other_bob = net.spawn("bob").await;
+	other_bob.state.join_topic_with_ticket(&ticket).await.unwrap();
+	other_bob.wait_online(&topic_id).await;
+	for node_id in [bob.node_id(), other_bob.node_id()] {
+		eventually("alice to admit both of bob's nodes", || async {
+			alice.state.db.is_member(&topic_id, &node_id).await.unwrap()
+		})
+		.await;
+	}
+
+	alice.close().await;
+	bob.close().await;
+	other_bob.close().await;
+}
@@ -0,0 +1,38 @@
+mod common;
+
+use common::TestNet;
+use crewcast_lib::comm::endpoint::NetworkConfig;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn switching_network_mode_keeps_node_and_topics() {
+	let net = TestNet::new();
+	let mut alice = net.spawn("alice").await;
+	let node_id = alice.node_id();
+
+	alice.state.start_new_topic("crew".to_string()).await.unwrap();
+	let joined = alice.state.list_joined_topics();
+	assert_eq!(joined.len(), 1);
+	assert_eq!(alice.state.get_network_config().await.unwrap(), NetworkConfig::Default);
+
+	// An invalid configuration is rejected before the running endpoint is touched
+	let invalid = NetworkConfig::CustomRelays { relay_urls: vec![] };
+	assert!(alice.state.set_network_config(invalid).await.is_err());
+	assert_eq!(alice.state.get_network_config().await.unwrap(), NetworkConfig::Default);
+
+	alice.state.set_network_config(NetworkConfig::LanOnly).await.unwrap();
+	assert_eq!(alice.state.get_network_config().await.unwrap(), NetworkConfig::LanOnly);
+	assert_eq!(alice.node_id(), node_id);
+	assert_eq!(alice.state.list_joined_topics(), joined);
+
+	// The blob store is usable again after the restart
+	let path = alice.dir().join("notes.txt");
+	std::fs::write(&path, b"still sharing").unwrap();
+	alice
+		.state
+		.share_file(joined[0].clone(), path.to_string_lossy().to_string())
+		.await
+		.unwrap();
+	assert_eq!(alice.state.list_files(joined[0].clone()).await.unwrap().len(), 1);
+
+	alice.close().await;
+}
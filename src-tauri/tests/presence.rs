@@ -0,0 +1,122 @@
+mod common;
+
+use common::{eventually, TestNet};
+use crewcast_lib::{
+	comm::events::{Event, GossipEvent, Presence},
+	error::Error,
+};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn members_go_offline_after_missed_check_ins() {
+	let net = TestNet::new();
+	let mut alice = net.spawn("alice").await;
+	let ticket = alice.state.start_new_topic("crew".to_string()).await.unwrap();
+	let topic_id = alice.state.list_joined_topics().remove(0);
+
+	let mut bob = net.spawn("bob").await;
+	let topic = bob.state.join_topic_with_ticket(&ticket).await.unwrap();
+	bob.wait_online(&topic.topic_id).await;
+	let bob_id = bob.node_id();
+
+	alice
+		.wait_for(|event| match event {
+			Event::Presence(Presence::Online {
+				topic_id: online,
+				node_id,
+				..
+			}) if online == topic_id && node_id == bob_id => Some(()),
+			_ => None,
+		})
+		.await;
+	let members = alice.state.list_members(topic_id.clone()).await.unwrap();
+	assert_eq!(members.len(), 2);
+	assert!(members.iter().all(|member| member.is_active));
+	let bob_member = members.iter().find(|member| member.node_id == bob_id).unwrap();
+	assert_eq!(bob_member.first_name.as_deref(), Some("bob"));
+	assert!(bob_member.last_seen.is_some());
+
+	bob.close().await;
+	let last_seen = alice
+		.wait_for(|event| match event {
+			Event::Presence(Presence::Offline {
+				topic_id: offline,
+				node_id,
+				last_seen,
+			}) if offline == topic_id && node_id == bob_id => Some(last_seen),
+			_ => None,
+		})
+		.await;
+	let members = alice.state.list_members(topic_id.clone()).await.unwrap();
+	let bob_member = members.iter().find(|member| member.node_id == bob_id).unwrap();
+	assert!(!bob_member.is_active);
+	assert_eq!(bob_member.last_seen, Some(last_seen));
+
+	alice.close().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn leaving_is_announced() {
+	let net = TestNet::new();
+	let mut alice = net.spawn("alice").await;
+	let ticket = alice.state.start_new_topic("crew".to_string()).await.unwrap();
+	let topic_id = alice.state.list_joined_topics().remove(0);
+
+	let mut bob = net.spawn("bob").await;
+	let mut carol = net.spawn("carol").await;
+	for node in [&mut bob, &mut carol] {
+		let topic = node.state.join_topic_with_ticket(&ticket).await.unwrap();
+		node.wait_online(&topic.topic_id).await;
+	}
+	let path = carol.dir().join("notes.txt");
+	std::fs::write(&path, "notes").unwrap();
+	carol
+		.state
+		.share_file(topic_id.clone(), path.to_string_lossy().to_string())
+		.await
+		.unwrap();
+	eventually("alice to see bob and carol online with carol's file", || async {
+		let members = alice.state.list_members(topic_id.clone()).await.unwrap();
+		members.len() == 3
+			&& members.iter().all(|member| member.is_active)
+			&& alice.state.list_files(topic_id.clone()).await.unwrap().len() == 1
+	})
+	.await;
+
+	// The owner has to hand the topic over before leaving for good
+	assert!(matches!(
+		alice.state.leave_topic(topic_id.clone(), true).await,
+		Err(Error::Forbidden(_))
+	));
+
+	// A temporary leave only takes bob offline
+	let bob_id = bob.node_id();
+	bob.state.leave_topic(topic_id.clone(), false).await.unwrap();
+	let permanent = alice
+		.wait_gossip(|event| match event {
+			GossipEvent::MemberLeft { node_id, permanent, .. } if node_id == bob_id => Some(permanent),
+			_ => None,
+		})
+		.await;
+	assert!(!permanent);
+	let members = alice.state.list_members(topic_id.clone()).await.unwrap();
+	let bob_member = members.iter().find(|member| member.node_id == bob_id).unwrap();
+	assert!(!bob_member.is_active);
+
+	// A permanent leave takes carol and the files only she serves out of the topic
+	let carol_id = carol.node_id();
+	carol.state.leave_topic(topic_id.clone(), true).await.unwrap();
+	let permanent = alice
+		.wait_gossip(|event| match event {
+			GossipEvent::MemberLeft { node_id, permanent, .. } if node_id == carol_id => Some(permanent),
+			_ => None,
+		})
+		.await;
+	assert!(permanent);
+	let topic = alice.state.get_topic_by_topic_id(topic_id.clone()).await.unwrap();
+	assert_eq!(topic.members, [bob_id]);
+	assert!(alice.state.list_files(topic_id.clone()).await.unwrap().is_empty());
+
+	for node in [alice, bob, carol] {
+		node.close().await;
+	}
+}
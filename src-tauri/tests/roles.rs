@@ -0,0 +1,134 @@
+mod common;
+
+use common::{eventually, TestNet, TestNode};
+use crewcast_lib::{database::member::Role, error::Error};
+
+async fn role(node: &TestNode, topic_id: &str, member: &TestNode) -> Option<Role> {
+	node.state
+		.list_member_roles(topic_id.to_string())
+		.await
+		.unwrap()
+		.into_iter()
+		.find(|topic_member| topic_member.node_id == member.node_id())
+		.map(|topic_member| topic_member.role)
+}
+
+async fn join(node: &mut TestNode, ticket: &str) {
+	let topic = node.state.join_topic_with_ticket(ticket).await.unwrap();
+	node.wait_online(&topic.topic_id).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn roles_limit_what_members_can_do() {
+	let net = TestNet::new();
+	let mut alice = net.spawn("alice").await;
+	let ticket = alice.state.start_new_topic("crew".to_string()).await.unwrap();
+	let topic_id = alice.state.list_joined_topics().remove(0);
+
+	let mut bob = net.spawn("bob").await;
+	join(&mut bob, &ticket).await;
+	let mut carol = net.spawn("carol").await;
+	join(&mut carol, &ticket).await;
+	eventually("alice to admit bob and carol", || async {
+		role(&alice, &topic_id, &bob).await == Some(Role::Member)
+			&& role(&alice, &topic_id, &carol).await == Some(Role::Member)
+	})
+	.await;
+
+	// Only admins invite
+	assert!(matches!(
+		bob.state.get_ticket_for_topic(topic_id.clone(), None, None).await,
+		Err(Error::Forbidden(_))
+	));
+	alice
+		.state
+		.set_member_role(topic_id.clone(), bob.node_id(), Role::Admin)
+		.await
+		.unwrap();
+	eventually("bob to become an admin", || async {
+		role(&bob, &topic_id, &bob).await == Some(Role::Admin)
+			&& role(&carol, &topic_id, &bob).await == Some(Role::Admin)
+	})
+	.await;
+	let bobs_ticket = bob
+		.state
+		.get_ticket_for_topic(topic_id.clone(), None, None)
+		.await
+		.unwrap();
+	let mut dave = net.spawn("dave").await;
+	join(&mut dave, &bobs_ticket).await;
+	eventually("alice to admit dave", || async {
+		role(&alice, &topic_id, &dave).await.is_some()
+	})
+	.await;
+
+	// Read-only members can not post, admins can not change roles
+	assert!(matches!(
+		bob.state
+			.set_member_role(topic_id.clone(), carol.node_id(), Role::ReadOnly)
+			.await,
+		Err(Error::Forbidden(_))
+	));
+	alice
+		.state
+		.set_member_role(topic_id.clone(), carol.node_id(), Role::ReadOnly)
+		.await
+		.unwrap();
+	eventually("carol to become read-only", || async {
+		role(&carol, &topic_id, &carol).await == Some(Role::ReadOnly)
+	})
+	.await;
+	assert!(matches!(
+		carol.state.send_message(topic_id.clone(), "hello".to_string()).await,
+		Err(Error::Forbidden(_))
+	));
+	let path = carol.dir().join("notes.txt");
+	std::fs::write(&path, "notes").unwrap();
+	assert!(matches!(
+		carol
+			.state
+			.share_file(topic_id.clone(), path.to_string_lossy().to_string())
+			.await,
+		Err(Error::Forbidden(_))
+	));
+
+	// The previous owner stays on as an admin
+	alice
+		.state
+		.transfer_ownership(topic_id.clone(), bob.node_id())
+		.await
+		.unwrap();
+	eventually("carol to see bob as the owner", || async {
+		role(&carol, &topic_id, &bob).await == Some(Role::Owner)
+			&& role(&carol, &topic_id, &alice).await == Some(Role::Admin)
+	})
+	.await;
+	assert!(matches!(
+		alice
+			.state
+			.set_member_role(topic_id.clone(), carol.node_id(), Role::Member)
+			.await,
+		Err(Error::Forbidden(_))
+	));
+	eventually("bob to own the topic", || async {
+		role(&bob, &topic_id, &bob).await == Some(Role::Owner)
+	})
+	.await;
+	bob.state
+		.set_member_role(topic_id.clone(), carol.node_id(), Role::Member)
+		.await
+		.unwrap();
+	eventually("carol to become a member again", || async {
+		role(&carol, &topic_id, &carol).await == Some(Role::Member)
+	})
+	.await;
+	carol
+		.state
+		.send_message(topic_id.clone(), "hello".to_string())
+		.await
+		.unwrap();
+
+	for node in [alice, bob, carol, dave] {
+		node.close().await;
+	}
+}
@@ -0,0 +1,111 @@
+mod common;
+
+use common::{TestNet, TestNode};
+use crewcast_lib::{
+	comm::events::GossipEvent,
+	database::{ticket::TicketOperations, Db},
+};
+
+// Joins with `ticket` and waits until `node` is connected to the topic, admitted or not
+async fn join(node: &mut TestNode, ticket: &str) -> String {
+	let topic = node.state.join_topic_with_ticket(ticket).await.unwrap();
+	node.wait_online(&topic.topic_id).await;
+	topic.topic_id
+}
+
+async fn wait_admitted(owner: &mut TestNode, newcomer: &TestNode) {
+	let newcomer_id = newcomer.node_id();
+	owner
+		.wait_gossip(|event| match event {
+			GossipEvent::CheckIn { sender, .. } if sender == newcomer_id => Some(()),
+			_ => None,
+		})
+		.await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ticket_terms_are_checked_on_first_check_in() {
+	let net = TestNet::new();
+	let mut alice = net.spawn("alice").await;
+	alice.state.start_new_topic("crew".to_string()).await.unwrap();
+	let topic_id = alice.state.list_joined_topics().remove(0);
+
+	let single_use = alice
+		.state
+		.get_ticket_for_topic(topic_id.clone(), None, Some(1))
+		.await
+		.unwrap();
+	let expiring = alice
+		.state
+		.get_ticket_for_topic(topic_id.clone(), Some(1), None)
+		.await
+		.unwrap();
+	let revoked = alice
+		.state
+		.get_ticket_for_topic(topic_id.clone(), None, None)
+		.await
+		.unwrap();
+	let tickets = alice.state.list_issued_tickets(Some(topic_id.clone())).await.unwrap();
+	// Newest first, the one from creating the topic is last
+	assert_eq!(tickets.len(), 4);
+	alice.state.revoke_ticket(tickets[0].nonce.clone()).await.unwrap();
+
+	let mut bob = net.spawn("bob").await;
+	join(&mut bob, &single_use).await;
+	wait_admitted(&mut alice, &bob).await;
+
+	// Turned away: the single use is taken, the other ticket expired and the last one is revoked
+	let mut carol = net.spawn("carol").await;
+	join(&mut carol, &single_use).await;
+	tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+	let mut dave = net.spawn("dave").await;
+	join(&mut dave, &expiring).await;
+	let mut erin = net.spawn("erin").await;
+	join(&mut erin, &revoked).await;
+
+	// Check-ins are handled in order, once a later newcomer is in the others have been decided on
+	let open = alice
+		.state
+		.get_ticket_for_topic(topic_id.clone(), Some(3600), Some(5))
+		.await
+		.unwrap();
+	let mut frank = net.spawn("frank").await;
+	join(&mut frank, &open).await;
+	wait_admitted(&mut alice, &frank).await;
+
+	let peers = alice
+		.state
+		.get_topic_by_topic_id(topic_id.clone())
+		.await
+		.unwrap()
+		.get_peers();
+	assert!(peers.contains(&bob.node_id()));
+	assert!(peers.contains(&frank.node_id()));
+	assert!(!peers.contains(&carol.node_id()));
+	assert!(!peers.contains(&dave.node_id()));
+	assert!(!peers.contains(&erin.node_id()));
+
+	let tickets = alice.state.list_issued_tickets(Some(topic_id)).await.unwrap();
+	let single_use = tickets.iter().find(|ticket| ticket.max_uses == Some(1)).unwrap();
+	assert_eq!(single_use.uses, 1);
+	assert_eq!(tickets.iter().filter(|ticket| ticket.revoked).count(), 1);
+
+	for node in [alice, bob, carol, dave, erin, frank] {
+		node.close().await;
+	}
+}
+
+#[tokio::test]
+async fn redemptions_are_kept_per_topic() {
+	let dir = tempfile::tempdir().unwrap();
+	let db = Db::init(&dir.path().join("data.db"), "password").await.unwrap();
+
+	assert!(db.redeem_ticket("crew", "crew-ticket", "bob", None).await.unwrap());
+	assert!(db.redeem_ticket("band", "band-ticket", "bob", Some(1)).await.unwrap());
+	assert_eq!(
+		db.list_redeemed_nonces("crew", "bob").await.unwrap(),
+		vec!["crew-ticket".to_string()]
+	);
+	assert!(db.list_redeemed_nonces("crew", "carol").await.unwrap().is_empty());
+	db.close().await.unwrap();
+}
@@ -0,0 +1,74 @@
+use std::path::Path;
+
+use crewcast_lib::database::{
+	member::{MemberOperations, Role},
+	topic::TopicOperations,
+	Db,
+};
+use sqlx::{migrate::Migrator, sqlite::SqliteConnectOptions, ConnectOptions, Connection};
+
+// The migration that moved topics.members into the topic_members table
+const TOPIC_MEMBERS_TABLE: &str = "20250904071836";
+
+#[tokio::test]
+async fn comma_joined_members_are_converted() {
+	let dir = tempfile::tempdir().unwrap();
+	let db_path = dir.path().join("data.db");
+
+	// A database migrated up to the comma-joined members column
+	let migrations = dir.path().join("migrations");
+	std::fs::create_dir(&migrations).unwrap();
+	for entry in std::fs::read_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join("migrations")).unwrap() {
+		let path = entry.unwrap().path();
+		if path.file_name().unwrap().to_string_lossy().as_ref() < TOPIC_MEMBERS_TABLE {
+			std::fs::copy(&path, migrations.join(path.file_name().unwrap())).unwrap();
+		}
+	}
+	let mut conn = SqliteConnectOptions::new()
+		.filename(&db_path)
+		.create_if_missing(true)
+		.connect()
+		.await
+		.unwrap();
+	Migrator::new(migrations.as_path())
+		.await
+		.unwrap()
+		.run(&mut conn)
+		.await
+		.unwrap();
+	for statement in [
+		"INSERT INTO topics (topic_id, name, owner, members) VALUES ('crew', 'crew', 'alice', 'bob,alice,carol')",
+		"INSERT INTO topics (topic_id, name, owner, members) VALUES ('solo', 'solo', 'alice', NULL)",
+		"INSERT INTO topic_members (topic_id, node_id, role) VALUES ('crew', 'carol', 'admin')",
+		"INSERT INTO topic_members (topic_id, node_id, role) VALUES ('crew', 'dave', 'read_only')",
+	] {
+		sqlx::query(statement).execute(&mut conn).await.unwrap();
+	}
+	conn.close().await.unwrap();
+
+	let db = Db::init(&db_path, "passphrase").await.unwrap();
+	let crew = db.get_topic_by_topic_id("crew".to_string()).await.unwrap();
+	assert_eq!(crew.members, ["bob", "carol", "dave"]);
+	assert_eq!(crew.get_peers(), ["alice", "bob", "carol", "dave"]);
+	let roles: Vec<_> = db
+		.list_members("crew")
+		.await
+		.unwrap()
+		.into_iter()
+		.map(|member| (member.node_id, member.role))
+		.collect();
+	assert_eq!(
+		roles,
+		[
+			("alice".to_string(), Role::Owner),
+			("bob".to_string(), Role::Member),
+			("carol".to_string(), Role::Admin),
+			("dave".to_string(), Role::ReadOnly),
+		]
+	);
+
+	let solo = db.get_topic_by_topic_id("solo".to_string()).await.unwrap();
+	assert!(solo.members.is_empty());
+	assert_eq!(solo.get_peers(), ["alice"]);
+	db.close().await.unwrap();
+}
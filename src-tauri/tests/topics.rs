@@ -0,0 +1,138 @@
+mod common;
+
+use common::{eventually, TestNet};
+use crewcast_lib::{
+	database::{member::MemberOperations, moderation::ModerationOperations, ticket::TicketOperations},
+	error::Error,
+};
+use iroh_blobs::{store::fs::FsStore, Hash};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn archived_topics_are_kept_and_deleted_topics_are_not() {
+	let net = TestNet::new();
+	let mut alice = net.spawn("alice").await;
+	alice.state.start_new_topic("crew".to_string()).await.unwrap();
+	let topic_id = alice.state.list_joined_topics().remove(0);
+	let path = alice.dir().join("notes.txt");
+	std::fs::write(&path, "notes").unwrap();
+	alice
+		.state
+		.share_file(topic_id.clone(), path.to_string_lossy().to_string())
+		.await
+		.unwrap();
+	let hash: Hash = alice.state.list_files(topic_id.clone()).await.unwrap()[0]
+		.hash
+		.parse()
+		.unwrap();
+	alice
+		.state
+		.send_message(topic_id.clone(), "hello".to_string())
+		.await
+		.unwrap();
+
+	// Archiving leaves the topic and only hides it
+	let topic = alice.state.archive_topic(topic_id.clone()).await.unwrap();
+	assert!(topic.archived && !topic.auto_join);
+	assert!(alice.state.list_joined_topics().is_empty());
+	assert!(alice.state.list_topics().await.unwrap().is_empty());
+	let id = topic.id;
+	assert_eq!(alice.state.list_archived_topics().await.unwrap(), [topic]);
+	assert_eq!(alice.state.list_files(topic_id.clone()).await.unwrap().len(), 1);
+	assert_eq!(
+		alice
+			.state
+			.list_messages(topic_id.clone(), None, 10)
+			.await
+			.unwrap()
+			.len(),
+		1
+	);
+
+	// Joining it again brings it back
+	let topic = alice.state.join_topic_with_id(id).await.unwrap();
+	assert!(!topic.archived);
+	assert_eq!(alice.state.list_topics().await.unwrap(), [topic]);
+
+	alice.state.delete_topic(topic_id.clone()).await.unwrap();
+	assert!(alice.state.list_joined_topics().is_empty());
+	assert!(alice.state.list_topics().await.unwrap().is_empty());
+	assert!(alice.state.get_topic_by_topic_id(topic_id.clone()).await.is_err());
+	assert!(alice.state.list_files(topic_id.clone()).await.unwrap().is_empty());
+	assert!(alice
+		.state
+		.list_messages(topic_id.clone(), None, 10)
+		.await
+		.unwrap()
+		.is_empty());
+	assert!(alice.state.list_members(topic_id).await.unwrap().is_empty());
+
+	// The name is free again
+	alice.state.start_new_topic("crew".to_string()).await.unwrap();
+	let topic_id = alice.state.list_joined_topics().remove(0);
+	let path = alice.dir().join("plans.txt");
+	std::fs::write(&path, "plans").unwrap();
+	alice
+		.state
+		.share_file(topic_id.clone(), path.to_string_lossy().to_string())
+		.await
+		.unwrap();
+	let kept: Hash = alice.state.list_files(topic_id).await.unwrap()[0].hash.parse().unwrap();
+
+	// The deleted topic's blob leaves the store with the next start, the one still shared stays
+	let mut alice = net.restart(alice).await;
+	alice.state.close().await.unwrap();
+	let store = FsStore::load(alice.dir()).await.unwrap();
+	assert!(!store.has(hash).await.unwrap());
+	assert!(store.has(kept).await.unwrap());
+	store.shutdown().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn leaving_for_good_takes_a_new_ticket_to_come_back() {
+	let net = TestNet::new();
+	let mut alice = net.spawn("alice").await;
+	let ticket = alice.state.start_new_topic("crew".to_string()).await.unwrap();
+	let topic_id = alice.state.list_joined_topics().remove(0);
+	let mut bob = net.spawn("bob").await;
+	let topic = bob.state.join_topic_with_ticket(&ticket).await.unwrap();
+	bob.wait_online(&topic_id).await;
+	let bob_id = bob.node_id();
+	eventually("alice to admit bob", || async {
+		alice.state.db.is_member(&topic_id, &bob_id).await.unwrap()
+	})
+	.await;
+
+	// The members spend the ticket bob joined with
+	let nonce = bob.state.db.get_join_grant(&topic_id).await.unwrap().unwrap().nonce;
+	bob.state.leave_topic(topic_id.clone(), true).await.unwrap();
+	eventually("alice to spend bob's ticket", || async {
+		alice
+			.state
+			.db
+			.is_ticket_spent(&topic_id, &bob_id, &nonce)
+			.await
+			.unwrap()
+	})
+	.await;
+	assert!(matches!(
+		bob.state.join_topic_with_id(topic.id).await,
+		Err(Error::Forbidden(_))
+	));
+
+	// A new ticket brings bob back into the topic he already has
+	let ticket = alice
+		.state
+		.get_ticket_for_topic(topic_id.clone(), None, None)
+		.await
+		.unwrap();
+	let rejoined = bob.state.join_topic_with_ticket(&ticket).await.unwrap();
+	assert_eq!(rejoined.id, topic.id);
+	bob.wait_online(&topic_id).await;
+	eventually("alice to admit bob again", || async {
+		alice.state.db.is_member(&topic_id, &bob_id).await.unwrap()
+	})
+	.await;
+
+	alice.close().await;
+	bob.close().await;
+}
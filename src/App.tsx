@@ -1,3 +1,4 @@
+import { useState } from 'react';
 import { ToastContainer } from 'react-toastify';
 import 'react-toastify/dist/ReactToastify.css';
 import './App.css';
@@ -10,6 +11,7 @@ import TopicsListPage from './components/TopicsListPage';
 import TopicDetailsPage from './components/TopicDetailsPage';
 import AppLoading from './components/AppLoading';
 import WindowControls from './components/WindowControls';
+import UnlockScreen from './components/UnlockScreen';
 
 const AppContent = () => {
   const { isLoading, isRegistered } = useUser();
@@ -44,21 +46,28 @@ const AppContent = () => {
 };
 
 function App() {
+  // The backend only serves data once the database is unlocked
+  const [isUnlocked, setIsUnlocked] = useState(false);
+
   return (
     <ThemeProvider>
-      <UserProvider>
-        <TopicProvider>
-          <div className="app-frame">
-            <div className="app-titlebar">
-              <div className="app-title">CrewCast</div>
-              <WindowControls />
-            </div>
-            <div className="app-container">
-              <AppContent />
-            </div>
-          </div>
-        </TopicProvider>
-      </UserProvider>
+      <div className="app-frame">
+        <div className="app-titlebar">
+          <div className="app-title">CrewCast</div>
+          <WindowControls />
+        </div>
+        <div className="app-container">
+          {isUnlocked ? (
+            <UserProvider>
+              <TopicProvider>
+                <AppContent />
+              </TopicProvider>
+            </UserProvider>
+          ) : (
+            <UnlockScreen onUnlocked={() => setIsUnlocked(true)} />
+          )}
+        </div>
+      </div>
     </ThemeProvider>
   );
 }
@@ -2,7 +2,7 @@ import React, { useState, useEffect, useRef } from 'react';
 import { invoke } from '@tauri-apps/api/core';
 import { listen } from '@tauri-apps/api/event';
 import { toast } from 'react-toastify';
-import { Message } from '../types/interfaces';
+import { Message, StoredMessage } from '../types/interfaces';
 import { useUser } from '../contexts/UserContext';
 
 interface ChatPanelProps {
@@ -41,6 +41,32 @@ const ChatPanel: React.FC<ChatPanelProps> = ({ topicId }) => {
         }
     };
 
+    useEffect(() => {
+        if (!topicId) return;
+
+        // Load stored chat history, returned newest first
+        const loadHistory = async () => {
+            try {
+                const history = await invoke<StoredMessage[]>('list_messages', { topicId });
+                const loaded = await Promise.all(
+                    history.reverse().map(async (msg) => {
+                        const user = await fetchUserByNodeId(msg.nodeId);
+                        return {
+                            content: msg.content,
+                            sender: msg.nodeId,
+                            firstName: user.firstName,
+                            timestamp: msg.sentAt * 1000
+                        };
+                    })
+                );
+                setMessages(loaded);
+            } catch (error) {
+                console.error('Failed to load chat history:', error);
+            }
+        };
+        loadHistory();
+    }, [topicId]);
+
     useEffect(() => {
         if (!topicId) return;
 
@@ -51,6 +77,9 @@ const ChatPanel: React.FC<ChatPanelProps> = ({ topicId }) => {
             try {
                 const parsedMessage = JSON.parse(message);
 
+                // Events of every joined topic arrive here, only handle this one
+                if (parsedMessage.topicId !== topicId) return;
+
                 if (parsedMessage.type === 'chat') {
                     const sender = parsedMessage.sender;
                     const user = await fetchUserByNodeId(sender);
@@ -64,6 +93,16 @@ const ChatPanel: React.FC<ChatPanelProps> = ({ topicId }) => {
                             timestamp: Date.now()
                         }
                     ]);
+                } else if (parsedMessage.type === 'chat_batch') {
+                    // History a peer replayed for us, merge it in time order
+                    const user = await fetchUserByNodeId(parsedMessage.sender);
+                    const synced: Message[] = (parsedMessage.messages as StoredMessage[]).map(msg => ({
+                        content: msg.content,
+                        sender: msg.nodeId,
+                        firstName: user.firstName,
+                        timestamp: msg.sentAt * 1000
+                    }));
+                    setMessages(prev => [...prev, ...synced].sort((a, b) => a.timestamp - b.timestamp));
                 }
             } catch (error) {
                 console.error('Failed to process chat message:', error);
@@ -83,7 +122,7 @@ const ChatPanel: React.FC<ChatPanelProps> = ({ topicId }) => {
         }
 
         try {
-            await invoke('send_message', { message: messageInput });
+            await invoke('send_message', { topicId, message: messageInput });
             setMessageInput('');
         } catch (error) {
             console.error('Failed to send message:', error);
@@ -63,6 +63,9 @@ const FilesPanel: React.FC<FilesPanelProps> = ({ topicId }) => {
             try {
                 const parsedMessage = JSON.parse(message);
 
+                // Events of every joined topic arrive here, only handle this one
+                if (parsedMessage.topicId !== topicId) return;
+
                 if (parsedMessage.type === 'file' && parsedMessage.file) {
                     // Reload files when new file is shared
                     loadFiles();
@@ -78,8 +81,7 @@ const FilesPanel: React.FC<FilesPanelProps> = ({ topicId }) => {
             const progress = event.payload as string;
             try {
                 const parsedMessage = JSON.parse(progress);
-                // Find file by name and update progress
-                const fileId = files.find(f => f.name === parsedMessage.fileName)?.id;
+                const fileId: number | undefined = parsedMessage.fileId;
                 if (fileId && typeof parsedMessage.percentage === 'number') {
                     setDownloadProgress(prev => ({
                         ...prev,
@@ -121,7 +123,7 @@ const FilesPanel: React.FC<FilesPanelProps> = ({ topicId }) => {
             });
 
             if (filePath && typeof filePath === 'string') {
-                await invoke('share_file', { filePath });
+                await invoke('share_file', { topicId, filePath });
                 toast.success('File shared successfully');
                 loadFiles(); // Refresh the files list
             }
@@ -12,115 +12,39 @@ const MembersPanel: React.FC<MembersPanelProps> = ({ topicId }) => {
     const [members, setMembers] = useState<Member[]>([]);
     const [loading, setLoading] = useState(true);
 
-    const fetchMembers = async () => {
-        setLoading(true);
+    const refreshMembers = async () => {
         try {
-            // Get topic info which includes members
-            const topic = await invoke<{ members: string[] }>('get_topic_by_topic_id', { topicId });
-
-            if (!topic.members) {
-                setMembers([]);
-                setLoading(false);
-                return;
-            }
-
-            // Fetch user info for each member
-            const memberPromises = topic.members.map(async (nodeId) => {
-                try {
-                    const user = await invoke<any>('get_user_by_node_id', { nodeId });
-                    return {
-                        nodeId,
-                        firstName: user.firstName || 'Unknown',
-                        lastName: user.lastName,
-                        lastSeen: Date.now(),
-                        isActive: true
-                    };
-                } catch (error) {
-                    return {
-                        nodeId,
-                        firstName: 'Unknown User',
-                        lastName: undefined,
-                        lastSeen: 0,
-                        isActive: false
-                    };
-                }
-            });
-
-            const fetchedMembers = await Promise.all(memberPromises);
-            setMembers(fetchedMembers);
+            setMembers(await invoke<Member[]>('list_members', { topicId }));
         } catch (error) {
             console.error('Failed to fetch members:', error);
             toast.error('Could not load topic members');
-        } finally {
-            setLoading(false);
         }
     };
 
+    const fetchMembers = async () => {
+        setLoading(true);
+        await refreshMembers();
+        setLoading(false);
+    };
+
     useEffect(() => {
         if (topicId) {
             fetchMembers();
         }
 
-        // Listen for check-in messages to update member status
-        const unlistenGossipMessage = listen('gossip-message', async (event) => {
-            const message = event.payload as string;
-
-            try {
-                const parsedMessage = JSON.parse(message);
-
-                if (parsedMessage.type === 'check_in') {
-                    const sender = parsedMessage.sender;
-                    const meta = parsedMessage.meta;
-
-                    setMembers(prev => {
-                        const existingMemberIndex = prev.findIndex(m => m.nodeId === sender);
-
-                        if (existingMemberIndex >= 0) {
-                            // Update existing member
-                            const updatedMembers = [...prev];
-                            updatedMembers[existingMemberIndex] = {
-                                ...updatedMembers[existingMemberIndex],
-                                firstName: meta.first_name || meta.firstName || updatedMembers[existingMemberIndex].firstName,
-                                lastName: meta.last_name || meta.lastName,
-                                lastSeen: Date.now(),
-                                isActive: true
-                            };
-                            return updatedMembers;
-                        } else {
-                            // Add new member
-                            const newMember = {
-                                nodeId: sender,
-                                firstName: meta.first_name || meta.firstName || 'Unknown',
-                                lastName: meta.last_name || meta.lastName,
-                                lastSeen: Date.now(),
-                                isActive: true
-                            };
-
-                            // Show a toast notification for new members
-                            toast.info(`${newMember.firstName} joined the topic`);
-
-                            return [...prev, newMember];
-                        }
-                    });
-                }
-            } catch (error) {
-                console.error('Failed to process check-in message:', error);
+        // Presence is tracked by the backend from check-ins, newcomers show up with their first one
+        const onPresence = (event: { payload: string }) => {
+            const presence = JSON.parse(event.payload);
+            if (presence.topicId === topicId) {
+                refreshMembers();
             }
-        });
-
-        // Update activity status every 30 seconds
-        const activityInterval = setInterval(() => {
-            setMembers(prev =>
-                prev.map(member => ({
-                    ...member,
-                    isActive: Date.now() - member.lastSeen < 60000 // Active if seen in the last minute
-                }))
-            );
-        }, 30000);
+        };
+        const unlistenOnline = listen<string>('member-online', onPresence);
+        const unlistenOffline = listen<string>('member-offline', onPresence);
 
         return () => {
-            unlistenGossipMessage.then(fn => fn());
-            clearInterval(activityInterval);
+            unlistenOnline.then(fn => fn());
+            unlistenOffline.then(fn => fn());
         };
     }, [topicId]);
 
@@ -139,11 +63,11 @@ const MembersPanel: React.FC<MembersPanelProps> = ({ topicId }) => {
                     {members.map((member) => (
                         <li key={member.nodeId} className={`member-item ${member.isActive ? 'active' : 'inactive'}`}>
                             <div className="member-avatar">
-                                {member.firstName.charAt(0).toUpperCase()}
+                                {(member.firstName || '?').charAt(0).toUpperCase()}
                             </div>
                             <div className="member-info">
                                 <div className="member-name">
-                                    {member.firstName} {member.lastName || ''}
+                                    {member.firstName || 'Unknown'} {member.lastName || ''}
                                 </div>
                                 <div className="member-status">
                                     {member.isActive ? 'Active' : 'Inactive'}
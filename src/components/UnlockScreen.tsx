@@ -0,0 +1,105 @@
+import React, { useEffect, useState } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { DatabaseStatus } from '../types/interfaces';
+import AppLoading from './AppLoading';
+
+interface UnlockScreenProps {
+    onUnlocked: () => void;
+}
+
+const UnlockScreen: React.FC<UnlockScreenProps> = ({ onUnlocked }) => {
+    const [status, setStatus] = useState<DatabaseStatus | null>(null);
+    const [passphrase, setPassphrase] = useState('');
+    const [confirmation, setConfirmation] = useState('');
+    const [error, setError] = useState<string | null>(null);
+    const [isUnlocking, setIsUnlocking] = useState(false);
+    // Data from before encryption gets its first passphrase, so it is confirmed like a new one
+    const isNew = status === 'new' || status === 'unencrypted';
+
+    useEffect(() => {
+        invoke<DatabaseStatus>('get_database_status')
+            .then((status) => {
+                if (status === 'unlocked') {
+                    onUnlocked();
+                } else {
+                    setStatus(status);
+                }
+            })
+            .catch((error) => setError(error.message ?? String(error)));
+    }, []);
+
+    const handleSubmit = async (e: React.FormEvent) => {
+        e.preventDefault();
+        if (isNew && passphrase !== confirmation) {
+            setError('Passphrases do not match');
+            return;
+        }
+
+        setIsUnlocking(true);
+        setError(null);
+        try {
+            await invoke('unlock_database', { passphrase });
+            onUnlocked();
+        } catch (error: any) {
+            setError(error.message ?? String(error));
+        } finally {
+            setIsUnlocking(false);
+        }
+    };
+
+    if (!status) {
+        return error ? <p className="form-error">{error}</p> : <AppLoading />;
+    }
+
+    return (
+        <div className="modal-overlay">
+            <div className="modal-container">
+                <div className="modal-header">
+                    <h2>{isNew ? 'Protect your data' : 'Unlock CrewCast'}</h2>
+                    <p>
+                        {status === 'unencrypted'
+                            ? 'Your data on this device is not encrypted yet, choose a passphrase to encrypt it'
+                            : isNew
+                              ? 'Choose a passphrase to encrypt your messages, files and keys on this device'
+                              : 'Enter your passphrase to open your data'}
+                    </p>
+                </div>
+
+                <form onSubmit={handleSubmit} className="registration-form">
+                    <div className="form-group">
+                        <label htmlFor="passphrase">Passphrase</label>
+                        <input
+                            id="passphrase"
+                            type="password"
+                            value={passphrase}
+                            onChange={(e) => setPassphrase(e.target.value)}
+                            autoFocus
+                            required
+                        />
+                    </div>
+
+                    {isNew && (
+                        <div className="form-group">
+                            <label htmlFor="confirmation">Confirm passphrase</label>
+                            <input
+                                id="confirmation"
+                                type="password"
+                                value={confirmation}
+                                onChange={(e) => setConfirmation(e.target.value)}
+                                required
+                            />
+                        </div>
+                    )}
+
+                    {error && <p className="form-error">{error}</p>}
+
+                    <button type="submit" className="btn btn-primary" disabled={isUnlocking}>
+                        {isNew ? 'Set passphrase' : 'Unlock'}
+                    </button>
+                </form>
+            </div>
+        </div>
+    );
+};
+
+export default UnlockScreen;
@@ -1,5 +1,6 @@
-import React, { createContext, useContext, useState } from 'react';
+import React, { createContext, useContext, useEffect, useState } from 'react';
 import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
 import { toast } from 'react-toastify';
 import { Topic } from '../types/interfaces';
 
@@ -19,13 +20,32 @@ const TopicContext = createContext<TopicContextType | null>(null);
 export const TopicProvider: React.FC<{ children: React.ReactNode }> = ({ children }) => {
     const [currentTopic, setCurrentTopic] = useState<Topic | null>(null);
 
+    // Topics flagged for auto join are subscribed on startup, report the ones that fail
+    useEffect(() => {
+        const unlistenTopicStatus = listen('topic-status', (event) => {
+            try {
+                const status = JSON.parse(event.payload as string);
+                if (status.status === 'failed') {
+                    toast.error(`Could not join topic: ${status.error}`);
+                }
+            } catch (error) {
+                console.error('Failed to process topic status:', error);
+            }
+        });
+
+        return () => {
+            unlistenTopicStatus.then(fn => fn());
+        };
+    }, []);
+
     const joinTopic = (topic: Topic) => {
         setCurrentTopic(topic);
     };
 
     const leaveTopic = async (): Promise<void> => {
+        if (!currentTopic) return;
         try {
-            const msg = await invoke<String>('leave_topic');
+            const msg = await invoke<String>('leave_topic', { topicId: currentTopic.topicId });
             console.log(msg);
             setCurrentTopic(null);
         } catch (error) {
@@ -11,7 +11,9 @@ export interface Topic {
     topicId: string; // For frontend compatibility
     name: string;
     owner: string;
-    members: string[] | null;
+    members: string[];
+    autoJoin: boolean;
+    archived: boolean;
 }
 
 export interface SharedFile {
@@ -20,13 +22,40 @@ export interface SharedFile {
     topicId: string;
     hash: string;
     name: string;
-    format: string;
+    absolutePath?: string;
+    format: string; // 'HashSeq' for a shared directory
     size: number;
-    status: string;
+    status: FileStatus;
     sharedAt: number;
     sender: string;
 }
 
+export interface FileEntry {
+    fileId: number;
+    position: number;
+    path: string;
+    hash: string;
+    size?: number;
+    absolutePath?: string;
+    selected: boolean;
+}
+
+export type FileStatus = 'Shared' | 'Queued' | 'Downloading' | 'Paused' | 'Failed' | 'Downloaded';
+
+export interface Download {
+    fileId: number;
+    topicId: string;
+    nodeId: string;
+    hash: string;
+    format: string;
+    name: string;
+    size: number;
+    status: FileStatus;
+    targetPath: string;
+    error?: string;
+    queuedAt: number;
+}
+
 export interface Message {
     content: string;
     sender: string;
@@ -34,16 +63,72 @@ export interface Message {
     timestamp: number;
 }
 
+export interface StoredMessage {
+    id: number;
+    nodeId: string;
+    topicId: string;
+    content: string;
+    sentAt: number;
+}
+
 export interface Member {
     nodeId: string;
-    firstName: string;
+    firstName?: string;
     lastName?: string;
-    lastSeen: number;
+    role: Role;
+    joinedAt: number;
+    lastSeen?: number;
     isActive: boolean;
 }
 
 export interface Node {
     id: number;
     nodeId: string;
-    secretKey?: string;
-}
\ No newline at end of file
+}
+export interface IssuedTicket {
+    nonce: string;
+    topicId: string;
+    expiresAt?: number;
+    maxUses?: number;
+    uses: number;
+    revoked: boolean;
+    createdAt: number;
+}
+
+export interface JoinRequest {
+    topicId: string;
+    nodeId: string;
+    email: string;
+    firstName: string;
+    lastName?: string;
+    requestedAt: number;
+}
+
+export type Role = 'owner' | 'admin' | 'member' | 'read_only';
+
+export interface TopicMember {
+    nodeId: string;
+    role: Role;
+    joinedAt: number;
+    lastSeen?: number;
+}
+
+export interface Neighbor {
+    nodeId: string;
+    since: number;
+}
+
+export interface Connectivity {
+    topicId: string;
+    neighbors: Neighbor[];
+    partitioned: boolean;
+    lagged: number;
+    lastLaggedAt?: number;
+}
+
+export type NetworkConfig =
+    | { mode: 'default' }
+    | { mode: 'lanOnly' }
+    | { mode: 'customRelays'; relayUrls: string[] };
+
+export type DatabaseStatus = 'new' | 'unencrypted' | 'locked' | 'unlocked';